@@ -0,0 +1,49 @@
+use std::env;
+use std::time::Instant;
+
+use simulations_base::{Energy, MetropolisCriterion, MCProtocol, MoversSet, MoversSetSampler, RandomStreams,
+                       UndoMCProtocol, ValidationMode};
+use disks::hard_disks::{DiskMover, HardDisk};
+use disks::vec2::{Coordinates, square_grid_atoms};
+
+/// Compares the speed of MCProtocol, which moves disks on a copy of the system,
+/// with UndoMCProtocol, which moves them in place and rolls back rejected moves.
+///
+/// Usage: disks_benchmark [n_sweeps] [seed]
+pub fn main() {
+    const R_REP: f64 = 4.0;
+    const E_REP: f64 = 10000.0;
+
+    let n_sweeps: usize = env::args().nth(1).map_or(20, |a| a.parse::<usize>().unwrap());
+    let seed: u64 = env::args().nth(2).map_or(1, |a| a.parse::<u64>().unwrap());
+    let en = HardDisk::new(R_REP, E_REP);
+
+    println!("{:>6} {:>16} {:>12} {:>10} {:>10}", "n", "protocol", "time/sweep", "success", "energy");
+    for n in [10, 20, 40, 80] {
+        let mut system = Coordinates::new(n * n);
+        system.set_box_len(n as f64 * 6.0);
+        square_grid_atoms(&mut system);
+
+        let mut copying: MCProtocol<MetropolisCriterion, Coordinates> =
+            MCProtocol::with_streams(MetropolisCriterion::new(1.0), RandomStreams::new(seed));
+        copying.validation = ValidationMode::Disabled;
+        copying.add_mover(Box::new(DiskMover::new(3.0)));
+        run(n, "MCProtocol", &mut copying, &system, &en, n_sweeps);
+
+        let mut in_place: UndoMCProtocol<MetropolisCriterion, Coordinates> =
+            UndoMCProtocol::with_streams(MetropolisCriterion::new(1.0), RandomStreams::new(seed));
+        in_place.validation = ValidationMode::Disabled;
+        in_place.add_mover(Box::new(DiskMover::new(3.0)));
+        run(n, "UndoMCProtocol", &mut in_place, &system, &en, n_sweeps);
+    }
+}
+
+fn run(n: usize, name: &str, sampler: &mut dyn MoversSetSampler<MetropolisCriterion, Coordinates>,
+       start: &Coordinates, en: &dyn Energy<Coordinates>, n_sweeps: usize) {
+    let mut system = start.clone();
+    let before = Instant::now();
+    sampler.make_sweeps(n_sweeps, &mut system, en).unwrap();
+    let per_sweep = before.elapsed() / n_sweeps as u32;
+    let rate = sampler.get_mover(0).unwrap().acceptance_statistics().success_rate();
+    println!("{:>6} {:>16} {:>12.2?} {:>10.4} {:>10.1}", n * n, name, per_sweep, rate, en.energy(&system));
+}
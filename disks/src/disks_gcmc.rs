@@ -0,0 +1,54 @@
+use std::env;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use simulations_base::{InsertRemoveMover, MetropolisCriterion, MCProtocol, MoversSet, RandomStreams,
+                       Sampler, System, TimeSeries, VolumeSystem};
+use disks::hard_disks::{DiskMover, HardDisk};
+use disks::vec2::Coordinates;
+
+const BOX_LEN: f64 = 96.0;
+const SIGMA: f64 = 4.0;         // disk diameter, i.e. the repulsion distance of HardDisk
+const E_REP: f64 = 10000.0;
+const SWEEP_SIZE: usize = 200;
+const N_BLOCKS: usize = 400;
+
+/// Adsorption isotherm of hard disks in the grand canonical ensemble.
+///
+/// For a series of chemical potentials prints the average number of disks, the packing fraction and
+/// `var(N) / <N>`, which measures density fluctuations: it equals 1.0 for an ideal gas and decreases
+/// as the disks get packed more densely.
+///
+/// Usage: disks_gcmc [seed]
+pub fn main() {
+    let mut streams = match env::args().nth(1) {
+        Some(seed) => RandomStreams::new(seed.parse::<u64>().unwrap()),
+        None => RandomStreams::from_entropy()
+    };
+
+    // ---------- an empty box; every chemical potential starts from the final state of the previous one
+    let mut system = Coordinates::new(0);
+    system.set_box_len(BOX_LEN);
+    let energy = Arc::new(HardDisk::new(SIGMA, E_REP));
+
+    println!("{:>6} {:>9} {:>7} {:>8} {:>10} {:>10}", "mu", "<N>", "+/-", "phi", "var/<N>", "acc_rate");
+    for mu in [-6.0, -4.0, -2.0, 0.0, 2.0, 4.0, 6.0] {
+        // --- sweep size is fixed, since the number of disks changes
+        let mut sampler: MCProtocol<MetropolisCriterion, Coordinates> =
+            MCProtocol::with_streams(MetropolisCriterion::new(1.0), streams.split());
+        sampler.sweep_size = Some(SWEEP_SIZE);
+        sampler.add_mover(Box::new(DiskMover::new(1.0)));
+        sampler.add_mover(Box::new(InsertRemoveMover::new(energy.clone(), mu, 1.0)));
+
+        sampler.make_sweeps(N_BLOCKS, &mut system, energy.as_ref()).unwrap();
+        let mut n = TimeSeries::new("N");
+        for _ in 0..N_BLOCKS {
+            sampler.make_sweeps(1, &mut system, energy.as_ref()).unwrap();
+            n.push(system.size() as f64);
+        }
+        let phi = n.mean() * PI * SIGMA * SIGMA / 4.0 / system.volume();
+        let rate = sampler.get_mover(1).unwrap().acceptance_statistics().success_rate();
+        println!("{:>6.1} {:>9.2} {:>7.2} {:>8.4} {:>10.4} {:>10.4}", mu, n.mean(), n.standard_error(), phi,
+                 n.variance() / n.mean(), rate);
+    }
+}
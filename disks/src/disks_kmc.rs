@@ -0,0 +1,66 @@
+use std::env;
+
+use simulations_base::{KineticMC, KineticObservation, KineticObserver, RandomStreams, System};
+use disks::lattice_disks::LatticeDisks;
+
+const SIDE: usize = 64;
+const HOP_RATE: f64 = 1.0;
+const N_OBSERVATIONS: usize = 20;
+
+/// Prints the mean squared displacement of disks, the diffusion coefficient `D(t) = MSD / 4t` and the rate
+/// of hops per disk at every observation
+struct DiffusionObserver {
+    t0: f64,
+    events0: usize,
+}
+
+impl KineticObserver<LatticeDisks> for DiffusionObserver {
+    fn observe(&mut self, observation: &KineticObservation<LatticeDisks>) {
+        let t = observation.time - self.t0;
+        let n = observation.system.size() as f64;
+        let msd = observation.system.msd();
+        println!("{:>10.2} {:>12} {:>10.3} {:>10.4} {:>10.4} {:>10.4}", t, observation.n_events - self.events0, msd,
+                 msd / (4.0 * t), (observation.n_events - self.events0) as f64 / (n * t), observation.total_rate / n);
+    }
+}
+
+/// Diffusion of hard disks on a square lattice, simulated by kinetic Monte Carlo.
+///
+/// Disks hop to free neighboring sites at rate `HOP_RATE`, so a lone disk diffuses with `D = HOP_RATE` (in lattice
+/// units); crowding slows the disks down. After a short relaxation, the mean squared displacement, `D(t)`
+/// and the rate of hops are printed at regular intervals of time. For `sigma = 1` the disks form a lattice gas,
+/// where the average number of free neighbors is known exactly, so the measured rate of hops is compared with
+/// `4 HOP_RATE (1 - (N - 1) / (L^2 - 1))`.
+///
+/// Usage: disks_kmc [density] [sigma] [time] [seed]
+pub fn main() {
+    let density: f64 = env::args().nth(1).map_or(0.5, |a| a.parse::<f64>().unwrap());
+    let sigma: f64 = env::args().nth(2).map_or(1.0, |a| a.parse::<f64>().unwrap());
+    let time: f64 = env::args().nth(3).map_or(100.0, |a| a.parse::<f64>().unwrap());
+    let mut streams = match env::args().nth(4) {
+        Some(seed) => RandomStreams::new(seed.parse::<u64>().unwrap()),
+        None => RandomStreams::from_entropy()
+    };
+
+    let n = (density * (SIDE * SIDE) as f64).round() as usize;
+    let mut system = LatticeDisks::random(SIDE, n, sigma, HOP_RATE, &mut streams.next_stream())
+        .unwrap_or_else(|| panic!("can't place {} disks of diameter {} on a {} x {} lattice", n, sigma, SIDE, SIDE));
+    let mut kmc = KineticMC::new(&system, streams.split()).unwrap();
+
+    // ---------- relax the initial placement, then measure displacements from the relaxed positions
+    kmc.run(&mut system, 10.0).unwrap();
+    system.reset_origins();
+    let (t0, events0) = (kmc.time(), kmc.count_events());
+    kmc.add_observer(Box::new(DiffusionObserver { t0, events0 }), time / N_OBSERVATIONS as f64).unwrap();
+    println!("# {} disks of diameter {} on a {} x {} lattice, density {:.4}", n, sigma, SIDE, SIDE, system.density());
+    println!("{:>10} {:>12} {:>10} {:>10} {:>10} {:>10}", "time", "events", "msd", "D(t)", "hop_rate", "total_rate");
+    kmc.run(&mut system, time).unwrap();
+
+    let hops = (kmc.count_events() - events0) as f64 / (n as f64 * time);
+    println!("# D / HOP_RATE: {:.4}, mean field (1 - density): {:.4}", system.msd() / (4.0 * time * HOP_RATE),
+             1.0 - system.density());
+    if sigma <= 1.0 {
+        let exact = 4.0 * HOP_RATE * (1.0 - (n as f64 - 1.0) / ((SIDE * SIDE) as f64 - 1.0));
+        println!("# hops per disk per unit time: {:.4}, exact: {:.4}", hops, exact);
+    }
+}
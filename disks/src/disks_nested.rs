@@ -0,0 +1,60 @@
+use std::env;
+use std::f64::consts::PI;
+
+use simulations_base::{NestedSampling, RandomStreams, VariableSizeSystem};
+use disks::hard_disks::{DiskMover, HardDisk};
+use disks::vec2::Coordinates;
+
+const N: usize = 16;
+const SIGMA: f64 = 4.0;         // disk diameter, i.e. the repulsion distance of HardDisk
+const E_REP: f64 = 10000.0;
+const N_LIVE: usize = 200;
+
+/// Excess free energy per disk `beta F_ex / N` of hard disks, integrated from Henderson's equation of state
+fn henderson_free_energy(phi: f64) -> f64 { 9.0 / 8.0 * phi / (1.0 - phi) - 7.0 / 8.0 * (1.0 - phi).ln() }
+
+/// Nested sampling of hard disks, starting from disks placed at random.
+///
+/// The energy counts overlapping pairs of disks, so the prior volume of the zero-energy level is the probability
+/// that randomly placed disks don't overlap; its logarithm is the excess free energy of hard disks, compared here
+/// with the value integrated from Henderson's equation of state. Then the average number of overlaps and the heat
+/// capacity of disks that may overlap at the cost of `E_REP` are given for a few temperatures.
+///
+/// Usage: disks_nested [packing_fraction] [seed]
+pub fn main() {
+    let phi: f64 = env::args().nth(1).map_or(0.2, |a| a.parse::<f64>().unwrap());
+    let mut streams = match env::args().nth(2) {
+        Some(seed) => RandomStreams::new(seed.parse::<u64>().unwrap()),
+        None => RandomStreams::from_entropy()
+    };
+
+    // ---------- live systems: disks placed uniformly in the box, overlaps allowed
+    let box_len = (N as f64 * PI * SIGMA * SIGMA / 4.0 / phi).sqrt();
+    let mut rng = streams.next_stream();
+    let live: Vec<Coordinates> = (0..N_LIVE).map(|_| {
+        let mut system = Coordinates::new(0);
+        system.set_box_len(box_len);
+        for _ in 0..N { system.insert_random(&mut rng); }
+        system
+    }).collect();
+    let energy = HardDisk::new(SIGMA, E_REP);
+    let mut sampler = NestedSampling::new(live, &energy, streams.split()).unwrap();
+    sampler.add_mover(Box::new(DiskMover::new(SIGMA))).unwrap();
+    sampler.walk_length = 20 * N;
+
+    // ---------- iterate until no live system has any overlap, then shrink the zero-energy level a bit more
+    while sampler.live_energies().iter().any(|e| *e > 0.0) { sampler.run(N_LIVE, &energy).unwrap(); }
+    sampler.run(5 * N_LIVE, &energy).unwrap();
+
+    let last_overlap = sampler.levels().iter().rposition(|l| l.energy > 0.0).unwrap();
+    let log_p = sampler.levels()[last_overlap].log_volume;
+    println!("# packing fraction: {:.3}, {} iterations", phi, sampler.levels().len());
+    println!("# -ln P(no overlap) / N: {:.4} +/- {:.4}, Henderson: {:.4}", -log_p / N as f64,
+             (-log_p / N_LIVE as f64).sqrt() / N as f64, henderson_free_energy(phi));
+
+    println!("{:>10} {:>12} {:>12} {:>12}", "T/E_rep", "ln_Z", "<overlaps>", "C_v");
+    for t in [0.05, 0.1, 0.2, 0.3, 0.5, 1.0, 2.0] {
+        let thermo = sampler.thermodynamics(t * E_REP);
+        println!("{:>10.3} {:>12.4} {:>12.4} {:>12.4}", t, thermo.log_z, thermo.energy / E_REP, thermo.heat_capacity);
+    }
+}
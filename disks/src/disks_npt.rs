@@ -0,0 +1,88 @@
+use std::env;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use simulations_base::{AdaptiveMCProtocol, Energy, MetropolisCriterion, MCProtocol, MoversSet, RandomStreams,
+                       SimulationDriver, System, TimeSeries, VolumeMover, VolumeSystem};
+use disks::hard_disks::{DiskMover, HardDisk};
+use disks::vec2::{Coordinates, coordinates_to_pdb, square_grid_atoms};
+
+const N: usize = 12;
+const SIGMA: f64 = 4.0;         // disk diameter, i.e. the repulsion distance of HardDisk
+const E_REP: f64 = 10000.0;
+const BLOCKS_PER_PRESSURE: usize = 400;
+
+fn packing_fraction(system: &Coordinates) -> f64 { system.size() as f64 * PI * SIGMA * SIGMA / 4.0 / system.volume() }
+
+/// Creates a driver sampling disk positions and the volume at a given pressure; temperature is 1.0
+fn npt_driver(energy: Arc<HardDisk>, pressure: f64, system: &Coordinates, streams: RandomStreams)
+        -> SimulationDriver<MetropolisCriterion, Coordinates> {
+    let mut sampler: MCProtocol<MetropolisCriterion, Coordinates> =
+        MCProtocol::with_streams(MetropolisCriterion::new(1.0), streams);
+    sampler.add_mover(Box::new(DiskMover::new(1.0)));
+    // --- a few volume moves per sweep; each of them costs about as much as a sweep of disk moves
+    let volume_mover = VolumeMover::new(energy, 0.005 * system.volume(), pressure, 1.0);
+    sampler.add_weighted_mover(Box::new(volume_mover), 4.0 / system.size() as f64).unwrap();
+    let mut adaptive = AdaptiveMCProtocol::new(Box::new(sampler));
+    adaptive.target_rate = 0.3;
+    return SimulationDriver::new(Box::new(adaptive), 10);
+}
+
+/// Hard disks in the isothermal–isobaric ensemble.
+///
+/// `disks_npt eos [seed]` computes the equation of state: the packing fraction and the compressibility factor
+/// `Z = PV / NkT` for a series of pressures. `disks_npt compress <packing_fraction> [seed]` raises the pressure
+/// until the given packing fraction is reached and writes the final configuration to `compressed.pdb`.
+pub fn main() {
+    let args: Vec<String> = env::args().collect();
+    let mode = args.get(1).map_or("eos", |m| m.as_str());
+    let target: f64 = if mode == "compress" { args[2].parse::<f64>().unwrap() } else { 0.0 };
+    let mut streams = match args.get(if mode == "compress" { 3 } else { 2 }) {
+        Some(seed) => RandomStreams::new(seed.parse::<u64>().unwrap()),
+        None => RandomStreams::from_entropy()
+    };
+
+    // ---------- dilute system on a square grid
+    let mut system = Coordinates::new(N * N);
+    system.set_box_len(N as f64 * 2.0 * SIGMA);
+    square_grid_atoms(&mut system);
+    let energy = Arc::new(HardDisk::new(SIGMA, E_REP));
+
+    if mode == "compress" {
+        // ---------- raise the pressure by 20% every 10 blocks until the target density is reached
+        let mut reduced_pressure = 0.1;
+        while packing_fraction(&system) < target {
+            let mut driver = npt_driver(energy.clone(), reduced_pressure / (SIGMA * SIGMA), &system, streams.split());
+            for _ in 0..10 {
+                driver.run(1, &mut system, energy.as_ref()).unwrap();
+                if packing_fraction(&system) >= target { break; }
+            }
+            println!("P* = {:.3} packing fraction: {:.4}", reduced_pressure, packing_fraction(&system));
+            reduced_pressure *= 1.2;
+        }
+        // --- expanding the box can't create any overlap
+        let target_volume = system.volume() * packing_fraction(&system) / target;
+        system.set_volume(target_volume);
+        println!("final packing fraction: {:.4}, energy: {}", packing_fraction(&system), energy.energy(&system));
+        coordinates_to_pdb(&system, 1, "compressed.pdb", false);
+        return;
+    }
+
+    // ---------- equation of state; every pressure starts from the final state of the previous one
+    println!("{:>8} {:>10} {:>8} {:>8} {:>8} {:>10}", "P*", "phi", "+/-", "Z", "+/-", "vol_rate");
+    for reduced_pressure in [0.05, 0.1, 0.2, 0.4, 0.8, 1.2, 1.6, 2.4] {
+        let pressure = reduced_pressure / (SIGMA * SIGMA);
+        let mut driver = npt_driver(energy.clone(), pressure, &system, streams.split());
+        driver.run(BLOCKS_PER_PRESSURE / 2, &mut system, energy.as_ref()).unwrap();
+        let mut phi = TimeSeries::new("phi");
+        let mut z = TimeSeries::new("Z");
+        for _ in 0..BLOCKS_PER_PRESSURE {
+            driver.run(1, &mut system, energy.as_ref()).unwrap();
+            phi.push(packing_fraction(&system));
+            z.push(pressure * system.volume() / system.size() as f64);
+        }
+        let rate = driver.sampler().get_mover(1).unwrap().acceptance_statistics().success_rate();
+        println!("{:>8.3} {:>10.4} {:>8.4} {:>8.3} {:>8.3} {:>10.3}", reduced_pressure, phi.mean(),
+                 phi.standard_error(), z.mean(), z.standard_error(), rate);
+    }
+}
@@ -0,0 +1,64 @@
+use std::env;
+
+use simulations_base::{unknown_kind, ComponentConfig, ComponentFactory, Energy, Mover, MoverConfig, Observer,
+                       ObserverConfig, SimulationError, SimulationRunner, TermConfig};
+use disks::hard_disks::{DiskMover, HardDisk};
+use disks::observers::{ObserveDensity, PdbObserver};
+use disks::vec2::{Coordinates, square_grid_atoms};
+
+/// Creates components of the hard disks model.
+///
+/// System: `square_grid` (`n_disks`, `box_len`); energy term: `hard_disk` (`r`, `e_rep`);
+/// mover: `disk`; observers: `pdb` and `density` (`dxy`, `nxy`), both writing to their `output`
+struct DisksFactory;
+
+impl ComponentFactory<Coordinates> for DisksFactory {
+    fn create_system(&self, config: &ComponentConfig) -> Result<Coordinates, SimulationError> {
+        match config.kind.as_str() {
+            "square_grid" => {
+                let mut system = Coordinates::new(config.params.number("n_disks")? as usize);
+                system.set_box_len(config.params.number("box_len")?);
+                square_grid_atoms(&mut system);
+                Ok(system)
+            }
+            _ => Err(unknown_kind("system", &config.kind)),
+        }
+    }
+
+    fn create_energy_term(&self, config: &TermConfig) -> Result<Box<dyn Energy<Coordinates>>, SimulationError> {
+        match config.kind.as_str() {
+            "hard_disk" => Ok(Box::new(HardDisk::new(config.params.number("r")?,
+                                                     config.params.number_or("e_rep", 10000.0)?))),
+            _ => Err(unknown_kind("energy term", &config.kind)),
+        }
+    }
+
+    fn create_mover(&self, config: &MoverConfig) -> Result<Box<dyn Mover<Coordinates>>, SimulationError> {
+        match config.kind.as_str() {
+            "disk" => Ok(Box::new(DiskMover::new(config.max_range))),
+            _ => Err(unknown_kind("mover", &config.kind)),
+        }
+    }
+
+    fn create_observer(&self, config: &ObserverConfig) -> Result<Box<dyn Observer<Coordinates>>, SimulationError> {
+        match config.kind.as_str() {
+            "pdb" => Ok(Box::new(PdbObserver::new(&config.output))),
+            "density" => Ok(Box::new(ObserveDensity::new(config.params.number_or("dxy", 1.0)?,
+                                                         config.params.number("nxy")? as u16, &config.output))),
+            _ => Err(unknown_kind("observer", &config.kind)),
+        }
+    }
+}
+
+/// Runs a hard disks simulation described by a TOML file given as the only argument
+pub fn main() {
+    let Some(fname) = env::args().nth(1) else {
+        eprintln!("Usage: disks_run <config.toml>");
+        std::process::exit(1);
+    };
+    let result = SimulationRunner::from_file(&fname, &DisksFactory).and_then(|mut runner| runner.run());
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,59 @@
+use std::env;
+use std::sync::Arc;
+
+use simulations_base::{CollectiveVariable, Energy, MetropolisCriterion, MCProtocol, MoversSet, RandomStreams,
+                       SimulationDriver, System, UmbrellaSampling};
+use disks::hard_disks::{DiskMover, HardDisk};
+use disks::vec2::{Coordinates, Vec2, square_grid_atoms};
+
+const N: usize = 20;
+const R_REP: f64 = 4.0;
+const E_REP: f64 = 10000.0;
+const R_PROBE: f64 = 20.0;      // radius of the probe circle in the middle of the box
+const W_PROBE: f64 = 1.0;       // width of the smooth edge of the probe circle
+
+/// Local density: the number of disks inside the probe circle, counted with a smooth switching function
+fn local_density(system: &Coordinates) -> f64 {
+    let center = Vec2::from_float(system.box_len() / 2.0);
+    let mut n = 0.0;
+    for i in 0..system.size() {
+        let r = system.closest_distance_square_to_vec(i, &center).sqrt();
+        n += 1.0 / (1.0 + ((r - R_PROBE) / W_PROBE).exp());
+    }
+    return n;
+}
+
+/// Free energy of local density fluctuations of hard disks, computed by umbrella sampling and WHAM.
+///
+/// Usage: disks_umbrella [n_blocks] [seed]
+pub fn main() {
+    let n_blocks: usize = env::args().nth(1).map_or(200, |a| a.parse::<usize>().unwrap());
+    let streams = match env::args().nth(2) {
+        Some(seed) => RandomStreams::new(seed.parse::<u64>().unwrap()),
+        None => RandomStreams::from_entropy()
+    };
+
+    let mut system = Coordinates::new(N * N);
+    system.set_box_len(N as f64 * 6.0);
+    square_grid_atoms(&mut system);
+    let energy: Arc<dyn Energy<Coordinates>> = Arc::new(HardDisk::new(R_REP, E_REP));
+    let cv: CollectiveVariable<Coordinates> = Arc::new(local_density);
+
+    // ---------- windows every 4 disks around the unbiased average of about 35 disks
+    let centers: Vec<f64> = (0..13).map(|i| 12.0 + 4.0 * i as f64).collect();
+    let mut umbrella = UmbrellaSampling::new(&system, energy, cv, &centers, 0.1, streams, |_, window_streams| {
+        let mut sampler: MCProtocol<MetropolisCriterion, Coordinates> =
+            MCProtocol::with_streams(MetropolisCriterion::new(1.0), window_streams);
+        sampler.add_mover(Box::new(DiskMover::new(3.0)));
+        SimulationDriver::new(Box::new(sampler), 5)
+    });
+    umbrella.burn_in = n_blocks / 10;
+    umbrella.run(n_blocks).unwrap();
+
+    println!("{:>8} {:>10} {:>8}", "center", "<cv>", "+/-");
+    for (center, samples) in centers.iter().zip(umbrella.samples().iter()) {
+        println!("{:>8.1} {:>10.3} {:>8.3}", center, samples.mean(), samples.standard_error());
+    }
+    let profile = umbrella.free_energy(50, 1.0).unwrap();
+    print!("{}", profile);
+}
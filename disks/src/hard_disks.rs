@@ -0,0 +1,104 @@
+use rand::Rng;
+
+use simulations_base::{AcceptanceStatistics, ChangedPositions, Energy, Mover, RandomStream, System, UndoLog};
+
+use crate::vec2::Coordinates;
+
+pub struct DiskMover {
+    max_step: f64,
+    succ_rate: AcceptanceStatistics
+}
+
+impl DiskMover {
+    pub fn new(max_range: f64) -> DiskMover {
+        DiskMover{ max_step: max_range, succ_rate: Default::default() }
+    }
+}
+
+impl Mover<Coordinates> for DiskMover {
+
+    fn perturb(&mut self, system: &mut Coordinates, rng: &mut RandomStream) -> ChangedPositions {
+        if system.size() == 0 { return ChangedPositions::Range(0..0); }
+        let i_moved = rng.gen_range(0..system.size());
+        system.add(i_moved,rng.gen_range(-self.max_step..self.max_step),
+                   rng.gen_range(-self.max_step..self.max_step));
+
+        ChangedPositions::single(i_moved)
+    }
+
+    fn perturb_in_place(&mut self, system: &mut Coordinates, undo: &mut UndoLog<Coordinates>, rng: &mut RandomStream) -> bool {
+        if system.size() == 0 { return true; }
+        let i_moved = rng.gen_range(0..system.size());
+        undo.record(system, i_moved);
+        system.add(i_moved,rng.gen_range(-self.max_step..self.max_step),
+                   rng.gen_range(-self.max_step..self.max_step));
+
+        true
+    }
+
+    fn acceptance_statistics(&self) -> AcceptanceStatistics { self.succ_rate.clone() }
+
+    fn add_success(&mut self) { self.succ_rate.n_succ += 1; }
+
+    fn add_failure(&mut self) { self.succ_rate.n_failed += 1; }
+
+    fn set_acceptance_statistics(&mut self, stats: AcceptanceStatistics) { self.succ_rate = stats; }
+
+    fn max_range(&self) -> f64 { return self.max_step; }
+
+    fn set_max_range(&mut self, new_val: f64) { self.max_step = new_val; }
+}
+
+pub struct HardDisk { r: f64, e_rep: f64, r2: f64 }
+
+impl HardDisk {
+    pub fn new(r:f64, e_rep: f64) -> HardDisk {
+        HardDisk { r, e_rep, r2: r * r }
+    }
+
+    pub fn r(&self) -> f64 { self.r }
+}
+
+impl Energy<Coordinates> for HardDisk {
+
+    fn energy(&self, system: &Coordinates) -> f64 {
+        let mut e = 0.0f64;
+        for i in 1..system.size() {
+            for j in 0..i {
+                let d2 = system.closest_distance_square(i, j);
+                if d2.le(&self.r2) { e += self.e_rep }
+            }
+        }
+        return e;
+    }
+
+    fn energy_by_pos(&self, system: &Coordinates, pos: usize) -> f64 {
+        let mut e = 0.0f64;
+        for j in 0..pos {
+            let d2 = system.closest_distance_square(pos, j);
+            if d2.le(&self.r2) { e += self.e_rep }
+        }
+        for j in pos+1..system.size() {
+            let d2 = system.closest_distance_square(pos, j);
+            if d2.le(&self.r2) { e += self.e_rep }
+        }
+        return e;
+    }
+
+    fn delta_energy_by_pos(&self, old_system: &Coordinates, new_system: &Coordinates, pos: usize) -> (f64, f64) {
+        let (mut en_old, mut en_new) = (0.0f64, 0.0f64);
+        for j in 0..pos {
+            let mut d2 = old_system.closest_distance_square(pos, j);
+            if d2.le(&self.r2) { en_old += self.e_rep }
+            d2 = new_system.closest_distance_square(pos, j);
+            if d2.le(&self.r2) { en_new += self.e_rep }
+        }
+        for j in pos+1..old_system.size() {
+            let mut d2 = old_system.closest_distance_square(pos, j);
+            if d2.le(&self.r2) { en_old += self.e_rep }
+            d2 = new_system.closest_distance_square(pos, j);
+            if d2.le(&self.r2) { en_new += self.e_rep }
+        }
+        (en_old, en_new)
+    }
+}
@@ -0,0 +1,145 @@
+use rand::seq::SliceRandom;
+
+use simulations_base::{KineticSystem, RandomStream, System};
+
+/// Lattice steps of a hop: +x, -x, +y and -y
+const HOPS: [(i64, i64); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+/// Offsets of all the lattice sites closer than `radius` to the origin, including the origin itself
+fn sites_within(radius: f64) -> Vec<(i64, i64)> {
+    let r = radius.ceil() as i64;
+    let mut out = vec![];
+    for dx in -r..=r {
+        for dy in -r..=r {
+            if ((dx * dx + dy * dy) as f64) < radius * radius { out.push((dx, dy)); }
+        }
+    }
+    return out;
+}
+
+/// Hard disks on a periodic square lattice, diffusing by hops to neighboring sites.
+///
+/// Every disk sits on a lattice site and no two disks may be closer than `sigma` lattice units; for `sigma = 1.0`
+/// it's a lattice gas with at most one disk per site. Every disk hops to each of the four neighboring sites at rate
+/// `hop_rate`, provided it doesn't overlap any other disk there. Event `4 * i + k` is the hop of the `i`-th disk
+/// in the `k`-th direction. Positions are kept unwrapped, so displacements can be measured across the box.
+#[derive(Clone, Debug)]
+pub struct LatticeDisks {
+    side: usize,
+    sigma: f64,
+    hop_rate: f64,
+    positions: Vec<(i64, i64)>,
+    origins: Vec<(i64, i64)>,
+    /// index of the disk that sits on each site
+    occupancy: Vec<Option<usize>>,
+    /// sites that must be free for a disk to sit on the origin
+    excluded: Vec<(i64, i64)>,
+    /// sites from which a disk may be blocked by a disk sitting on the origin
+    affected: Vec<(i64, i64)>,
+}
+
+impl LatticeDisks {
+    /// Places `n` disks on randomly chosen sites of a `side` x `side` lattice.
+    ///
+    /// Sites are visited in a random order and a disk is placed on every site where it doesn't overlap any other one;
+    /// for `sigma = 1.0` this gives the equilibrium distribution. Returns `None` when fewer than `n` disks fit.
+    pub fn random(side: usize, n: usize, sigma: f64, hop_rate: f64, rng: &mut RandomStream) -> Option<LatticeDisks> {
+        let mut system = LatticeDisks { side, sigma, hop_rate, positions: vec![], origins: vec![],
+            occupancy: vec![None; side * side], excluded: sites_within(sigma), affected: sites_within(sigma + 1.0) };
+        let mut sites: Vec<(i64, i64)> = (0..side * side).map(|k| ((k % side) as i64, (k / side) as i64)).collect();
+        sites.shuffle(rng);
+        for site in sites {
+            if system.positions.len() == n { break; }
+            if system.is_free(site, None) {
+                let idx = system.site_index(site);
+                system.occupancy[idx] = Some(system.positions.len());
+                system.positions.push(site);
+            }
+        }
+        if system.positions.len() < n { return None; }
+        system.origins = system.positions.clone();
+        return Some(system);
+    }
+
+    pub fn side(&self) -> usize { self.side }
+
+    pub fn sigma(&self) -> f64 { self.sigma }
+
+    pub fn hop_rate(&self) -> f64 { self.hop_rate }
+
+    /// Number of disks per lattice site
+    pub fn density(&self) -> f64 { self.positions.len() as f64 / (self.side * self.side) as f64 }
+
+    /// Unwrapped position of the `i`-th disk
+    pub fn position(&self, i: usize) -> (i64, i64) { self.positions[i] }
+
+    /// Mean squared displacement of the disks from their origins
+    pub fn msd(&self) -> f64 {
+        let sum: i64 = self.positions.iter().zip(self.origins.iter())
+            .map(|(p, o)| (p.0 - o.0) * (p.0 - o.0) + (p.1 - o.1) * (p.1 - o.1)).sum();
+        return sum as f64 / self.positions.len() as f64;
+    }
+
+    /// Makes the current positions the origins of displacements
+    pub fn reset_origins(&mut self) { self.origins.clone_from(&self.positions); }
+
+    fn site_index(&self, site: (i64, i64)) -> usize {
+        let l = self.side as i64;
+        (site.1.rem_euclid(l) * l + site.0.rem_euclid(l)) as usize
+    }
+
+    /// Returns true if a disk may sit on a given site; the disk `ignored`, if any, doesn't count as an obstacle
+    fn is_free(&self, site: (i64, i64), ignored: Option<usize>) -> bool {
+        self.excluded.iter().all(|(dx, dy)| {
+            let who = self.occupancy[self.site_index((site.0 + dx, site.1 + dy))];
+            who.is_none() || who == ignored
+        })
+    }
+
+    /// Disks sitting on the sites affected by a disk at a given site
+    fn disks_around(&self, site: (i64, i64), out: &mut Vec<usize>) {
+        for (dx, dy) in self.affected.iter() {
+            if let Some(j) = self.occupancy[self.site_index((site.0 + dx, site.1 + dy))] { out.push(j); }
+        }
+    }
+}
+
+impl System for LatticeDisks {
+    fn size(&self) -> usize { self.positions.len() }
+
+    fn copy_from(&mut self, i: usize, rhs: &Self) {
+        let old = self.site_index(self.positions[i]);
+        if self.occupancy[old] == Some(i) { self.occupancy[old] = None; }
+        self.positions[i] = rhs.positions[i];
+        self.origins[i] = rhs.origins[i];
+        let new = self.site_index(self.positions[i]);
+        self.occupancy[new] = Some(i);
+    }
+}
+
+impl KineticSystem for LatticeDisks {
+    fn count_events(&self) -> usize { 4 * self.positions.len() }
+
+    fn rate(&self, event: usize) -> f64 {
+        let (i, (dx, dy)) = (event / 4, HOPS[event % 4]);
+        let (x, y) = self.positions[i];
+        if self.is_free((x + dx, y + dy), Some(i)) { self.hop_rate } else { 0.0 }
+    }
+
+    fn execute(&mut self, event: usize) -> Vec<usize> {
+        let (i, (dx, dy)) = (event / 4, HOPS[event % 4]);
+        let old = self.positions[i];
+        let new = (old.0 + dx, old.1 + dy);
+        let (old_idx, new_idx) = (self.site_index(old), self.site_index(new));
+        self.occupancy[old_idx] = None;
+        self.occupancy[new_idx] = Some(i);
+        self.positions[i] = new;
+
+        let mut disks = vec![];
+        self.disks_around(old, &mut disks);
+        self.disks_around(new, &mut disks);
+        disks.sort_unstable();
+        disks.dedup();
+        return disks.iter().flat_map(|j| 4 * j..4 * j + 4).collect();
+    }
+}
@@ -0,0 +1,6 @@
+//! Hard disks in a square box with periodic boundaries and on a periodic lattice, shared by the simulation programs
+//! of this crate and by the Python bindings in `py_library_examples/pysimulations`
+pub mod hard_disks;
+pub mod lattice_disks;
+pub mod observers;
+pub mod vec2;
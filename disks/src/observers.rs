@@ -0,0 +1,71 @@
+use std::fs;
+use std::io::Write;
+
+use simulations_base::{out_writer, Observation, Observer, System};
+
+use crate::vec2::{Coordinates, coordinates_to_pdb};
+
+/// Writes every observed conformation as a subsequent model of a PDB trajectory, appended to the output file.
+///
+/// Models are numbered from 1; when the file already holds models, e.g. written before a restart
+/// from a checkpoint, the numbering continues after them.
+pub struct PdbObserver {
+    out_fname: String,
+    i_model: i16
+}
+
+impl PdbObserver {
+    pub fn new(out_fname: &str) -> PdbObserver {
+        let n_models = fs::read_to_string(out_fname)
+            .map(|text| text.lines().filter(|l| l.starts_with("MODEL")).count()).unwrap_or(0);
+        PdbObserver{ out_fname: out_fname.to_string(), i_model: n_models as i16 }
+    }
+}
+
+impl Observer<Coordinates> for PdbObserver {
+    fn observe(&mut self, observation: &Observation<Coordinates>) {
+        self.i_model += 1;
+        coordinates_to_pdb(observation.system, self.i_model, &self.out_fname, true);
+    }
+}
+
+/// Counts disks in the cells of a square grid; the grid covers `[0, nxy * dxy)` along each axis
+/// and disks outside of it are not counted.
+///
+/// The counts are written when the simulation is finalized, to a given file or to the standard output
+/// if the name is empty.
+pub struct ObserveDensity {
+    m:Vec<Vec<u32>>,
+    dxy: f64,
+    out_fname: String,
+}
+
+impl ObserveDensity {
+    pub fn new(dxy:f64, nxy:u16, out_fname: &str) -> ObserveDensity {
+        let m = vec![vec![0; nxy as usize]; nxy as usize];
+        ObserveDensity{m, dxy, out_fname: out_fname.to_string()}
+    }
+
+    fn write(&self) -> std::io::Result<()> {
+        let mut out = out_writer(&self.out_fname)?;
+        for i in 0..self.m.len() {
+            for j in 0..self.m.len() {
+                writeln!(out, "{} {} {}", i, j, self.m[i][j])?;
+            }
+        }
+        out.flush()
+    }
+}
+
+impl Observer<Coordinates> for ObserveDensity {
+    fn observe(&mut self, observation: &Observation<Coordinates>) {
+        let system = observation.system;
+        for i in 0..system.size() {
+            let x = (system.x(i) / self.dxy) as usize;
+            let y = (system.y(i) / self.dxy) as usize;
+            if let Some(cell) = self.m.get_mut(x).and_then(|row| row.get_mut(y)) { *cell += 1; }
+        }
+    }
+
+    fn finalize(&mut self) { self.write().ok(); }
+}
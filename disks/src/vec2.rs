@@ -2,9 +2,12 @@ use std::ops::{Index, IndexMut};
 use std::fs::File;
 use std::io::{Write};
 
-use simulations_base::{System};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug)]
+use simulations_base::{RandomStream, System, UndoSystem, VariableSizeSystem, VolumeSystem};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Vec2 {
     pub x: f64,
     pub y: f64
@@ -22,7 +25,7 @@ impl Vec2 {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Coordinates {
     box_len: f64,
     box_len_half: f64,
@@ -152,6 +155,41 @@ impl System for Coordinates {
     }
 }
 
+impl UndoSystem for Coordinates {
+    type Backup = Vec2;
+
+    fn backup(&self, pos: usize) -> Vec2 { self.v[pos].clone() }
+
+    fn restore(&mut self, pos: usize, backup: &Vec2) {
+        self.v[pos].x = backup.x;
+        self.v[pos].y = backup.y;
+    }
+}
+
+/// The volume of a two-dimensional system is the area of its square box
+impl VolumeSystem for Coordinates {
+    fn volume(&self) -> f64 { self.box_len * self.box_len }
+
+    fn set_volume(&mut self, new_volume: f64) {
+        let factor = (new_volume / self.volume()).sqrt();
+        for v in self.v.iter_mut() {
+            v.x *= factor;
+            v.y *= factor;
+        }
+        self.set_box_len(self.box_len * factor);
+    }
+}
+
+impl VariableSizeSystem for Coordinates {
+    fn insert_random(&mut self, rng: &mut RandomStream) {
+        let x = rng.gen_range(0.0..self.box_len);
+        let y = rng.gen_range(0.0..self.box_len);
+        self.v.push(Vec2::new(x, y));
+    }
+
+    fn remove(&mut self, pos: usize) { self.v.swap_remove(pos); }
+}
+
 impl Index<usize> for Coordinates {
     type Output = Vec2;
     fn index(&self, i: usize) -> &Vec2 {
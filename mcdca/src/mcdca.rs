@@ -1,12 +1,16 @@
 use rand::Rng;
+use std::env;
 use std::collections::HashMap;
-use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
 
 use bioshell_core::sequence::Sequence;
 
-use simulations_base::{Energy, MetropolisCriterion, MCProtocol, System, MoversSet, Sampler, Mover, AcceptanceStatistics};
+use simulations_base::{Energy, MetropolisCriterion, MCProtocol, System, MoversSet, Mover, AcceptanceStatistics,
+                       RandomStream, RandomStreams, SimulationDriver, Observer, Observation, EnergyObserver, ChangedPositions,
+                       StatisticsObserver};
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct SequenceSystem (Vec<u8>);
 
 impl System for SequenceSystem {
@@ -150,6 +154,24 @@ pub fn accumulate_counts(system: &SequenceSystem, n_aa:usize, counts: &mut Vec<V
     }
 }
 
+/// Observer that accumulates pairwise amino acid counts by calling [`accumulate_counts()`](accumulate_counts)
+pub struct CountsObserver {
+    pub n_aa: usize,
+    pub counts: Vec<Vec<f32>>
+}
+
+impl CountsObserver {
+    pub fn new(seq_len: usize, n_aa: usize) -> CountsObserver {
+        CountsObserver{ n_aa, counts: vec![vec![0.0; seq_len * n_aa]; seq_len * n_aa] }
+    }
+}
+
+impl Observer<SequenceSystem> for CountsObserver {
+    fn observe(&mut self, observation: &Observation<SequenceSystem>) {
+        accumulate_counts(observation.system, self.n_aa, &mut self.counts);
+    }
+}
+
 struct SingleAAMover {
     n_aa: usize,
     succ_rate: AcceptanceStatistics
@@ -160,13 +182,12 @@ impl SingleAAMover {
 }
 
 impl Mover<SequenceSystem> for SingleAAMover {
-    fn perturb(&mut self, system: &mut SequenceSystem) -> Range<usize> {
-        let mut rng = rand::thread_rng();
+    fn perturb(&mut self, system: &mut SequenceSystem, rng: &mut RandomStream) -> ChangedPositions {
         let i_moved = rng.gen_range(0..system.size());
 
         system.0[i_moved] = rng.gen_range(0..self.n_aa as u8);
 
-        i_moved..i_moved
+        ChangedPositions::single(i_moved)
     }
 
     fn acceptance_statistics(&self) -> AcceptanceStatistics { self.succ_rate.clone() }
@@ -175,6 +196,8 @@ impl Mover<SequenceSystem> for SingleAAMover {
 
     fn add_failure(&mut self) { self.succ_rate.n_failed += 1; }
 
+    fn set_acceptance_statistics(&mut self, stats: AcceptanceStatistics) { self.succ_rate = stats; }
+
     fn max_range(&self) -> f64 { 1.0 }
 
     fn set_max_range(&mut self, _new_val: f64) {  }
@@ -193,19 +216,28 @@ pub fn main() {
     // let en = Box::new(Couplings::new(seq_len, aa_order));
     en.energy(&system);
 
+    // ---------- random streams: seed may be given as the first argument to reproduce a run
+    let streams = match env::args().nth(1) {
+        Some(seed) => RandomStreams::new(seed.parse::<u64>().unwrap()),
+        None => RandomStreams::from_entropy()
+    };
+
     // ---------- Sampling
-    let mut sampler: MCProtocol<MetropolisCriterion,SequenceSystem> = MCProtocol::new(MetropolisCriterion::new(1.0));
+    let mut sampler: MCProtocol<MetropolisCriterion,SequenceSystem> =
+        MCProtocol::with_streams(MetropolisCriterion::new(1.0), streams);
     sampler.add_mover(Box::new(SingleAAMover::new(aa_len)));
 
-    // ---------- Observe counts for amino acids
-    let mut counts: Vec<Vec<f32>> = vec![vec![0.0; seq_len * aa_len]; seq_len * aa_len];
-
-    for i in 0..1000 {
-        sampler.make_sweeps(10,&mut system, &en);
-        accumulate_counts(&system, aa_len, &mut counts);
-
-        println!("{} {}", i, en.energy(&system));
-    }
+    // ---------- Observe energy and counts for amino acids
+    let mut driver = SimulationDriver::new(Box::new(sampler), 10);
+    driver.add_observer(Box::new(CountsObserver::new(seq_len, aa_len)), 1);
+    driver.add_observer(Box::new(EnergyObserver::new("").unwrap()), 1);
+    let mut statistics = StatisticsObserver::new("");
+    statistics.burn_in = 100;
+    statistics.add_observable("energy", Box::new(|o| o.energy));
+    driver.add_observer(Box::new(statistics), 1);
+
+    driver.run(1000, &mut system, en.as_ref()).unwrap();
+    driver.finalize();
 
     // let counts = isothermal_mc(&mut system, &en,1000,10000);
     // en.show();
@@ -1,11 +1,13 @@
 use crate::vec3::Vec3;
 
 use rand::Rng;                      // to create a random versor
+use simulations_base::{ContinuousSystem, RandomStream, System};
 
 use std::io::stdout;
 use std::io::{BufWriter,Write};
 use std::fs::{File};
 
+#[derive(Clone)]
 pub struct CoordinatesV {
     pub v: Vec<Vec3>
 }
@@ -59,9 +61,29 @@ impl CoordinatesV {
     }
 }
 
-pub fn random_unit_versor() -> (f32, f32, f32) {
+impl System for CoordinatesV {
+    fn size(&self) -> usize { self.v.len() }
+
+    fn copy_from(&mut self, i: usize, rhs: &Self) { self.v[i] = rhs.v[i].clone(); }
+}
+
+/// Degrees of freedom are `x, y, z` of every bead, in the order of beads
+impl ContinuousSystem for CoordinatesV {
+    fn dof(&self) -> usize { 3 * self.v.len() }
+
+    fn coordinate(&self, i: usize) -> f64 {
+        let v = &self.v[i / 3];
+        return match i % 3 { 0 => v.x, 1 => v.y, _ => v.z } as f64;
+    }
+
+    fn set_coordinate(&mut self, i: usize, value: f64) {
+        let v = &mut self.v[i / 3];
+        match i % 3 { 0 => v.x = value as f32, 1 => v.y = value as f32, _ => v.z = value as f32 }
+    }
+}
+
+pub fn random_unit_versor(rng: &mut RandomStream) -> (f32, f32, f32) {
 
-    let mut rng = rand::thread_rng();
     let x : f32 = rng.gen_range(-1.0..1.0);
     let y : f32 = rng.gen_range(-1.0..1.0);
     let z : f32 = rng.gen_range(-1.0..1.0);
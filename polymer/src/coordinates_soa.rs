@@ -1,5 +1,6 @@
 
 use rand::Rng;                      // to create a random versor
+use simulations_base::RandomStream;
 
 use std::io::stdout;
 use std::io::{BufWriter,Write};
@@ -63,9 +64,8 @@ impl Coordinates {
     }
 }
 
-pub fn random_unit_versor() -> (f32, f32, f32) {
+pub fn random_unit_versor(rng: &mut RandomStream) -> (f32, f32, f32) {
 
-    let mut rng = rand::thread_rng();
     let x : f32 = rng.gen_range(-1.0..1.0);
     let y : f32 = rng.gen_range(-1.0..1.0);
     let z : f32 = rng.gen_range(-1.0..1.0);
@@ -5,15 +5,16 @@ use rand::Rng;
 use std::env;
 use std::time::Instant;
 
+use simulations_base::{Energy, RandomStream, RandomStreams, TotalEnergy};
+
 const A :f32 = 3.0;
 const A2 :f32 = A*A;
 const B :f32 = 4.0;
 const B2 :f32 = B*B;
 const K :f32 = 1.0;     // stiffness
 
-pub fn metropolis_criterion(temp: f64, en_before: f64, en_after: f64) -> bool {
+pub fn metropolis_criterion(temp: f64, en_before: f64, en_after: f64, rng: &mut RandomStream) -> bool {
     if en_after < en_before { return true; }
-    let mut rng = rand::thread_rng();
     return f64::exp(-(en_after - en_before) / temp) > rng.gen();
 }
 
@@ -36,59 +37,93 @@ macro_rules! pairwise_contact_kernel {
     }
 }
 
-pub fn energy_for_position(pos:usize, chain: & coordinates_aos::CoordinatesV) -> f64 {
-    let mut en: f64 = 0.0;
+/// Harmonic bond stretching term
+pub struct HarmonicBonds;
+
+impl Energy<coordinates_aos::CoordinatesV> for HarmonicBonds {
+    fn energy(&self, chain: &coordinates_aos::CoordinatesV) -> f64 { total_energy(self, chain) }
 
-    let x:f32 = chain.v[pos].x;
-    let y:f32 = chain.v[pos].y;
-    let z:f32 = chain.v[pos].z;
-    if pos > 0 {
-        let d = chain.distance_square(pos-1,pos) - 3.8;
-        en += (K*d*d) as f64;
+    fn energy_by_pos(&self, chain: &coordinates_aos::CoordinatesV, pos: usize) -> f64 {
+        let mut en: f64 = 0.0;
+        if pos > 0 {
+            let d = chain.distance_square(pos-1,pos) - 3.8;
+            en += (K*d*d) as f64;
+        }
+        if pos < chain.size()-1 {
+            let d = chain.distance_square(pos,pos+1) - 3.8;
+            en += (K*d*d) as f64;
+        }
+        return en;
     }
-    if pos < chain.size()-1 {
-        let d = chain.distance_square(pos,pos+1) - 3.8;
-        en += (K*d*d) as f64;
+
+    fn delta_energy_by_pos(&self, old_chain: &coordinates_aos::CoordinatesV, new_chain: &coordinates_aos::CoordinatesV, pos: usize) -> (f64, f64) {
+        (self.energy_by_pos(old_chain, pos), self.energy_by_pos(new_chain, pos))
     }
+}
+
+/// Contact energy between non-bonded beads: hard core repulsion below A, attraction below B
+pub struct ContactEnergy;
+
+impl Energy<coordinates_aos::CoordinatesV> for ContactEnergy {
+    fn energy(&self, chain: &coordinates_aos::CoordinatesV) -> f64 { total_energy(self, chain) }
 
-    if pos > 1 {
-        for i in 0..pos - 1 {
-            pairwise_contact_kernel!(x,y,z,chain, i, A2, B2, en);
+    fn energy_by_pos(&self, chain: &coordinates_aos::CoordinatesV, pos: usize) -> f64 {
+        let mut en: f64 = 0.0;
+
+        let x: f32 = chain.v[pos].x;
+        let y: f32 = chain.v[pos].y;
+        let z: f32 = chain.v[pos].z;
+        if pos > 1 {
+            for i in 0..pos - 1 {
+                pairwise_contact_kernel!(x, y, z, chain, i, A2, B2, en);
+            }
         }
-    }
-    if pos < chain.size()-2 {
-        for i in pos + 2..chain.size() {
-            pairwise_contact_kernel!(x,y,z,chain, i, A2, B2, en);
+        if pos < chain.size() - 2 {
+            for i in pos + 2..chain.size() {
+                pairwise_contact_kernel!(x, y, z, chain, i, A2, B2, en);
+            }
         }
+        return en;
+    }
+
+    fn delta_energy_by_pos(&self, old_chain: &coordinates_aos::CoordinatesV, new_chain: &coordinates_aos::CoordinatesV, pos: usize) -> (f64, f64) {
+        (self.energy_by_pos(old_chain, pos), self.energy_by_pos(new_chain, pos))
     }
-    return en;
 }
 
-pub fn energy(chain: & coordinates_aos::CoordinatesV) -> f64 {
+/// Sums the energy of every position; each interaction is counted twice
+fn total_energy(term: &dyn Energy<coordinates_aos::CoordinatesV>, chain: &coordinates_aos::CoordinatesV) -> f64 {
     let mut en: f64 = 0.0;
     for i in 0..chain.size() {
-        en += energy_for_position(i,chain);
+        en += term.energy_by_pos(chain, i);
     }
     return en / 2.0;
 }
 
-pub fn sample(chain: &mut coordinates_aos::CoordinatesV, temp: f64, n_cycles: i32) -> i32 {
-    let mut rng = rand::thread_rng();
+/// Energy of the polymer model: bond stretching plus contact energy
+pub fn polymer_energy() -> TotalEnergy<coordinates_aos::CoordinatesV> {
+    let mut energy = TotalEnergy::new();
+    energy.add_term("bonds", 1.0, Box::new(HarmonicBonds));
+    energy.add_term("contacts", 1.0, Box::new(ContactEnergy));
+    return energy;
+}
+
+pub fn sample(chain: &mut coordinates_aos::CoordinatesV, energy: &dyn Energy<coordinates_aos::CoordinatesV>, temp: f64, n_cycles: i32, rng: &mut RandomStream) -> i32 {
 
     let step :f32 = 0.5;
     let mut succ = 0;
     for _i in 0..n_cycles {
         for _j in 0..chain.size() {
             let i_moved = rng.gen_range(0..chain.size());
-            let en_before = energy_for_position(i_moved,chain);
+            let en_before = energy.energy_by_pos(chain, i_moved);
 
             let dx : f32 = rng.gen_range(-step..step);
             let dy : f32 = rng.gen_range(-step..step);
             let dz : f32 = rng.gen_range(-step..step);
 
             add_point_coordinates_v!(chain, i_moved, dx, dy, dz);
-            let en_after = energy_for_position(i_moved,chain);
-            if ! metropolis_criterion(temp, en_before, en_after) {
+            let en_after = energy.energy_by_pos(chain, i_moved);
+            if ! metropolis_criterion(temp, en_before, en_after, rng) {
                 add_point_coordinates_v!(chain, i_moved, -dx, -dy, -dz);
             } else { succ+=1; }
         }
@@ -96,13 +131,13 @@ pub fn sample(chain: &mut coordinates_aos::CoordinatesV, temp: f64, n_cycles: i3
     return succ;
 }
 
-pub fn randomize_chain(bond_length:f32, chain: &mut coordinates_aos::CoordinatesV) {
+pub fn randomize_chain(bond_length:f32, chain: &mut coordinates_aos::CoordinatesV, rng: &mut RandomStream) {
     chain.set(0,0.0);
 
     for i in 1..chain.size() {
         let mut go_on:bool = true;
         while go_on {
-            let (x, y, z) = coordinates_aos::random_unit_versor();
+            let (x, y, z) = coordinates_aos::random_unit_versor(rng);
             chain.v[i].x = chain.v[i-1].x + x*bond_length;
             chain.v[i].y = chain.v[i-1].y + y*bond_length;
             chain.v[i].z = chain.v[i-1].z + z*bond_length;
@@ -124,14 +159,19 @@ pub fn main() {
     let n_beads: i32 = if args.len() > 1 { args[1].parse::<i32>().unwrap() } else { 100 };
     let temp: f64 = if args.len() > 2 { args[2].parse::<f64>().unwrap() } else { 1.0 };
     let n_big :i32 = if args.len() > 3 { args[3].parse::<i32>().unwrap() } else { 1000 };
+    let mut streams = if args.len() > 4 { RandomStreams::new(args[4].parse::<u64>().unwrap()) }
+                      else { RandomStreams::from_entropy() };
+    let mut rng = streams.next_stream();
+    let energy = polymer_energy();
     let mut chain = coordinates_aos::CoordinatesV::new(n_beads as usize);
-    randomize_chain(3.8, &mut chain);
+    randomize_chain(3.8, &mut chain, &mut rng);
     chain.to_pdb("");
     let before = Instant::now();
     for i in 0..n_big {
-        let n_succ = sample(&mut chain,temp,n_small);
+        let n_succ = sample(&mut chain, &energy, temp, n_small, &mut rng);
         let (cx, cy, cz) = chain.cm();
-        println!("En: {} : {}, {}, {} {} {}, {:.2?}", i, energy(&chain),
+        let terms = energy.breakdown(&chain);
+        println!("En: {} : {} ({} {}), {}, {} {} {}, {:.2?}", i, energy.energy(&chain), terms[0], terms[1],
                  (n_succ as f32) / ((n_small * n_beads) as f32), cx, cy, cz, before.elapsed());
         if i % 10 == 0 { chain.to_pdb(""); }
     }
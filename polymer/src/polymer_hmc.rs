@@ -0,0 +1,65 @@
+#[allow(dead_code, unused_imports, unused_mut)]
+mod coordinates_aos;
+#[allow(dead_code)]
+mod smooth_polymer;
+#[allow(dead_code)]
+mod vec3;
+
+use std::env;
+use std::sync::Arc;
+
+use simulations_base::{maxwell_boltzmann, AcceptanceObserver, AdaptiveMCProtocol, ContinuousSystem, Energy,
+                       EnergyObserver, Force, HmcMover, LangevinIntegrator, MCProtocol, MetropolisCriterion,
+                       RandomStreams, SimulationDriver, StatisticsObserver};
+
+use coordinates_aos::CoordinatesV;
+use smooth_polymer::{SmoothBonds, SmoothContacts, SmoothPolymer, BOND_LENGTH};
+
+/// Samples the smooth polymer model by Hybrid Monte Carlo, after a short Langevin dynamics equilibration.
+///
+/// Usage: polymer_hmc [n_beads] [temperature] [n_blocks] [seed]
+pub fn main() {
+    let args: Vec<String> = env::args().collect();
+    let n_beads: usize = if args.len() > 1 { args[1].parse::<usize>().unwrap() } else { 50 };
+    let temp: f64 = if args.len() > 2 { args[2].parse::<f64>().unwrap() } else { 1.0 };
+    let n_blocks: usize = if args.len() > 3 { args[3].parse::<usize>().unwrap() } else { 1000 };
+    let mut streams = if args.len() > 4 { RandomStreams::new(args[4].parse::<u64>().unwrap()) }
+                      else { RandomStreams::from_entropy() };
+
+    // ---------- extended chain along the x axis
+    let mut chain = CoordinatesV::new(n_beads);
+    for i in 0..n_beads { chain.v[i].x = (i as f64 * BOND_LENGTH) as f32; }
+    let polymer = Arc::new(SmoothPolymer { bonds: SmoothBonds, contacts: SmoothContacts });
+
+    // ---------- Langevin dynamics equilibration
+    let mut rng = streams.next_stream();
+    let langevin = LangevinIntegrator::new(0.01, 1.0, temp);
+    let mut velocities = vec![0.0; chain.dof()];
+    maxwell_boltzmann(&mut velocities, temp, &mut rng);
+    let mut forces = vec![0.0; chain.dof()];
+    polymer.forces(&chain, &mut forces);
+    let en = langevin.run(&mut chain, &mut velocities, &mut forces, polymer.as_ref(), 10000, &mut rng);
+    println!("# energy after Langevin equilibration: {:.3}", en);
+
+    // ---------- Hybrid Monte Carlo: a single trajectory per sweep, time step tuned for 65% acceptance
+    let mut sampler: MCProtocol<MetropolisCriterion, CoordinatesV> =
+        MCProtocol::with_streams(MetropolisCriterion::new(temp), streams.split());
+    let hmc = HmcMover::new(polymer.clone(), 0.02, 20, temp);
+    sampler.add_weighted_mover(Box::new(hmc), 1.0 / n_beads as f64).unwrap();
+    let mut adaptive = AdaptiveMCProtocol::new(Box::new(sampler));
+    adaptive.target_rate = 0.65;
+
+    let mut driver = SimulationDriver::new(Box::new(adaptive), 10);
+    driver.add_observer(Box::new(EnergyObserver::new("").unwrap()), 10);
+    driver.add_observer(Box::new(AcceptanceObserver::new("").unwrap()), 100);
+    let mut statistics = StatisticsObserver::new("");
+    statistics.burn_in = n_blocks / 10;
+    statistics.add_observable("energy", Box::new(|o| o.energy));
+    driver.add_observer(Box::new(statistics), 1);
+
+    driver.run(n_blocks, &mut chain, polymer.as_ref()).unwrap();
+    driver.finalize();
+    println!("# final energy: {:.3} (bonds {:.3}, contacts {:.3})", polymer.energy(&chain),
+             polymer.bonds.energy(&chain), polymer.contacts.energy(&chain));
+    chain.to_pdb("");
+}
@@ -0,0 +1,61 @@
+#[allow(dead_code, unused_imports, unused_mut)]
+mod coordinates_aos;
+#[allow(dead_code)]
+mod smooth_polymer;
+#[allow(dead_code)]
+mod vec3;
+
+use std::env;
+
+use simulations_base::{NestedSampling, RandomStream, RandomStreams};
+
+use coordinates_aos::{random_unit_versor, CoordinatesV};
+use smooth_polymer::{BeadMover, SmoothBonds, SmoothContacts, SmoothPolymer, BOND_LENGTH};
+
+const MIN_DISTANCE: f32 = 3.0;  // the closest distance between non-bonded beads of a starting chain
+
+/// Random self-avoiding chain with all bonds of the ideal length
+fn random_chain(n_beads: usize, rng: &mut RandomStream) -> CoordinatesV {
+    let mut chain = CoordinatesV::new(n_beads);
+    let mut i = 1;
+    while i < n_beads {
+        let (x, y, z) = random_unit_versor(rng);
+        chain.v[i].x = chain.v[i - 1].x + x * BOND_LENGTH as f32;
+        chain.v[i].y = chain.v[i - 1].y + y * BOND_LENGTH as f32;
+        chain.v[i].z = chain.v[i - 1].z + z * BOND_LENGTH as f32;
+        if (0..i - 1).all(|j| chain.distance_square(i, j) > MIN_DISTANCE * MIN_DISTANCE) { i += 1; }
+    }
+    return chain;
+}
+
+/// Heat capacity curve of the smooth polymer model computed by nested sampling.
+///
+/// Live chains start as random self-avoiding walks with ideal bonds, which only approximates the prior: that
+/// shifts the partition function by a constant, but hardly affects the energy and the heat capacity below
+/// the coil-globule transition, where the peak of the heat capacity is expected.
+///
+/// Usage: polymer_nested [n_beads] [n_live] [seed]
+pub fn main() {
+    let args: Vec<String> = env::args().collect();
+    let n_beads: usize = if args.len() > 1 { args[1].parse::<usize>().unwrap() } else { 10 };
+    let n_live: usize = if args.len() > 2 { args[2].parse::<usize>().unwrap() } else { 200 };
+    let mut streams = if args.len() > 3 { RandomStreams::new(args[3].parse::<u64>().unwrap()) }
+                      else { RandomStreams::from_entropy() };
+
+    let mut rng = streams.next_stream();
+    let live: Vec<CoordinatesV> = (0..n_live).map(|_| random_chain(n_beads, &mut rng)).collect();
+    let polymer = SmoothPolymer { bonds: SmoothBonds, contacts: SmoothContacts };
+    let mut sampler = NestedSampling::new(live, &polymer, streams.split()).unwrap();
+    sampler.add_mover(Box::new(BeadMover::new(1.0))).unwrap();
+    sampler.walk_length = 20 * n_beads;
+    sampler.run(60 * n_live, &polymer).unwrap();
+
+    let lowest = sampler.live_energies().iter().cloned().fold(f64::INFINITY, f64::min);
+    println!("# {} iterations, lowest energy found: {:.3}", sampler.levels().len(), lowest);
+    println!("{:>8} {:>12} {:>12} {:>12}", "T", "ln_Z", "<E>", "C_v");
+    for i in 1..=30 {
+        let thermo = sampler.thermodynamics(0.05 * i as f64);
+        println!("{:>8.3} {:>12.4} {:>12.4} {:>12.4}", thermo.temperature, thermo.log_z, thermo.energy,
+                 thermo.heat_capacity);
+    }
+}
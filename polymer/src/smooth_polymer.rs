@@ -0,0 +1,167 @@
+use rand::Rng;
+
+use simulations_base::{AcceptanceStatistics, ChangedPositions, ContinuousSystem, Energy, Force, Mover, RandomStream};
+
+use crate::coordinates_aos::CoordinatesV;
+
+pub const BOND_LENGTH: f64 = 3.8;
+const K_BOND: f64 = 5.0;        // bond stiffness
+const R_MIN: f64 = 3.5;         // distance of the contact energy minimum, between A = 3.0 and B = 4.0 of polymer_aos
+const R_CUT: f64 = 1.5 * R_MIN;
+
+/// Harmonic bonds `K_BOND * (r - BOND_LENGTH)^2`, a differentiable variant of `HarmonicBonds` from polymer_aos
+pub struct SmoothBonds;
+
+impl SmoothBonds {
+    /// Energy of the bond between beads `i` and `i + 1`
+    fn bond_energy(chain: &CoordinatesV, i: usize) -> f64 {
+        let d = (chain.distance_square(i, i + 1) as f64).sqrt() - BOND_LENGTH;
+        return K_BOND * d * d;
+    }
+}
+
+impl Energy<CoordinatesV> for SmoothBonds {
+    fn energy(&self, chain: &CoordinatesV) -> f64 {
+        (0..chain.size().saturating_sub(1)).map(|i| SmoothBonds::bond_energy(chain, i)).sum()
+    }
+
+    fn energy_by_pos(&self, chain: &CoordinatesV, pos: usize) -> f64 {
+        let mut en = 0.0;
+        if pos > 0 { en += SmoothBonds::bond_energy(chain, pos - 1); }
+        if pos + 1 < chain.size() { en += SmoothBonds::bond_energy(chain, pos); }
+        return en;
+    }
+
+    fn delta_energy_by_pos(&self, old_chain: &CoordinatesV, new_chain: &CoordinatesV, pos: usize) -> (f64, f64) {
+        (self.energy_by_pos(old_chain, pos), self.energy_by_pos(new_chain, pos))
+    }
+}
+
+impl Force<CoordinatesV> for SmoothBonds {
+    fn forces(&self, chain: &CoordinatesV, forces: &mut [f64]) -> f64 {
+        forces.fill(0.0);
+        let mut en = 0.0;
+        for i in 0..chain.size().saturating_sub(1) {
+            let r = (chain.distance_square(i, i + 1) as f64).sqrt();
+            en += K_BOND * (r - BOND_LENGTH) * (r - BOND_LENGTH);
+            let f_over_r = 2.0 * K_BOND * (r - BOND_LENGTH) / r;
+            for k in 0..3 {
+                let f = f_over_r * (chain.coordinate(3 * i + 3 + k) - chain.coordinate(3 * i + k));
+                forces[3 * i + k] += f;
+                forces[3 * i + 3 + k] -= f;
+            }
+        }
+        return en;
+    }
+}
+
+/// Contact energy between non-bonded beads, a differentiable variant of `ContactEnergy` from polymer_aos.
+///
+/// The 12-6 potential `(R_MIN/r)^12 - 2 (R_MIN/r)^6` has its minimum of -1.0 at `R_MIN`; it is truncated
+/// at `R_CUT` and shifted to zero there.
+pub struct SmoothContacts;
+
+impl SmoothContacts {
+    /// Energy and `-dE/dr / r` of a pair of beads at a given squared distance
+    fn pair(d2: f64) -> (f64, f64) {
+        if d2 >= R_CUT * R_CUT { return (0.0, 0.0); }
+        let s = (R_MIN * R_MIN / d2).powi(3);
+        let c = (R_MIN * R_MIN / (R_CUT * R_CUT)).powi(3);
+        return (s * s - 2.0 * s - (c * c - 2.0 * c), 12.0 * (s * s - s) / d2);
+    }
+}
+
+impl Energy<CoordinatesV> for SmoothContacts {
+    fn energy(&self, chain: &CoordinatesV) -> f64 {
+        let mut en = 0.0;
+        for i in 2..chain.size() {
+            for j in 0..i - 1 { en += SmoothContacts::pair(chain.distance_square(i, j) as f64).0; }
+        }
+        return en;
+    }
+
+    fn energy_by_pos(&self, chain: &CoordinatesV, pos: usize) -> f64 {
+        (0..chain.size()).filter(|j| j.abs_diff(pos) > 1)
+            .map(|j| SmoothContacts::pair(chain.distance_square(pos, j) as f64).0).sum()
+    }
+
+    fn delta_energy_by_pos(&self, old_chain: &CoordinatesV, new_chain: &CoordinatesV, pos: usize) -> (f64, f64) {
+        (self.energy_by_pos(old_chain, pos), self.energy_by_pos(new_chain, pos))
+    }
+}
+
+impl Force<CoordinatesV> for SmoothContacts {
+    fn forces(&self, chain: &CoordinatesV, forces: &mut [f64]) -> f64 {
+        forces.fill(0.0);
+        let mut en = 0.0;
+        for i in 2..chain.size() {
+            for j in 0..i - 1 {
+                let (e, f_over_r) = SmoothContacts::pair(chain.distance_square(i, j) as f64);
+                if f_over_r == 0.0 { continue; }
+                en += e;
+                for k in 0..3 {
+                    let f = f_over_r * (chain.coordinate(3 * i + k) - chain.coordinate(3 * j + k));
+                    forces[3 * i + k] += f;
+                    forces[3 * j + k] -= f;
+                }
+            }
+        }
+        return en;
+    }
+}
+
+/// Smooth polymer model: bonds plus contacts, both providing forces
+pub struct SmoothPolymer { pub bonds: SmoothBonds, pub contacts: SmoothContacts }
+
+impl Energy<CoordinatesV> for SmoothPolymer {
+    fn energy(&self, chain: &CoordinatesV) -> f64 { self.bonds.energy(chain) + self.contacts.energy(chain) }
+
+    fn energy_by_pos(&self, chain: &CoordinatesV, pos: usize) -> f64 {
+        self.bonds.energy_by_pos(chain, pos) + self.contacts.energy_by_pos(chain, pos)
+    }
+
+    fn delta_energy_by_pos(&self, old_chain: &CoordinatesV, new_chain: &CoordinatesV, pos: usize) -> (f64, f64) {
+        (self.energy_by_pos(old_chain, pos), self.energy_by_pos(new_chain, pos))
+    }
+}
+
+impl Force<CoordinatesV> for SmoothPolymer {
+    fn forces(&self, chain: &CoordinatesV, forces: &mut [f64]) -> f64 {
+        let mut contact_forces = vec![0.0; forces.len()];
+        let en = self.bonds.forces(chain, forces) + self.contacts.forces(chain, &mut contact_forces);
+        for (f, c) in forces.iter_mut().zip(contact_forces.iter()) { *f += c; }
+        return en;
+    }
+}
+
+/// Moves a randomly selected bead by a random vector from a cube of a given size
+pub struct BeadMover {
+    max_step: f64,
+    succ_rate: AcceptanceStatistics
+}
+
+impl BeadMover {
+    pub fn new(max_range: f64) -> BeadMover { BeadMover { max_step: max_range, succ_rate: Default::default() } }
+}
+
+impl Mover<CoordinatesV> for BeadMover {
+    fn perturb(&mut self, chain: &mut CoordinatesV, rng: &mut RandomStream) -> ChangedPositions {
+        let i_moved = rng.gen_range(0..chain.size());
+        for k in 3 * i_moved..3 * i_moved + 3 {
+            chain.set_coordinate(k, chain.coordinate(k) + rng.gen_range(-self.max_step..self.max_step));
+        }
+        ChangedPositions::single(i_moved)
+    }
+
+    fn acceptance_statistics(&self) -> AcceptanceStatistics { self.succ_rate.clone() }
+
+    fn add_success(&mut self) { self.succ_rate.n_succ += 1; }
+
+    fn add_failure(&mut self) { self.succ_rate.n_failed += 1; }
+
+    fn set_acceptance_statistics(&mut self, stats: AcceptanceStatistics) { self.succ_rate = stats; }
+
+    fn max_range(&self) -> f64 { self.max_step }
+
+    fn set_max_range(&mut self, new_val: f64) { self.max_step = new_val; }
+}
@@ -0,0 +1,24 @@
+import numpy as np
+
+from simulations import (AdaptiveMCProtocol, DiskMover, HardDiskEnergy, HardDisks, MCProtocol,
+                         MetropolisCriterion)
+
+# 400 disks of diameter 4.0 placed on a square grid; the sampler and the energy run in Rust
+system = HardDisks(400, 120.0)
+energy = HardDiskEnergy(4.0, 10000.0)
+
+sampler = MCProtocol(MetropolisCriterion(1.0), seed=7)
+sampler.add_mover(DiskMover(1.0))
+sampler = AdaptiveMCProtocol(sampler)
+sampler.target_rate = 0.3
+
+for block in range(10):
+    sampler.make_sweeps(100, system, energy)
+    stats = sampler.acceptance_statistics(0)
+    print(f"{sampler.count_sweeps():6d} energy: {energy.energy(system):8.1f} "
+          f"success rate: {stats.success_rate():.3f} step: {sampler.max_range(0):.3f}")
+
+# coordinates come as a (400, 2) numpy array
+xy = system.coordinates()
+print("center of mass:", xy.mean(axis=0))
+np.savetxt("disks.txt", xy, fmt="%.4f")
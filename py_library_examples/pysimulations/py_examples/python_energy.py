@@ -0,0 +1,63 @@
+import random
+
+from simulations import IsingEnergy, MCProtocol, MetropolisCriterion, SpinFlipMover, SpinLattice
+
+L = 8
+T = 3.0
+
+
+class PythonIsing:
+    """Ising energy prototyped in Python; it must agree with the native IsingEnergy"""
+
+    def neighbors(self, i):
+        r, c = divmod(i, L)
+        return [((r + 1) % L) * L + c, ((r - 1) % L) * L + c, r * L + (c + 1) % L, r * L + (c - 1) % L]
+
+    def energy(self, system):
+        # --- every pair counted once: the right and the lower neighbor of each spin
+        spins = [1 - 2 * system.spin(i) for i in range(len(system))]
+        return -sum(spins[i] * spins[j] for i in range(len(spins)) for j in self.neighbors(i)[::2])
+
+    def energy_by_pos(self, system, i):
+        s = 1 - 2 * system.spin(i)
+        return -sum(s * (1 - 2 * system.spin(j)) for j in self.neighbors(i))
+
+
+class PythonFlip:
+    """Flips a random spin; changes the given system in place and returns the changed positions.
+
+    The sampler sets ``seed`` before every move, so the run is reproducible for a seeded sampler
+    """
+
+    def __init__(self):
+        self.seed = 0
+
+    def perturb(self, system):
+        i = random.Random(self.seed).randrange(len(system))
+        system.set_spin(i, 1 - system.spin(i))
+        return [i]
+
+
+def average_energy(sampler, system, energy, n_sweeps=1000):
+    sampler.make_sweeps(100, system, energy)
+    total = 0.0
+    for _ in range(n_sweeps):
+        sampler.make_sweeps(1, system, energy)
+        total += native.energy(system)
+    return total / n_sweeps / len(system)
+
+
+native = IsingEnergy(1.0, 0.0)
+
+system = SpinLattice([L, L])
+system.randomize(seed=1)
+print("energy of a random state, python:", PythonIsing().energy(system), "native:", native.energy(system))
+
+sampler = MCProtocol(MetropolisCriterion(T), seed=1)
+sampler.add_mover(SpinFlipMover())
+print("<E>/N native energy and mover:", average_energy(sampler, system, native))
+
+sampler = MCProtocol(MetropolisCriterion(T), seed=2)
+sampler.add_mover(PythonFlip())
+print("<E>/N python energy and mover:", average_energy(sampler, system, PythonIsing()))
+print("spins:", system.states())
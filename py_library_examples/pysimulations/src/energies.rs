@@ -0,0 +1,232 @@
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+
+use pyo3::exceptions::PyTypeError;
+use pyo3::prelude::*;
+
+use disks::hard_disks::HardDisk;
+use simulations_base::{ChangedPositions, Energy, IsingEnergy, PottsEnergy};
+
+use crate::systems::{system_to_python, with_system, AnySystem, AsSystem, SystemKind};
+
+/// The first exception raised by Python code called back from a running simulation.
+///
+/// Rust traits can't return Python exceptions, so a callback stores its exception here and the sampler
+/// raises it when the sweeps are done; after an exception the callback isn't called again.
+#[derive(Clone, Default)]
+pub struct CallbackError(Arc<Mutex<Option<PyErr>>>);
+
+impl CallbackError {
+    pub fn is_set(&self) -> bool { self.0.lock().unwrap().is_some() }
+
+    pub fn set(&self, error: PyErr) {
+        let mut slot = self.0.lock().unwrap();
+        if slot.is_none() { *slot = Some(error); }
+    }
+
+    pub fn take(&self) -> Option<PyErr> { self.0.lock().unwrap().take() }
+}
+
+/// Energy of a Rust system, evaluated for [`AnySystem`]; any other kind of system has `NaN` energy
+pub struct NativeEnergy<S, E> {
+    energy: E,
+    system: PhantomData<fn() -> S>,
+}
+
+impl<S, E: Energy<S>> NativeEnergy<S, E> {
+    pub fn new(energy: E) -> NativeEnergy<S, E> { NativeEnergy { energy, system: PhantomData } }
+}
+
+impl<S, E: Energy<S>> Energy<AnySystem> for NativeEnergy<S, E> where AnySystem: AsSystem<S> {
+    fn energy(&self, system: &AnySystem) -> f64 {
+        AsSystem::<S>::get(system).map_or(f64::NAN, |s| self.energy.energy(s))
+    }
+
+    fn energy_by_pos(&self, system: &AnySystem, pos: usize) -> f64 {
+        AsSystem::<S>::get(system).map_or(f64::NAN, |s| self.energy.energy_by_pos(s, pos))
+    }
+
+    fn delta_energy_by_pos(&self, old_system: &AnySystem, new_system: &AnySystem, pos: usize) -> (f64, f64) {
+        match (AsSystem::<S>::get(old_system), AsSystem::<S>::get(new_system)) {
+            (Some(old), Some(new)) => self.energy.delta_energy_by_pos(old, new, pos),
+            _ => (f64::NAN, f64::NAN),
+        }
+    }
+
+    fn delta_energy(&self, old_system: &AnySystem, new_system: &AnySystem, changed: &ChangedPositions) -> (f64, f64) {
+        match (AsSystem::<S>::get(old_system), AsSystem::<S>::get(new_system)) {
+            (Some(old), Some(new)) => self.energy.delta_energy(old, new, changed),
+            _ => (f64::NAN, f64::NAN),
+        }
+    }
+}
+
+/// Energy implemented in Python by an object with an `energy(system)` method.
+///
+/// When the object also has `energy_by_pos(system, pos)`, energy changes are evaluated from the changed
+/// positions only; otherwise every move evaluates the total energy twice. Every call passes a copy of the system,
+/// which makes Python energies suitable for prototyping rather than for long runs.
+pub struct PythonEnergy {
+    object: Py<PyAny>,
+    by_pos: bool,
+    error: CallbackError,
+}
+
+impl PythonEnergy {
+    pub fn new(object: &Bound<'_, PyAny>) -> PyResult<PythonEnergy> {
+        if !object.hasattr("energy")? {
+            return Err(PyTypeError::new_err("an energy must be a native energy or an object with energy(system) method"));
+        }
+        Ok(PythonEnergy { object: object.clone().unbind(), by_pos: object.hasattr("energy_by_pos")?,
+            error: CallbackError::default() })
+    }
+
+    /// Calls Python, returning `NaN` in place of an exception
+    fn call(&self, f: impl FnOnce(Python<'_>) -> PyResult<f64>) -> f64 {
+        if self.error.is_set() { return f64::NAN; }
+        return Python::attach(|py| f(py).unwrap_or_else(|e| {
+            self.error.set(e);
+            f64::NAN
+        }));
+    }
+
+    fn call_energy(&self, py: Python<'_>, system: &Py<PyAny>) -> PyResult<f64> {
+        self.object.call_method1(py, "energy", (system,))?.extract(py)
+    }
+
+    fn call_energy_by_pos(&self, py: Python<'_>, system: &Py<PyAny>, pos: usize) -> PyResult<f64> {
+        self.object.call_method1(py, "energy_by_pos", (system, pos))?.extract(py)
+    }
+}
+
+impl Energy<AnySystem> for PythonEnergy {
+    fn energy(&self, system: &AnySystem) -> f64 {
+        self.call(|py| self.call_energy(py, &system_to_python(py, system)?))
+    }
+
+    /// Energy of a single position, or the total energy when the Python object can't evaluate it
+    fn energy_by_pos(&self, system: &AnySystem, pos: usize) -> f64 {
+        if !self.by_pos { return self.energy(system); }
+        self.call(|py| self.call_energy_by_pos(py, &system_to_python(py, system)?, pos))
+    }
+
+    fn delta_energy_by_pos(&self, old_system: &AnySystem, new_system: &AnySystem, pos: usize) -> (f64, f64) {
+        (self.energy_by_pos(old_system, pos), self.energy_by_pos(new_system, pos))
+    }
+
+    fn delta_energy(&self, old_system: &AnySystem, new_system: &AnySystem, changed: &ChangedPositions) -> (f64, f64) {
+        if !self.by_pos { return (self.energy(old_system), self.energy(new_system)); }
+        // --- copy each system to Python just once
+        let mut after = 0.0;
+        let before = self.call(|py| {
+            let (old, new) = (system_to_python(py, old_system)?, system_to_python(py, new_system)?);
+            let mut before = 0.0;
+            for pos in changed.iter() {
+                before += self.call_energy_by_pos(py, &old, pos)?;
+                after += self.call_energy_by_pos(py, &new, pos)?;
+            }
+            Ok(before)
+        });
+        if before.is_nan() { return (f64::NAN, f64::NAN); }
+        (before, after)
+    }
+}
+
+/// An energy prepared for a simulation: a native one works only for a single kind of system
+pub struct EnergyHandle {
+    pub energy: Arc<dyn Energy<AnySystem>>,
+    pub kind: Option<SystemKind>,
+    pub error: CallbackError,
+}
+
+impl EnergyHandle {
+    /// Unwraps a native energy or wraps a Python one
+    pub fn from_python(object: &Bound<'_, PyAny>) -> PyResult<EnergyHandle> {
+        let native = |energy: &Arc<dyn Energy<AnySystem>>, kind| {
+            Ok(EnergyHandle { energy: energy.clone(), kind: Some(kind), error: CallbackError::default() })
+        };
+        if let Ok(e) = object.cast::<PyHardDiskEnergy>() { return native(&e.borrow().energy, SystemKind::Disks); }
+        if let Ok(e) = object.cast::<PyIsingEnergy>() { return native(&e.borrow().energy, SystemKind::Spins); }
+        if let Ok(e) = object.cast::<PyPottsEnergy>() { return native(&e.borrow().energy, SystemKind::Spins); }
+        let energy = PythonEnergy::new(object)?;
+        let error = energy.error.clone();
+        Ok(EnergyHandle { energy: Arc::new(energy), kind: None, error })
+    }
+
+    /// Raises `TypeError` unless this energy can evaluate systems of a given kind
+    pub fn check_kind(&self, kind: SystemKind) -> PyResult<()> {
+        match self.kind {
+            Some(k) if k != kind => Err(PyTypeError::new_err(format!("this energy can't evaluate {}", kind.name()))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Total energy of a Python system object
+    pub fn evaluate(&self, system: &Bound<'_, PyAny>) -> PyResult<f64> {
+        let energy = with_system(system, |s| {
+            self.check_kind(s.kind())?;
+            Ok::<f64, PyErr>(self.energy.energy(s))
+        })??;
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => Ok(energy),
+        }
+    }
+}
+
+/// Disks overlapping closer than `r` repel each other with energy `e_rep`
+#[pyclass(name = "HardDiskEnergy", frozen)]
+pub struct PyHardDiskEnergy {
+    energy: Arc<dyn Energy<AnySystem>>,
+}
+
+#[pymethods]
+impl PyHardDiskEnergy {
+    #[new]
+    #[pyo3(signature = (r, e_rep=10000.0))]
+    pub fn new(r: f64, e_rep: f64) -> Self {
+        PyHardDiskEnergy { energy: Arc::new(NativeEnergy::new(HardDisk::new(r, e_rep))) }
+    }
+
+    pub fn energy(slf: &Bound<'_, Self>, system: &Bound<'_, PyAny>) -> PyResult<f64> {
+        EnergyHandle::from_python(slf.as_any())?.evaluate(system)
+    }
+}
+
+/// Ising model: `-J s_i s_j` for every pair of neighbors and `-h s_i` for every spin; state 0 is spin +1
+#[pyclass(name = "IsingEnergy", frozen)]
+pub struct PyIsingEnergy {
+    energy: Arc<dyn Energy<AnySystem>>,
+}
+
+#[pymethods]
+impl PyIsingEnergy {
+    #[new]
+    #[pyo3(signature = (coupling=1.0, field=0.0))]
+    pub fn new(coupling: f64, field: f64) -> Self {
+        PyIsingEnergy { energy: Arc::new(NativeEnergy::new(IsingEnergy::new(coupling, field))) }
+    }
+
+    pub fn energy(slf: &Bound<'_, Self>, system: &Bound<'_, PyAny>) -> PyResult<f64> {
+        EnergyHandle::from_python(slf.as_any())?.evaluate(system)
+    }
+}
+
+/// Potts model: `-J` for every pair of neighbors in the same state and `-h` for every spin in state 0
+#[pyclass(name = "PottsEnergy", frozen)]
+pub struct PyPottsEnergy {
+    energy: Arc<dyn Energy<AnySystem>>,
+}
+
+#[pymethods]
+impl PyPottsEnergy {
+    #[new]
+    #[pyo3(signature = (coupling=1.0, field=0.0))]
+    pub fn new(coupling: f64, field: f64) -> Self {
+        PyPottsEnergy { energy: Arc::new(NativeEnergy::new(PottsEnergy::new(coupling, field))) }
+    }
+
+    pub fn energy(slf: &Bound<'_, Self>, system: &Bound<'_, PyAny>) -> PyResult<f64> {
+        EnergyHandle::from_python(slf.as_any())?.evaluate(system)
+    }
+}
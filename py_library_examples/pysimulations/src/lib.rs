@@ -0,0 +1,28 @@
+mod energies;
+mod movers;
+mod samplers;
+mod systems;
+
+use pyo3::prelude::*;
+
+pub use energies::*;
+pub use movers::*;
+pub use samplers::*;
+pub use systems::*;
+
+/// Python module `simulations`: Monte Carlo samplers of `simulations_base` with native and Python systems
+#[pymodule]
+fn simulations(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyAcceptanceStatistics>()?;
+    m.add_class::<PyMetropolisCriterion>()?;
+    m.add_class::<PyMCProtocol>()?;
+    m.add_class::<PyAdaptiveMCProtocol>()?;
+    m.add_class::<PyHardDisks>()?;
+    m.add_class::<PySpinLattice>()?;
+    m.add_class::<PyHardDiskEnergy>()?;
+    m.add_class::<PyIsingEnergy>()?;
+    m.add_class::<PyPottsEnergy>()?;
+    m.add_class::<PyDiskMover>()?;
+    m.add_class::<PySpinFlipMover>()?;
+    Ok(())
+}
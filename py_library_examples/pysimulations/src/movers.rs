@@ -0,0 +1,181 @@
+use std::marker::PhantomData;
+
+use pyo3::exceptions::{PyIndexError, PyTypeError};
+use pyo3::prelude::*;
+use rand::Rng;
+
+use disks::hard_disks::DiskMover;
+use simulations_base::{AcceptanceStatistics, ChangedPositions, Mover, RandomStream, SpinFlipMover, System};
+
+use crate::energies::CallbackError;
+use crate::systems::{system_to_python, with_system, AnySystem, AsSystem, SystemKind};
+
+/// Mover of a Rust system, applied to [`AnySystem`]; it doesn't change systems of any other kind
+pub struct NativeMover<S, M> {
+    mover: M,
+    system: PhantomData<fn() -> S>,
+}
+
+impl<S: System, M: Mover<S>> NativeMover<S, M> {
+    pub fn new(mover: M) -> NativeMover<S, M> { NativeMover { mover, system: PhantomData } }
+}
+
+impl<S: System, M: Mover<S>> Mover<AnySystem> for NativeMover<S, M> where AnySystem: AsSystem<S> {
+    fn perturb(&mut self, system: &mut AnySystem, rng: &mut RandomStream) -> ChangedPositions {
+        match AsSystem::<S>::get_mut(system) {
+            Some(s) => self.mover.perturb(s, rng),
+            None => ChangedPositions::List(vec![]),
+        }
+    }
+
+    fn acceptance_statistics(&self) -> AcceptanceStatistics { self.mover.acceptance_statistics() }
+
+    fn add_success(&mut self) { self.mover.add_success(); }
+
+    fn add_failure(&mut self) { self.mover.add_failure(); }
+
+    fn set_acceptance_statistics(&mut self, stats: AcceptanceStatistics) { self.mover.set_acceptance_statistics(stats); }
+
+    fn max_range(&self) -> f64 { self.mover.max_range() }
+
+    fn set_max_range(&mut self, new_val: f64) { self.mover.set_max_range(new_val); }
+
+    fn self_accepting(&self) -> bool { self.mover.self_accepting() }
+
+    fn set_temperature(&mut self, temperature: f64) { self.mover.set_temperature(temperature); }
+}
+
+/// Mover implemented in Python by an object with a `perturb(system)` method.
+///
+/// The method changes the given copy of a system in place and returns the list of changed positions, which are
+/// then copied to the simulated system. If the object has a `max_range` attribute, it's used as the step size,
+/// so an adaptive sampler can tune it; a `temperature` attribute is set to the temperature of the sampler.
+/// If the object has a `seed` attribute, it's set before every move to a number drawn from the random stream
+/// of the sampler: a mover that seeds its generator with it is reproducible for a seeded sampler.
+/// Otherwise Python movers draw random numbers from their own generators.
+pub struct PythonMover {
+    object: Py<PyAny>,
+    max_range: f64,
+    stats: AcceptanceStatistics,
+    error: CallbackError,
+}
+
+impl PythonMover {
+    pub fn new(object: &Bound<'_, PyAny>) -> PyResult<PythonMover> {
+        if !object.hasattr("perturb")? {
+            return Err(PyTypeError::new_err("a mover must be a native mover or an object with perturb(system) method"));
+        }
+        Ok(PythonMover { object: object.clone().unbind(), max_range: 1.0, stats: AcceptanceStatistics::default(),
+            error: CallbackError::default() })
+    }
+
+    fn call_perturb(&self, py: Python<'_>, system: &mut AnySystem, rng: &mut RandomStream) -> PyResult<Vec<usize>> {
+        if self.object.bind(py).hasattr("seed")? { self.object.setattr(py, "seed", rng.gen::<u64>())?; }
+        let copy = system_to_python(py, system)?;
+        let changed: Vec<usize> = self.object.call_method1(py, "perturb", (copy.clone_ref(py),))?.extract(py)?;
+        let size = system.size();
+        with_system(copy.bind(py), |moved| {
+            for pos in changed.iter() {
+                if *pos >= size {
+                    return Err(PyIndexError::new_err(format!("mover changed position {} of {}", pos, size)));
+                }
+                system.copy_from(*pos, moved);
+            }
+            Ok(())
+        })??;
+        Ok(changed)
+    }
+}
+
+impl Mover<AnySystem> for PythonMover {
+    fn perturb(&mut self, system: &mut AnySystem, rng: &mut RandomStream) -> ChangedPositions {
+        if self.error.is_set() { return ChangedPositions::List(vec![]); }
+        match Python::attach(|py| self.call_perturb(py, system, rng)) {
+            Ok(changed) => ChangedPositions::List(changed),
+            Err(e) => {
+                self.error.set(e);
+                ChangedPositions::List(vec![])
+            }
+        }
+    }
+
+    fn acceptance_statistics(&self) -> AcceptanceStatistics { self.stats.clone() }
+
+    fn add_success(&mut self) { self.stats.n_succ += 1; }
+
+    fn add_failure(&mut self) { self.stats.n_failed += 1; }
+
+    fn set_acceptance_statistics(&mut self, stats: AcceptanceStatistics) { self.stats = stats; }
+
+    fn max_range(&self) -> f64 {
+        Python::attach(|py| self.object.getattr(py, "max_range").and_then(|r| r.extract(py)).unwrap_or(self.max_range))
+    }
+
+    fn set_max_range(&mut self, new_val: f64) {
+        self.max_range = new_val;
+        Python::attach(|py| {
+            if self.object.bind(py).hasattr("max_range").unwrap_or(false) {
+                self.object.setattr(py, "max_range", new_val).ok();
+            }
+        });
+    }
+
+    fn set_temperature(&mut self, temperature: f64) {
+        Python::attach(|py| {
+            if self.object.bind(py).hasattr("temperature").unwrap_or(false) {
+                self.object.setattr(py, "temperature", temperature).ok();
+            }
+        });
+    }
+}
+
+/// A mover prepared for a sampler: a native one works only for a single kind of system
+pub struct MoverHandle {
+    pub mover: Box<dyn Mover<AnySystem>>,
+    pub kind: Option<SystemKind>,
+    pub error: CallbackError,
+}
+
+impl MoverHandle {
+    /// Creates a new native mover from its Python description or wraps a Python mover
+    pub fn from_python(object: &Bound<'_, PyAny>) -> PyResult<MoverHandle> {
+        let native = |mover: Box<dyn Mover<AnySystem>>, kind| {
+            Ok(MoverHandle { mover, kind: Some(kind), error: CallbackError::default() })
+        };
+        if let Ok(m) = object.cast::<PyDiskMover>() {
+            return native(Box::new(NativeMover::new(DiskMover::new(m.borrow().max_range))), SystemKind::Disks);
+        }
+        if object.cast::<PySpinFlipMover>().is_ok() {
+            return native(Box::new(NativeMover::new(SpinFlipMover::new())), SystemKind::Spins);
+        }
+        let mover = PythonMover::new(object)?;
+        let error = mover.error.clone();
+        Ok(MoverHandle { mover: Box::new(mover), kind: None, error })
+    }
+}
+
+/// Moves a randomly selected disk by up to `max_range` along each axis
+#[pyclass(name = "DiskMover")]
+pub struct PyDiskMover {
+    #[pyo3(get, set)]
+    pub max_range: f64,
+}
+
+#[pymethods]
+impl PyDiskMover {
+    #[new]
+    pub fn new(max_range: f64) -> Self { PyDiskMover { max_range } }
+
+    pub fn __repr__(&self) -> String { format!("DiskMover(max_range={})", self.max_range) }
+}
+
+/// Changes a randomly selected spin to a different, randomly selected state
+#[pyclass(name = "SpinFlipMover", frozen)]
+#[derive(Default)]
+pub struct PySpinFlipMover;
+
+#[pymethods]
+impl PySpinFlipMover {
+    #[new]
+    pub fn new() -> Self { PySpinFlipMover }
+}
@@ -0,0 +1,238 @@
+use pyo3::exceptions::{PyIndexError, PyRuntimeError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+
+use simulations_base::{AcceptanceStatistics, AdaptiveMCProtocol, MCProtocol, MetropolisCriterion, MoversSetSampler,
+                       RandomStreams, Sampler, SimulationError, ValidationMode};
+
+use crate::energies::{CallbackError, EnergyHandle};
+use crate::movers::MoverHandle;
+use crate::systems::{with_system, AnySystem, SystemKind};
+
+fn to_py_err(error: SimulationError) -> PyErr {
+    match error {
+        SimulationError::MoverIndex { .. } | SimulationError::TermIndex { .. } =>
+            PyIndexError::new_err(error.to_string()),
+        SimulationError::InvalidParameter(_) | SimulationError::Config(_) => PyValueError::new_err(error.to_string()),
+        _ => PyRuntimeError::new_err(error.to_string()),
+    }
+}
+
+/// Counts of accepted and rejected moves
+#[pyclass(name = "AcceptanceStatistics", frozen)]
+#[derive(Clone)]
+pub struct PyAcceptanceStatistics {
+    stats: AcceptanceStatistics,
+}
+
+#[pymethods]
+impl PyAcceptanceStatistics {
+    #[new]
+    #[pyo3(signature = (n_succ=0, n_failed=0))]
+    pub fn new(n_succ: i32, n_failed: i32) -> Self { PyAcceptanceStatistics { stats: AcceptanceStatistics { n_succ, n_failed } } }
+
+    #[getter]
+    pub fn n_succ(&self) -> i32 { self.stats.n_succ }
+
+    #[getter]
+    pub fn n_failed(&self) -> i32 { self.stats.n_failed }
+
+    pub fn success_rate(&self) -> f64 { self.stats.success_rate() }
+
+    /// Success rate of the moves made since the `previous` statistics were taken
+    pub fn recent_success_rate(&self, previous: PyRef<'_, Self>) -> f64 { self.stats.recent_success_rate(&previous.stats) }
+
+    pub fn __repr__(&self) -> String {
+        format!("AcceptanceStatistics(n_succ={}, n_failed={})", self.stats.n_succ, self.stats.n_failed)
+    }
+}
+
+/// Metropolis criterion at a given temperature
+#[pyclass(name = "MetropolisCriterion")]
+pub struct PyMetropolisCriterion {
+    #[pyo3(get, set)]
+    pub temperature: f64,
+}
+
+#[pymethods]
+impl PyMetropolisCriterion {
+    #[new]
+    pub fn new(temperature: f64) -> Self { PyMetropolisCriterion { temperature } }
+
+    pub fn __repr__(&self) -> String { format!("MetropolisCriterion(temperature={})", self.temperature) }
+}
+
+/// A sampler together with what it needs to know about its movers to run from Python
+pub struct SamplerCore<P> {
+    sampler: P,
+    mover_kinds: Vec<Option<SystemKind>>,
+    errors: Vec<CallbackError>,
+}
+
+impl<P: MoversSetSampler<MetropolisCriterion, AnySystem>> SamplerCore<P> {
+    fn add_mover(&mut self, mover: &Bound<'_, PyAny>) -> PyResult<()> {
+        let handle = MoverHandle::from_python(mover)?;
+        self.sampler.add_mover(handle.mover);
+        self.mover_kinds.push(handle.kind);
+        self.errors.push(handle.error);
+        Ok(())
+    }
+
+    /// Runs sweeps, then raises the first exception of a Python energy or mover, if any
+    fn make_sweeps(&mut self, n: usize, system: &Bound<'_, PyAny>, energy: &Bound<'_, PyAny>) -> PyResult<()> {
+        let energy = EnergyHandle::from_python(energy)?;
+        with_system(system, |s| {
+            energy.check_kind(s.kind())?;
+            if self.mover_kinds.iter().flatten().any(|k| *k != s.kind()) {
+                return Err(PyTypeError::new_err(format!("a mover of this sampler can't move {}", s.kind().name())));
+            }
+            self.sampler.make_sweeps(n, s, energy.energy.as_ref()).map_err(to_py_err)
+        })??;
+        for error in std::iter::once(&energy.error).chain(self.errors.iter()) {
+            if let Some(e) = error.take() { return Err(e); }
+        }
+        Ok(())
+    }
+
+    fn acceptance_statistics(&mut self, which_one: usize) -> PyResult<PyAcceptanceStatistics> {
+        let mover = self.sampler.get_mover(which_one).map_err(to_py_err)?;
+        Ok(PyAcceptanceStatistics { stats: mover.acceptance_statistics() })
+    }
+
+    fn max_range(&mut self, which_one: usize) -> PyResult<f64> {
+        Ok(self.sampler.get_mover(which_one).map_err(to_py_err)?.max_range())
+    }
+
+    fn set_max_range(&mut self, which_one: usize, max_range: f64) -> PyResult<()> {
+        self.sampler.get_mover(which_one).map_err(to_py_err)?.set_max_range(max_range);
+        Ok(())
+    }
+}
+
+type Protocol = MCProtocol<MetropolisCriterion, AnySystem>;
+
+/// Monte Carlo sampler; works with any system, energy and movers of this module and with Python energies and movers.
+///
+/// Energy consistency isn't validated, as it would evaluate the total energy of a system twice for every move.
+#[pyclass(name = "MCProtocol", unsendable)]
+pub struct PyMCProtocol {
+    /// `None` once the sampler has been taken over by an `AdaptiveMCProtocol`
+    core: Option<SamplerCore<Protocol>>,
+}
+
+impl PyMCProtocol {
+    fn core(&mut self) -> PyResult<&mut SamplerCore<Protocol>> {
+        self.core.as_mut().ok_or_else(|| PyRuntimeError::new_err("this sampler is used by an AdaptiveMCProtocol"))
+    }
+}
+
+#[pymethods]
+impl PyMCProtocol {
+    /// Creates a sampler; without a seed its random streams are drawn from the entropy source
+    #[new]
+    #[pyo3(signature = (criterion, seed=None))]
+    pub fn new(criterion: PyRef<'_, PyMetropolisCriterion>, seed: Option<u64>) -> Self {
+        let streams = seed.map_or_else(RandomStreams::from_entropy, RandomStreams::new);
+        let mut sampler = MCProtocol::with_streams(MetropolisCriterion::new(criterion.temperature), streams);
+        sampler.validation = ValidationMode::Disabled;
+        PyMCProtocol { core: Some(SamplerCore { sampler, mover_kinds: vec![], errors: vec![] }) }
+    }
+
+    /// Adds a native mover, e.g. `DiskMover`, or any object with a `perturb(system)` method
+    pub fn add_mover(&mut self, mover: &Bound<'_, PyAny>) -> PyResult<()> { self.core()?.add_mover(mover) }
+
+    /// Makes `n` sweeps of a system with a native energy or any object with an `energy(system)` method
+    pub fn make_sweeps(&mut self, n: usize, system: &Bound<'_, PyAny>, energy: &Bound<'_, PyAny>) -> PyResult<()> {
+        self.core()?.make_sweeps(n, system, energy)
+    }
+
+    #[getter]
+    pub fn temperature(&mut self) -> PyResult<f64> { Ok(self.core()?.sampler.acceptance_criterion.temperature) }
+
+    #[setter]
+    pub fn set_temperature(&mut self, temperature: f64) -> PyResult<()> {
+        Sampler::set_temperature(&mut self.core()?.sampler, temperature);
+        Ok(())
+    }
+
+    pub fn count_movers(&mut self) -> PyResult<usize> { Ok(self.core()?.mover_kinds.len()) }
+
+    pub fn acceptance_statistics(&mut self, which_one: usize) -> PyResult<PyAcceptanceStatistics> {
+        self.core()?.acceptance_statistics(which_one)
+    }
+
+    pub fn max_range(&mut self, which_one: usize) -> PyResult<f64> { self.core()?.max_range(which_one) }
+
+    pub fn set_max_range(&mut self, which_one: usize, max_range: f64) -> PyResult<()> {
+        self.core()?.set_max_range(which_one, max_range)
+    }
+}
+
+type Adaptive = AdaptiveMCProtocol<MetropolisCriterion, AnySystem>;
+
+/// Sampler that tunes the step size of every mover of an `MCProtocol` towards a target success rate.
+///
+/// It takes over the given `MCProtocol`, which can't be used on its own anymore.
+#[pyclass(name = "AdaptiveMCProtocol", unsendable)]
+pub struct PyAdaptiveMCProtocol {
+    core: SamplerCore<Adaptive>,
+}
+
+#[pymethods]
+impl PyAdaptiveMCProtocol {
+    #[new]
+    pub fn new(mut sampler: PyRefMut<'_, PyMCProtocol>) -> PyResult<Self> {
+        let core = sampler.core.take()
+            .ok_or_else(|| PyRuntimeError::new_err("this sampler is already used by an AdaptiveMCProtocol"))?;
+        let adaptive = AdaptiveMCProtocol::new(Box::new(core.sampler));
+        Ok(PyAdaptiveMCProtocol { core: SamplerCore { sampler: adaptive, mover_kinds: core.mover_kinds,
+            errors: core.errors } })
+    }
+
+    pub fn add_mover(&mut self, mover: &Bound<'_, PyAny>) -> PyResult<()> { self.core.add_mover(mover) }
+
+    pub fn make_sweeps(&mut self, n: usize, system: &Bound<'_, PyAny>, energy: &Bound<'_, PyAny>) -> PyResult<()> {
+        self.core.make_sweeps(n, system, energy)
+    }
+
+    #[getter]
+    pub fn temperature(&self) -> f64 { Sampler::temperature(&self.core.sampler) }
+
+    #[setter]
+    pub fn set_temperature(&mut self, temperature: f64) { Sampler::set_temperature(&mut self.core.sampler, temperature); }
+
+    #[getter]
+    pub fn target_rate(&self) -> f64 { self.core.sampler.target_rate }
+
+    #[setter]
+    pub fn set_target_rate(&mut self, rate: f64) { self.core.sampler.target_rate = rate; }
+
+    /// Number of first sweeps during which step sizes are adapted; `None` adapts them all the time
+    #[getter]
+    pub fn adaptation_sweeps(&self) -> Option<usize> { self.core.sampler.adaptation_sweeps }
+
+    #[setter]
+    pub fn set_adaptation_sweeps(&mut self, n: Option<usize>) { self.core.sampler.adaptation_sweeps = n; }
+
+    /// Sets the target success rate of a single mover, which overrides `target_rate` for that mover
+    pub fn set_mover_target_rate(&mut self, which_one: usize, rate: f64) -> PyResult<()> {
+        self.core.sampler.set_mover_target_rate(which_one, rate).map_err(to_py_err)
+    }
+
+    pub fn mover_target_rate(&self, which_one: usize) -> f64 { self.core.sampler.mover_target_rate(which_one) }
+
+    pub fn count_sweeps(&self) -> usize { self.core.sampler.count_sweeps() }
+
+    pub fn is_frozen(&self) -> bool { self.core.sampler.is_frozen() }
+
+    pub fn count_movers(&self) -> usize { self.core.mover_kinds.len() }
+
+    pub fn acceptance_statistics(&mut self, which_one: usize) -> PyResult<PyAcceptanceStatistics> {
+        self.core.acceptance_statistics(which_one)
+    }
+
+    pub fn max_range(&mut self, which_one: usize) -> PyResult<f64> { self.core.max_range(which_one) }
+
+    pub fn set_max_range(&mut self, which_one: usize, max_range: f64) -> PyResult<()> {
+        self.core.set_max_range(which_one, max_range)
+    }
+}
@@ -0,0 +1,236 @@
+use numpy::ndarray::Array2;
+use numpy::{IntoPyArray, PyArray1, PyArray2, PyReadonlyArray1, PyReadonlyArray2, PyUntypedArrayMethods};
+use pyo3::exceptions::{PyIndexError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+
+use disks::vec2::{square_grid_atoms, Coordinates};
+use simulations_base::{RandomStreams, SpinLattice, System};
+
+/// Kinds of systems simulated natively by this module
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SystemKind { Disks, Spins }
+
+impl SystemKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SystemKind::Disks => "HardDisks",
+            SystemKind::Spins => "SpinLattice",
+        }
+    }
+}
+
+/// Any of the systems available from Python.
+///
+/// All the samplers of this module work on this type, so a single sampler class serves every system;
+/// native energies and movers unwrap the system they were written for.
+#[derive(Clone)]
+pub enum AnySystem {
+    Disks(Coordinates),
+    Spins(SpinLattice),
+}
+
+impl AnySystem {
+    pub fn kind(&self) -> SystemKind {
+        match self {
+            AnySystem::Disks(_) => SystemKind::Disks,
+            AnySystem::Spins(_) => SystemKind::Spins,
+        }
+    }
+}
+
+impl System for AnySystem {
+    fn size(&self) -> usize {
+        match self {
+            AnySystem::Disks(c) => c.size(),
+            AnySystem::Spins(s) => s.size(),
+        }
+    }
+
+    fn copy_from(&mut self, i: usize, rhs: &Self) {
+        match (self, rhs) {
+            (AnySystem::Disks(c), AnySystem::Disks(rhs)) => c.copy_from(i, rhs),
+            (AnySystem::Spins(s), AnySystem::Spins(rhs)) => s.copy_from(i, rhs),
+            _ => panic!("can't copy a position between systems of different kinds"),
+        }
+    }
+}
+
+/// Gives access to the concrete system held by [`AnySystem`], if it's of the right kind
+pub trait AsSystem<S> {
+    fn get(&self) -> Option<&S>;
+    fn get_mut(&mut self) -> Option<&mut S>;
+}
+
+impl AsSystem<Coordinates> for AnySystem {
+    fn get(&self) -> Option<&Coordinates> { if let AnySystem::Disks(c) = self { Some(c) } else { None } }
+
+    fn get_mut(&mut self) -> Option<&mut Coordinates> { if let AnySystem::Disks(c) = self { Some(c) } else { None } }
+}
+
+impl AsSystem<SpinLattice> for AnySystem {
+    fn get(&self) -> Option<&SpinLattice> { if let AnySystem::Spins(s) = self { Some(s) } else { None } }
+
+    fn get_mut(&mut self) -> Option<&mut SpinLattice> { if let AnySystem::Spins(s) = self { Some(s) } else { None } }
+}
+
+/// Calls a function on the system held by a Python `HardDisks` or `SpinLattice` object
+pub fn with_system<R>(object: &Bound<'_, PyAny>, f: impl FnOnce(&mut AnySystem) -> R) -> PyResult<R> {
+    if let Ok(disks) = object.cast::<PyHardDisks>() { return Ok(f(&mut disks.try_borrow_mut()?.system)); }
+    if let Ok(spins) = object.cast::<PySpinLattice>() { return Ok(f(&mut spins.try_borrow_mut()?.system)); }
+    Err(PyTypeError::new_err(format!("expected HardDisks or SpinLattice, got {}", object.get_type().name()?)))
+}
+
+/// Creates a new Python object holding a copy of a system
+pub fn system_to_python(py: Python<'_>, system: &AnySystem) -> PyResult<Py<PyAny>> {
+    let object = match system {
+        AnySystem::Disks(_) => Bound::new(py, PyHardDisks { system: system.clone() })?.into_any(),
+        AnySystem::Spins(_) => Bound::new(py, PySpinLattice { system: system.clone() })?.into_any(),
+    };
+    Ok(object.unbind())
+}
+
+fn check_index(i: usize, size: usize) -> PyResult<()> {
+    if i >= size { return Err(PyIndexError::new_err(format!("index {} out of range for {} positions", i, size))); }
+    Ok(())
+}
+
+/// Disks in a square box with periodic boundaries
+#[pyclass(name = "HardDisks")]
+pub struct PyHardDisks {
+    pub system: AnySystem,
+}
+
+impl PyHardDisks {
+    fn coords(&self) -> &Coordinates { self.system.get().expect("HardDisks holds disks") }
+
+    fn coords_mut(&mut self) -> &mut Coordinates { self.system.get_mut().expect("HardDisks holds disks") }
+}
+
+#[pymethods]
+impl PyHardDisks {
+    /// Places `n` disks on a square grid filling a box of a given side
+    #[new]
+    pub fn new(n: usize, box_len: f64) -> PyResult<Self> {
+        if box_len <= 0.0 { return Err(PyValueError::new_err("box length must be positive")); }
+        let mut coords = Coordinates::new(n);
+        coords.set_box_len(box_len);
+        square_grid_atoms(&mut coords);
+        Ok(PyHardDisks { system: AnySystem::Disks(coords) })
+    }
+
+    pub fn __len__(&self) -> usize { self.coords().size() }
+
+    #[getter]
+    pub fn box_len(&self) -> f64 { self.coords().box_len() }
+
+    pub fn x(&self, i: usize) -> PyResult<f64> {
+        check_index(i, self.coords().size())?;
+        Ok(self.coords().x(i))
+    }
+
+    pub fn y(&self, i: usize) -> PyResult<f64> {
+        check_index(i, self.coords().size())?;
+        Ok(self.coords().y(i))
+    }
+
+    /// Moves the `i`-th disk, wrapping the new position into the box
+    pub fn set(&mut self, i: usize, x: f64, y: f64) -> PyResult<()> {
+        check_index(i, self.coords().size())?;
+        self.coords_mut().set(i, x, y);
+        Ok(())
+    }
+
+    /// Positions of all the disks as an `(n, 2)` array
+    pub fn coordinates<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray2<f64>> {
+        let coords = self.coords();
+        Array2::from_shape_fn((coords.size(), 2), |(i, k)| if k == 0 { coords.x(i) } else { coords.y(i) })
+            .into_pyarray(py)
+    }
+
+    /// Sets the positions of all the disks from an `(n, 2)` array
+    pub fn set_coordinates(&mut self, xy: PyReadonlyArray2<'_, f64>) -> PyResult<()> {
+        let n = self.coords().size();
+        if xy.shape() != [n, 2] {
+            return Err(PyValueError::new_err(format!("expected an array of shape ({}, 2), got {:?}", n, xy.shape())));
+        }
+        let xy = xy.as_array();
+        for i in 0..n { self.coords_mut().set(i, xy[[i, 0]], xy[[i, 1]]); }
+        Ok(())
+    }
+
+    pub fn __repr__(&self) -> String { format!("HardDisks(n={}, box_len={})", self.coords().size(), self.box_len()) }
+}
+
+/// Spins of a q-state Potts model on a periodic hypercubic lattice; a one-dimensional lattice is a Potts sequence
+#[pyclass(name = "SpinLattice")]
+pub struct PySpinLattice {
+    pub system: AnySystem,
+}
+
+impl PySpinLattice {
+    fn spins(&self) -> &SpinLattice { self.system.get().expect("SpinLattice holds spins") }
+
+    fn spins_mut(&mut self) -> &mut SpinLattice { self.system.get_mut().expect("SpinLattice holds spins") }
+}
+
+#[pymethods]
+impl PySpinLattice {
+    /// Creates a lattice of given dimensions with every spin in state 0
+    #[new]
+    #[pyo3(signature = (dims, q=2))]
+    pub fn new(dims: Vec<usize>, q: u8) -> PyResult<Self> {
+        let lattice = SpinLattice::new(&dims, q).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(PySpinLattice { system: AnySystem::Spins(lattice) })
+    }
+
+    pub fn __len__(&self) -> usize { self.spins().size() }
+
+    #[getter]
+    pub fn dims(&self) -> Vec<usize> { self.spins().dims().clone() }
+
+    #[getter]
+    pub fn q(&self) -> u8 { self.spins().q() }
+
+    pub fn spin(&self, i: usize) -> PyResult<u8> {
+        check_index(i, self.spins().size())?;
+        Ok(self.spins().spin(i))
+    }
+
+    pub fn set_spin(&mut self, i: usize, state: u8) -> PyResult<()> {
+        check_index(i, self.spins().size())?;
+        if state >= self.spins().q() {
+            return Err(PyValueError::new_err(format!("state {} out of range for q = {}", state, self.spins().q())));
+        }
+        self.spins_mut().set_spin(i, state);
+        Ok(())
+    }
+
+    /// States of all the spins, in row-major order
+    pub fn states<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray1<u8>> {
+        let spins = self.spins();
+        PyArray1::from_vec(py, (0..spins.size()).map(|i| spins.spin(i)).collect())
+    }
+
+    pub fn set_states(&mut self, states: PyReadonlyArray1<'_, u8>) -> PyResult<()> {
+        let (n, q) = (self.spins().size(), self.spins().q());
+        let states = states.as_array();
+        if states.len() != n {
+            return Err(PyValueError::new_err(format!("expected {} states, got {}", n, states.len())));
+        }
+        if states.iter().any(|s| *s >= q) { return Err(PyValueError::new_err(format!("states must be below {}", q))); }
+        for (i, s) in states.iter().enumerate() { self.spins_mut().set_spin(i, *s); }
+        Ok(())
+    }
+
+    /// Sets every spin to a random state; without a seed the state is drawn from the entropy source
+    #[pyo3(signature = (seed=None))]
+    pub fn randomize(&mut self, seed: Option<u64>) {
+        let mut streams = seed.map_or_else(RandomStreams::from_entropy, RandomStreams::new);
+        self.spins_mut().randomize(&mut streams.next_stream());
+    }
+
+    /// Order parameter `(q n_0 / N - 1) / (q - 1)`, where `n_0` counts spins in state 0; the average Ising spin
+    pub fn magnetization(&self) -> f64 { self.spins().magnetization() }
+
+    pub fn __repr__(&self) -> String { format!("SpinLattice(dims={:?}, q={})", self.spins().dims(), self.spins().q()) }
+}
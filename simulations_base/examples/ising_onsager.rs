@@ -0,0 +1,99 @@
+use std::env;
+use std::sync::Arc;
+
+use simulations_base::{ising_critical_temperature, onsager_energy, AdaptiveMCProtocol, Energy, IsingEnergy,
+                       MCProtocol, MetropolisCriterion, MoversSet, MoversSetSampler, RandomStreams, SpinFlipMover,
+                       SpinLattice, SwendsenWangMover, System, TimeSeries, UndoMCProtocol, ValidationMode, WolffMover};
+
+const L: usize = 32;
+const BURN_IN: usize = 500;
+const N_SWEEPS: usize = 5000;
+
+type IsingSampler = Box<dyn MoversSetSampler<MetropolisCriterion, SpinLattice>>;
+
+/// Energy per spin and the Binder cumulant `1 - <m^4> / 3 <m^2>^2` sampled by a given sampler
+fn sample(sampler: &mut dyn MoversSetSampler<MetropolisCriterion, SpinLattice>, system: &mut SpinLattice,
+          energy: &IsingEnergy) -> (TimeSeries, f64) {
+    sampler.make_sweeps(BURN_IN, system, energy).unwrap();
+    let mut en = TimeSeries::new("energy");
+    let (mut m2, mut m4) = (0.0, 0.0);
+    for _ in 0..N_SWEEPS {
+        sampler.make_sweeps(1, system, energy).unwrap();
+        en.push(energy.energy(system) / system.size() as f64);
+        let m = system.magnetization();
+        m2 += m * m;
+        m4 += m * m * m * m;
+    }
+    m2 /= N_SWEEPS as f64;
+    m4 /= N_SWEEPS as f64;
+    return (en, 1.0 - m4 / (3.0 * m2 * m2));
+}
+
+/// Validates samplers against exact results for the two-dimensional Ising model.
+///
+/// The energy per spin of a 32 x 32 lattice sampled by every sampler is compared with Onsager's solution, which
+/// holds for the infinite lattice (the finite-size difference is small except close to the critical temperature).
+/// Then the Binder cumulant at the critical temperature is computed for a few lattice sizes; its values should be
+/// close to each other and to the universal value of about 0.611.
+///
+/// Run with: cargo run --release --example ising_onsager [seed]
+pub fn main() {
+    let mut streams = match env::args().nth(1) {
+        Some(seed) => RandomStreams::new(seed.parse::<u64>().unwrap()),
+        None => RandomStreams::from_entropy()
+    };
+    let energy = IsingEnergy::new(1.0, 0.0);
+    let coupling = Arc::new(energy.clone());
+    let tc = ising_critical_temperature();
+
+    println!("{:>8} {:>14} {:>10} {:>8} {:>10} {:>8}", "T", "sampler", "energy", "+/-", "exact", "dev/err");
+    for temperature in [1.5, 2.0, tc, 2.5, 3.0] {
+        let n = (L * L) as f64;
+        let samplers: Vec<(&str, IsingSampler)> = vec![
+            ("metropolis", {
+                let mut s = MCProtocol::with_streams(MetropolisCriterion::new(temperature), streams.split());
+                s.validation = ValidationMode::Disabled;
+                s.add_mover(Box::new(SpinFlipMover::new()));
+                Box::new(s)
+            }),
+            ("adaptive", {
+                let mut s = MCProtocol::with_streams(MetropolisCriterion::new(temperature), streams.split());
+                s.validation = ValidationMode::Disabled;
+                s.add_mover(Box::new(SpinFlipMover::new()));
+                Box::new(AdaptiveMCProtocol::new(Box::new(s)))
+            }),
+            ("undo", {
+                let mut s = UndoMCProtocol::with_streams(MetropolisCriterion::new(temperature), streams.split());
+                s.validation = ValidationMode::Disabled;
+                s.add_mover(Box::new(SpinFlipMover::new()));
+                Box::new(s)
+            }),
+            ("wolff", {
+                let mut s = MCProtocol::with_streams(MetropolisCriterion::new(temperature), streams.split());
+                s.add_weighted_mover(Box::new(WolffMover::new(coupling.clone(), temperature).unwrap()), 10.0 / n).unwrap();
+                Box::new(s)
+            }),
+            ("swendsen_wang", {
+                let mut s = MCProtocol::with_streams(MetropolisCriterion::new(temperature), streams.split());
+                s.add_weighted_mover(Box::new(SwendsenWangMover::new(coupling.clone(), temperature).unwrap()), 1.0 / n).unwrap();
+                Box::new(s)
+            }),
+        ];
+        let exact = onsager_energy(temperature);
+        for (name, mut sampler) in samplers {
+            let mut system = SpinLattice::new(&[L, L], 2).unwrap();
+            let (en, _) = sample(sampler.as_mut(), &mut system, &energy);
+            println!("{:>8.4} {:>14} {:>10.5} {:>8.5} {:>10.5} {:>8.2}", temperature, name, en.mean(),
+                     en.standard_error(), exact, (en.mean() - exact) / en.standard_error());
+        }
+    }
+
+    println!("\nBinder cumulant at T_c = {:.5}", tc);
+    for l in [8, 16, 32] {
+        let mut sampler = MCProtocol::with_streams(MetropolisCriterion::new(tc), streams.split());
+        sampler.add_weighted_mover(Box::new(WolffMover::new(coupling.clone(), tc).unwrap()), 10.0 / (l * l) as f64).unwrap();
+        let mut system = SpinLattice::new(&[l, l], 2).unwrap();
+        let (_, binder) = sample(&mut sampler, &mut system, &energy);
+        println!("{:>4} {:>8.4}", l, binder);
+    }
+}
@@ -0,0 +1,252 @@
+use std::io;
+use std::io::Write;
+use std::ops::Range;
+
+use crate::{out_writer, AcceptanceCriterion, AcceptanceStatistics, AdaptiveMCProtocolState, Energy, EnergyInconsistency,
+            Mover, MoversSet, MoversSetSampler, Sampler, SamplerCheckpoint, SamplerState, SimulationError, System};
+use crate::checkpoint::{invalid_state, wrong_sampler};
+use crate::error::check_mover_index;
+
+/// Decides how [`AdaptiveMCProtocol`] changes the step size of a mover.
+pub trait AdaptationStrategy: Send {
+    /// Returns a new step size of a mover.
+    ///
+    /// `rate` is the success rate of the mover observed since its previous update, while `update` counts
+    /// the updates of that mover made so far, starting from 0.
+    fn new_range(&self, range: f64, rate: f64, target_rate: f64, update: usize) -> f64;
+}
+
+/// Multiplies or divides the step size by `factor` when the success rate falls outside `target_rate ± tolerance`
+pub struct DeadBandAdaptation {
+    pub factor: f64,
+    pub tolerance: f64,
+}
+
+impl DeadBandAdaptation {
+    pub fn new(factor: f64, tolerance: f64) -> DeadBandAdaptation { DeadBandAdaptation { factor, tolerance } }
+}
+
+impl Default for DeadBandAdaptation {
+    fn default() -> Self { DeadBandAdaptation::new(0.95, 0.05) }
+}
+
+impl AdaptationStrategy for DeadBandAdaptation {
+    fn new_range(&self, range: f64, rate: f64, target_rate: f64, _update: usize) -> f64 {
+        if rate < target_rate - self.tolerance { return range * self.factor; }
+        if rate > target_rate + self.tolerance { return range / self.factor; }
+        return range;
+    }
+}
+
+/// Robbins-Monro stochastic approximation of the step size giving the target rate.
+///
+/// The logarithm of the step size is shifted by `gain * (rate - target_rate) / (update + 1)^decay`. These shifts
+/// decrease with every update, so the step size converges instead of fluctuating around its best value;
+/// `decay` should be larger than 0.5 and not larger than 1.0.
+pub struct RobbinsMonroAdaptation {
+    pub gain: f64,
+    pub decay: f64,
+}
+
+impl RobbinsMonroAdaptation {
+    pub fn new(gain: f64, decay: f64) -> RobbinsMonroAdaptation { RobbinsMonroAdaptation { gain, decay } }
+}
+
+impl Default for RobbinsMonroAdaptation {
+    fn default() -> Self { RobbinsMonroAdaptation::new(1.0, 0.6) }
+}
+
+impl AdaptationStrategy for RobbinsMonroAdaptation {
+    fn new_range(&self, range: f64, rate: f64, target_rate: f64, update: usize) -> f64 {
+        let gain = self.gain / ((update + 1) as f64).powf(self.decay);
+        return range * (gain * (rate - target_rate)).exp();
+    }
+}
+
+/// A single update of a step size made by [`AdaptiveMCProtocol`]
+#[derive(Clone, Debug)]
+pub struct AdaptationRecord {
+    /// number of sweeps made so far, including the sweeps the success rate was measured on
+    pub sweep: usize,
+    pub mover: usize,
+    pub success_rate: f64,
+    pub target_rate: f64,
+    pub old_range: f64,
+    pub new_range: f64,
+}
+
+/// Adjusts the step size of every mover of a sampler so that its success rate stays close to a target rate.
+///
+/// Step sizes are updated after every call of [`make_sweeps()`](Sampler::make_sweeps), by
+/// a [`DeadBandAdaptation`] unless another [`AdaptationStrategy`] is set; a mover that hasn't been called since
+/// the previous update keeps its step size. The step size of a mover may change between 0.2 and 5.0 times its value
+/// at the first sweep made by that mover. Every mover aims at `target_rate` unless it has its own target,
+/// given by [`set_mover_target_rate()`](AdaptiveMCProtocol::set_mover_target_rate).
+///
+/// When `adaptation_sweeps` is set, step sizes are adapted only during that many first sweeps and then frozen,
+/// so that the statistics of the production run are not biased by ongoing tuning. Every update of a step size
+/// is recorded in the [`adaptation_log()`](AdaptiveMCProtocol::adaptation_log).
+pub struct AdaptiveMCProtocol<T: AcceptanceCriterion, S: System> {
+    pub target_rate: f64,
+    pub adaptation_sweeps: Option<usize>,
+    sampler: Box<dyn MoversSetSampler<T, S>>,
+    strategy: Box<dyn AdaptationStrategy>,
+    mover_target_rates: Vec<Option<f64>>,
+    allowed_ranges: Vec<Range<f64>>,
+    n_updates: Vec<usize>,
+    n_sweeps: usize,
+    log: Vec<AdaptationRecord>,
+}
+
+impl<T: AcceptanceCriterion, S: System> AdaptiveMCProtocol<T, S> {
+    pub fn new(sampler: Box<dyn MoversSetSampler<T, S>>) -> AdaptiveMCProtocol<T, S> {
+        let out = AdaptiveMCProtocol { target_rate: 0.4, adaptation_sweeps: None, sampler,
+            strategy: Box::new(DeadBandAdaptation::default()), mover_target_rates: vec![], allowed_ranges: vec![],
+            n_updates: vec![], n_sweeps: 0, log: vec![] };
+        return out;
+    }
+
+    pub fn set_strategy(&mut self, strategy: Box<dyn AdaptationStrategy>) { self.strategy = strategy; }
+
+    /// Sets the target success rate of a single mover, which overrides `target_rate` for that mover
+    pub fn set_mover_target_rate(&mut self, which_one: usize, rate: f64) -> Result<(), SimulationError> {
+        check_mover_index(which_one, self.sampler.count_movers())?;
+        if self.mover_target_rates.len() <= which_one { self.mover_target_rates.resize(which_one + 1, None); }
+        self.mover_target_rates[which_one] = Some(rate);
+        Ok(())
+    }
+
+    /// Target success rate of a given mover
+    pub fn mover_target_rate(&self, which_one: usize) -> f64 {
+        self.mover_target_rates.get(which_one).copied().flatten().unwrap_or(self.target_rate)
+    }
+
+    /// Number of sweeps made so far by this sampler
+    pub fn count_sweeps(&self) -> usize { self.n_sweeps }
+
+    /// Returns true when the adaptation phase is over and step sizes don't change anymore
+    pub fn is_frozen(&self) -> bool { self.adaptation_sweeps.is_some_and(|n| self.n_sweeps >= n) }
+
+    /// Every update of a step size made so far, including the ones that left it unchanged
+    pub fn adaptation_log(&self) -> &Vec<AdaptationRecord> { &self.log }
+
+    /// Writes the adaptation log as a table; an empty file name means the standard output
+    pub fn write_adaptation_log(&self, out_fname: &str) -> io::Result<()> {
+        let mut out = out_writer(out_fname)?;
+        writeln!(out, "{:>10} {:>5} {:>8} {:>8} {:>12} {:>12}", "sweep", "mover", "rate", "target", "old_range",
+                 "new_range")?;
+        for r in self.log.iter() {
+            writeln!(out, "{:>10} {:>5} {:>8.4} {:>8.4} {:>12.6} {:>12.6}", r.sweep, r.mover, r.success_rate,
+                     r.target_rate, r.old_range, r.new_range)?;
+        }
+        out.flush()
+    }
+
+    /// Computes allowed ranges for movers that have been added since the previous sweep
+    fn update_allowed_ranges(&mut self) -> Result<(), SimulationError> {
+        for i in self.allowed_ranges.len()..self.sampler.count_movers() {
+            let r = self.sampler.get_mover(i)?.max_range();
+            self.allowed_ranges.push(r * 0.2..r * 5.0);
+            self.n_updates.push(0);
+        }
+        Ok(())
+    }
+
+    /// Makes sweeps and then updates the step size of every mover
+    fn make_adapted_sweeps(&mut self, n: usize, coords: &mut S, energy: &dyn Energy<S>) -> Result<(), SimulationError> {
+        let mut stats_before: Vec<AcceptanceStatistics> = vec![];
+        for i in 0..self.sampler.count_movers() {
+            stats_before.push(self.sampler.get_mover(i)?.acceptance_statistics());
+        }
+        self.sampler.make_sweeps(n, coords, energy)?;
+        self.n_sweeps += n;
+        for (i, stats) in stats_before.iter().enumerate() {
+            let stats_after = self.sampler.get_mover(i)?.acceptance_statistics();
+            if stats_after.n_succ + stats_after.n_failed == stats.n_succ + stats.n_failed { continue; }
+            let rate = stats_after.recent_success_rate(stats);
+            let target_rate = self.mover_target_rate(i);
+
+            let old_range = self.sampler.get_mover(i)?.max_range();
+            let mut range = self.strategy.new_range(old_range, rate, target_rate, self.n_updates[i]);
+            if self.allowed_ranges[i].end.lt(&range) { range = self.allowed_ranges[i].end }
+            if self.allowed_ranges[i].start.gt(&range) { range = self.allowed_ranges[i].start }
+            self.sampler.get_mover(i)?.set_max_range(range);
+            self.n_updates[i] += 1;
+            self.log.push(AdaptationRecord { sweep: self.n_sweeps, mover: i, success_rate: rate, target_rate,
+                old_range, new_range: range });
+        }
+        Ok(())
+    }
+}
+
+impl<T: AcceptanceCriterion, S: System> Sampler<T, S>  for AdaptiveMCProtocol<T, S> {
+
+    /// Makes `n` sweeps; when the adaptation phase ends within these sweeps, the remaining ones are made
+    /// with frozen step sizes
+    fn make_sweeps(&mut self, n: usize, coords: &mut S, energy: &dyn Energy<S>) -> Result<(), SimulationError> {
+        self.update_allowed_ranges()?;
+        let n_adapted = match self.adaptation_sweeps {
+            Some(limit) => n.min(limit.saturating_sub(self.n_sweeps)),
+            None => n
+        };
+        if n_adapted > 0 { self.make_adapted_sweeps(n_adapted, coords, energy)?; }
+        if n > n_adapted {
+            self.sampler.make_sweeps(n - n_adapted, coords, energy)?;
+            self.n_sweeps += n - n_adapted;
+        }
+        Ok(())
+    }
+
+    fn temperature(&self) -> f64 { self.sampler.temperature() }
+
+    fn set_temperature(&mut self, temperature: f64) { self.sampler.set_temperature(temperature); }
+
+    fn energy_inconsistencies(&self) -> Vec<EnergyInconsistency> { self.sampler.energy_inconsistencies() }
+}
+
+impl<T: AcceptanceCriterion, S: System> MoversSet<T, S>  for AdaptiveMCProtocol<T, S> {
+    fn add_mover(&mut self, perturb_fn: Box<dyn Mover<S>>) { self.sampler.add_mover(perturb_fn); }
+
+    fn get_mover(&mut self, which_one: usize) -> Result<&mut Box<dyn Mover<S>>, SimulationError> {
+        self.sampler.get_mover(which_one)
+    }
+
+    fn count_movers(&self) -> usize { self.sampler.count_movers() }
+}
+
+impl<T: AcceptanceCriterion, S: System> MoversSetSampler<T, S> for AdaptiveMCProtocol<T, S> {}
+
+/// Stores the target rate, the number of sweeps, the number of updates of every mover and the allowed ranges,
+/// followed by the state of the wrapped sampler. The strategy and the adaptation log are not stored.
+impl<T: AcceptanceCriterion, S: System> SamplerCheckpoint<S> for AdaptiveMCProtocol<T, S> {
+    fn save_state(&self) -> SamplerState<S> {
+        return SamplerState::AdaptiveMCProtocol(AdaptiveMCProtocolState {
+            target_rate: self.target_rate,
+            mover_target_rates: self.mover_target_rates.clone(),
+            adaptation_sweeps: self.adaptation_sweeps,
+            n_sweeps: self.n_sweeps,
+            n_updates: self.n_updates.clone(),
+            allowed_ranges: self.allowed_ranges.clone(),
+            sampler: Box::new(self.sampler.save_state()),
+        });
+    }
+
+    fn restore_state(&mut self, state: SamplerState<S>) -> io::Result<()> {
+        let state = match state {
+            SamplerState::AdaptiveMCProtocol(state) => state,
+            other => return Err(wrong_sampler("AdaptiveMCProtocol", &other)),
+        };
+        if state.n_updates.len() != state.allowed_ranges.len() {
+            return Err(invalid_state(format!("checkpoint holds {} update counters for {} movers",
+                                             state.n_updates.len(), state.allowed_ranges.len())));
+        }
+        self.sampler.restore_state(*state.sampler)?;
+        self.target_rate = state.target_rate;
+        self.mover_target_rates = state.mover_target_rates;
+        self.adaptation_sweeps = state.adaptation_sweeps;
+        self.n_sweeps = state.n_sweeps;
+        self.n_updates = state.n_updates;
+        self.allowed_ranges = state.allowed_ranges;
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+use crate::SimulationError;
+
+/// Defines how the temperature of a simulation changes in time.
+///
+/// A [`SimulationDriver`](crate::SimulationDriver) asks its schedule for the temperature before every block of sweeps;
+/// blocks are counted from 0. Any closure `Fn(usize) -> f64 + Send` can be used as a custom schedule.
+pub trait TemperatureSchedule: Send {
+    fn temperature(&self, block: usize) -> f64;
+}
+
+impl<F: Fn(usize) -> f64 + Send> TemperatureSchedule for F {
+    fn temperature(&self, block: usize) -> f64 { self(block) }
+}
+
+/// Changes the temperature linearly from `t_start` to `t_end` in `n_blocks` blocks, then keeps `t_end`
+pub struct LinearSchedule {
+    pub t_start: f64,
+    pub t_end: f64,
+    pub n_blocks: usize,
+}
+
+impl LinearSchedule {
+    pub fn new(t_start: f64, t_end: f64, n_blocks: usize) -> LinearSchedule { LinearSchedule { t_start, t_end, n_blocks } }
+}
+
+impl TemperatureSchedule for LinearSchedule {
+    fn temperature(&self, block: usize) -> f64 {
+        if block >= self.n_blocks { return self.t_end; }
+        return self.t_start + (self.t_end - self.t_start) * block as f64 / self.n_blocks as f64;
+    }
+}
+
+/// Multiplies the temperature by `ratio` after every block, but doesn't go below `t_min`
+pub struct GeometricSchedule {
+    pub t_start: f64,
+    pub ratio: f64,
+    pub t_min: f64,
+}
+
+impl GeometricSchedule {
+    pub fn new(t_start: f64, ratio: f64, t_min: f64) -> GeometricSchedule { GeometricSchedule { t_start, ratio, t_min } }
+}
+
+impl TemperatureSchedule for GeometricSchedule {
+    fn temperature(&self, block: usize) -> f64 {
+        return (self.t_start * self.ratio.powi(block as i32)).max(self.t_min);
+    }
+}
+
+/// Temperature decays exponentially from `t_start` towards `t_end`: `T = t_end + (t_start - t_end) exp(-block / tau)`
+pub struct ExponentialSchedule {
+    pub t_start: f64,
+    pub t_end: f64,
+    tau: f64,
+}
+
+impl ExponentialSchedule {
+    /// Creates a schedule; the decay time `tau`, counted in blocks, must be positive
+    pub fn new(t_start: f64, t_end: f64, tau: f64) -> Result<ExponentialSchedule, SimulationError> {
+        if tau <= 0.0 || !tau.is_finite() {
+            return Err(SimulationError::InvalidParameter(format!("decay time {} is not positive", tau)));
+        }
+        Ok(ExponentialSchedule { t_start, t_end, tau })
+    }
+
+    pub fn tau(&self) -> f64 { self.tau }
+}
+
+impl TemperatureSchedule for ExponentialSchedule {
+    fn temperature(&self, block: usize) -> f64 {
+        return self.t_end + (self.t_start - self.t_end) * (-(block as f64) / self.tau).exp();
+    }
+}
+
+/// Repeats a schedule in cycles of `cycle_length` blocks, reheating the system at the beginning of every cycle
+pub struct ReheatingSchedule {
+    pub cycle_length: usize,
+    schedule: Box<dyn TemperatureSchedule>,
+}
+
+impl ReheatingSchedule {
+    pub fn new(schedule: Box<dyn TemperatureSchedule>, cycle_length: usize) -> ReheatingSchedule {
+        ReheatingSchedule { cycle_length, schedule }
+    }
+}
+
+impl TemperatureSchedule for ReheatingSchedule {
+    fn temperature(&self, block: usize) -> f64 { self.schedule.temperature(block % self.cycle_length.max(1)) }
+}
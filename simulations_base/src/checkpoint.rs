@@ -0,0 +1,228 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Write};
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+
+use crate::{AcceptanceStatistics, Mover, RandomStream, System};
+
+/// A [`System`] that can be stored in a checkpoint file.
+///
+/// Systems opt into checkpointing by deriving serde's `Serialize` and `Deserialize`.
+pub trait CheckpointSystem: System + Serialize + DeserializeOwned {}
+
+impl<S: System + Serialize + DeserializeOwned> CheckpointSystem for S {}
+
+/// State of a single mover: its current step size and acceptance statistics
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MoverState {
+    pub max_range: f64,
+    pub statistics: AcceptanceStatistics,
+}
+
+impl MoverState {
+    pub fn new<S: System>(mover: &dyn Mover<S>) -> MoverState {
+        MoverState { max_range: mover.max_range(), statistics: mover.acceptance_statistics() }
+    }
+
+    pub fn restore<S: System>(&self, mover: &mut dyn Mover<S>) {
+        mover.set_max_range(self.max_range);
+        mover.set_acceptance_statistics(self.statistics.clone());
+    }
+}
+
+/// State of [`WangLandauCriterion`](crate::WangLandauCriterion): its estimate of ln g(E) and the histogram of visits
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WangLandauState {
+    pub ln_g: Vec<f64>,
+    pub histogram: Vec<u64>,
+    pub ln_f: f64,
+    pub energy: f64,
+    pub n_visits: usize,
+}
+
+/// State an acceptance criterion has learned while sampling, tagged by the name of the criterion
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum CriterionState {
+    WangLandau(WangLandauState),
+}
+
+/// State of [`MCProtocol`](crate::MCProtocol): its movers, random streams and the state of its acceptance criterion.
+///
+/// The temperature is `None` for a criterion that has no temperature.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MCProtocolState {
+    pub temperature: Option<f64>,
+    pub criterion: Option<CriterionState>,
+    pub movers: Vec<MoverState>,
+    pub streams: RandomStream,
+    pub criterion_rng: RandomStream,
+    pub selection_rng: RandomStream,
+    pub mover_rngs: Vec<RandomStream>,
+}
+
+/// State of [`UndoMCProtocol`](crate::UndoMCProtocol): its movers, random streams and the state of its acceptance criterion
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UndoMCProtocolState {
+    pub temperature: Option<f64>,
+    pub criterion: Option<CriterionState>,
+    pub movers: Vec<MoverState>,
+    pub streams: RandomStream,
+    pub criterion_rng: RandomStream,
+    pub mover_rngs: Vec<RandomStream>,
+}
+
+/// State of [`AdaptiveMCProtocol`](crate::AdaptiveMCProtocol) and of the sampler it wraps
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AdaptiveMCProtocolState<S> {
+    pub target_rate: f64,
+    pub mover_target_rates: Vec<Option<f64>>,
+    pub adaptation_sweeps: Option<usize>,
+    pub n_sweeps: usize,
+    pub n_updates: Vec<usize>,
+    pub allowed_ranges: Vec<Range<f64>>,
+    pub sampler: Box<SamplerState<S>>,
+}
+
+/// State of [`ReplicaExchangeProtocol`](crate::ReplicaExchangeProtocol): its replicas and the states of their samplers
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReplicaExchangeState<S> {
+    pub n_exchanges: usize,
+    pub sweeps_since_exchange: usize,
+    pub temperatures: Vec<f64>,
+    pub rng: RandomStream,
+    pub swap_stats: Vec<AcceptanceStatistics>,
+    pub replicas: Vec<S>,
+    pub samplers: Vec<SamplerState<S>>,
+}
+
+/// State of a sampler that allows a simulation to be restarted.
+///
+/// Every kind of sampler stores its own state, tagged in a checkpoint file by the name of the sampler,
+/// so a state can't be restored into a sampler of a different kind.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum SamplerState<S> {
+    MCProtocol(MCProtocolState),
+    UndoMCProtocol(UndoMCProtocolState),
+    AdaptiveMCProtocol(AdaptiveMCProtocolState<S>),
+    ReplicaExchangeProtocol(ReplicaExchangeState<S>),
+}
+
+impl<S> SamplerState<S> {
+    /// Name of the sampler this state belongs to
+    pub fn sampler_name(&self) -> &'static str {
+        match self {
+            SamplerState::MCProtocol(_) => "MCProtocol",
+            SamplerState::UndoMCProtocol(_) => "UndoMCProtocol",
+            SamplerState::AdaptiveMCProtocol(_) => "AdaptiveMCProtocol",
+            SamplerState::ReplicaExchangeProtocol(_) => "ReplicaExchangeProtocol",
+        }
+    }
+}
+
+/// A sampler which state can be saved and later restored.
+///
+/// After [`restore_state()`](SamplerCheckpoint::restore_state) the sampler must continue exactly as the sampler
+/// that [saved](SamplerCheckpoint::save_state) the state, including its random streams.
+pub trait SamplerCheckpoint<S> {
+    fn save_state(&self) -> SamplerState<S>;
+    fn restore_state(&mut self, state: SamplerState<S>) -> io::Result<()>;
+}
+
+/// Error reported when a checkpoint doesn't fit the sampler it's restored into
+pub(crate) fn invalid_state(msg: String) -> io::Error { io::Error::new(io::ErrorKind::InvalidData, msg) }
+
+/// Error reported when a checkpoint holds a state of a different kind of sampler
+pub(crate) fn wrong_sampler<S>(expected: &str, state: &SamplerState<S>) -> io::Error {
+    invalid_state(format!("checkpoint holds a state of {} rather than {}", state.sampler_name(), expected))
+}
+
+/// Everything needed to restart a Monte Carlo simulation: the system, the state of the sampler and the sweep counter.
+///
+/// A checkpoint is stored as a JSON file. A run that writes `Checkpoint::new(sweep, &system, &sampler).save(fname)`
+/// periodically can be resumed with `Checkpoint::load(fname)?.restore(&mut system, &mut sampler)`.
+#[derive(Serialize, Deserialize)]
+pub struct Checkpoint<S> {
+    pub sweep: usize,
+    pub system: S,
+    pub sampler: SamplerState<S>,
+}
+
+impl<S: CheckpointSystem> Checkpoint<S> {
+    pub fn new(sweep: usize, system: &S, sampler: &dyn SamplerCheckpoint<S>) -> Checkpoint<S> {
+        Checkpoint { sweep, system: system.clone(), sampler: sampler.save_state() }
+    }
+
+    /// Writes this checkpoint into a file.
+    ///
+    /// The data is first written to a temporary file which is then renamed, so a crash while writing
+    /// doesn't destroy the previous checkpoint.
+    pub fn save(&self, out_fname: &str) -> io::Result<()> {
+        let tmp_fname = format!("{}.tmp", out_fname);
+        let mut out_writer = BufWriter::new(File::create(&tmp_fname)?);
+        serde_json::to_writer(&mut out_writer, self)?;
+        out_writer.flush()?;
+        drop(out_writer);
+        std::fs::rename(&tmp_fname, out_fname)
+    }
+
+    pub fn load(in_fname: &str) -> io::Result<Checkpoint<S>> {
+        let reader = BufReader::new(File::open(in_fname)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Restores the system and the sampler from this checkpoint and returns the sweep counter
+    pub fn restore(self, system: &mut S, sampler: &mut dyn SamplerCheckpoint<S>) -> io::Result<usize> {
+        sampler.restore_state(self.sampler)?;
+        *system = self.system;
+        Ok(self.sweep)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{AdaptiveMCProtocol, IsingEnergy, MCProtocol, MetropolisCriterion, MoversSet, RandomStreams, Sampler,
+                SamplerCheckpoint, SamplerState, SpinFlipMover, SpinLattice};
+
+    fn adaptive_sampler(seed: u64) -> AdaptiveMCProtocol<MetropolisCriterion, SpinLattice> {
+        let mut sampler = MCProtocol::with_streams(MetropolisCriterion::new(2.0), RandomStreams::new(seed));
+        sampler.add_mover(Box::new(SpinFlipMover::new()));
+        return AdaptiveMCProtocol::new(Box::new(sampler));
+    }
+
+    #[test]
+    fn restored_sampler_continues_the_run() {
+        let energy = IsingEnergy::new(1.0, 0.0);
+        let mut system = SpinLattice::new(&[6, 6], 2).unwrap();
+        let mut sampler = adaptive_sampler(7);
+        sampler.adaptation_sweeps = Some(15);
+        sampler.set_mover_target_rate(0, 0.3).unwrap();
+        sampler.make_sweeps(10, &mut system, &energy).unwrap();
+
+        let json = serde_json::to_string(&sampler.save_state()).unwrap();
+        assert!(json.contains("\"kind\":\"AdaptiveMCProtocol\""));
+        let mut restored = adaptive_sampler(1234);
+        restored.restore_state(serde_json::from_str(&json).unwrap()).unwrap();
+        assert_eq!(restored.count_sweeps(), 10);
+        assert_eq!(restored.adaptation_sweeps, Some(15));
+        assert_eq!(restored.mover_target_rate(0), 0.3);
+
+        let mut restored_system = system.clone();
+        sampler.make_sweeps(10, &mut system, &energy).unwrap();
+        restored.make_sweeps(10, &mut restored_system, &energy).unwrap();
+        let spins = |s: &SpinLattice| (0..36).map(|i| s.spin(i)).collect::<Vec<u8>>();
+        assert_eq!(spins(&system), spins(&restored_system));
+    }
+
+    #[test]
+    fn state_of_other_sampler_is_rejected() {
+        let mut sampler = MCProtocol::with_streams(MetropolisCriterion::new(2.0), RandomStreams::new(7));
+        sampler.add_mover(Box::new(SpinFlipMover::new()));
+        let state: SamplerState<SpinLattice> = sampler.save_state();
+        assert!(adaptive_sampler(7).restore_state(state).is_err());
+    }
+}
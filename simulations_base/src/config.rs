@@ -0,0 +1,555 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{AcceptanceCriterion, AcceptanceObserver, AdaptationStrategy, AdaptiveMCProtocol, BarkerCriterion,
+            CheckpointSystem, CsvSink, DeadBandAdaptation, Energy, EnergyObserver, ExponentialSchedule,
+            GeometricSchedule, JsonLinesSink, LinearSchedule, MCProtocol, MetricsObserver, MetricsSink,
+            MetropolisCriterion, Mover, MoversSetSampler, Observer, RandomStreams, RobbinsMonroAdaptation,
+            SimulationDriver, SimulationError, StatisticsObserver, SweepPolicy, System, TemperatureSchedule,
+            TimingObserver, TotalEnergy, TsallisCriterion, ZeroTemperatureCriterion};
+
+/// Value of a single parameter of a component: a number or a text
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Parameter {
+    Number(f64),
+    Text(String),
+}
+
+/// Named parameters of a component, given in a configuration file next to the `kind` of that component.
+///
+/// Every parameter read by a factory is marked as used; [`SimulationRunner`] reports parameters that no component
+/// has read, which are usually misspelled names.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Parameters {
+    values: BTreeMap<String, Parameter>,
+    #[serde(skip)]
+    used: RefCell<BTreeSet<String>>,
+}
+
+impl PartialEq for Parameters {
+    fn eq(&self, other: &Self) -> bool { self.values == other.values }
+}
+
+impl Parameters {
+    pub fn new(values: BTreeMap<String, Parameter>) -> Parameters { Parameters { values, used: Default::default() } }
+
+    pub fn values(&self) -> &BTreeMap<String, Parameter> { &self.values }
+
+    /// Returns a parameter and marks it as used
+    fn get(&self, name: &str) -> Option<&Parameter> {
+        self.used.borrow_mut().insert(name.to_string());
+        self.values.get(name)
+    }
+
+    /// Names of the parameters that haven't been read so far
+    pub fn unused(&self) -> Vec<&str> {
+        let used = self.used.borrow();
+        self.values.keys().filter(|k| !used.contains(*k)).map(|k| k.as_str()).collect()
+    }
+
+    /// Fails when any parameter of a given component hasn't been read
+    pub fn check_used(&self, component: &str) -> Result<(), SimulationError> {
+        let unused = self.unused();
+        if unused.is_empty() { return Ok(()); }
+        return Err(SimulationError::Config(format!("unknown parameters of {}: {}", component, unused.join(", "))));
+    }
+
+    /// Returns a numeric parameter; fails if it's missing or it's not a number
+    pub fn number(&self, name: &str) -> Result<f64, SimulationError> {
+        match self.get(name) {
+            Some(Parameter::Number(v)) => Ok(*v),
+            Some(Parameter::Text(_)) => Err(SimulationError::Config(format!("parameter {} must be a number", name))),
+            None => Err(SimulationError::Config(format!("missing parameter: {}", name))),
+        }
+    }
+
+    /// Returns a numeric parameter or the default value when the parameter is missing
+    pub fn number_or(&self, name: &str, default: f64) -> Result<f64, SimulationError> {
+        if self.values.contains_key(name) { self.number(name) } else { Ok(default) }
+    }
+
+    /// Returns a text parameter or the default value when the parameter is missing
+    pub fn text_or<'a>(&'a self, name: &str, default: &'a str) -> Result<&'a str, SimulationError> {
+        if self.values.contains_key(name) { self.text(name) } else { Ok(default) }
+    }
+
+    /// Returns a text parameter; fails if it's missing or it's not a text
+    pub fn text(&self, name: &str) -> Result<&str, SimulationError> {
+        match self.get(name) {
+            Some(Parameter::Text(v)) => Ok(v),
+            Some(Parameter::Number(_)) => Err(SimulationError::Config(format!("parameter {} must be a text", name))),
+            None => Err(SimulationError::Config(format!("missing parameter: {}", name))),
+        }
+    }
+}
+
+/// Configuration of a component, e.g. a system or a temperature schedule
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ComponentConfig {
+    pub kind: String,
+    #[serde(flatten)]
+    pub params: Parameters,
+}
+
+/// Configuration of a single term of a [`TotalEnergy`]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TermConfig {
+    pub name: String,
+    pub kind: String,
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+    #[serde(flatten)]
+    pub params: Parameters,
+}
+
+/// Configuration of a mover
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MoverConfig {
+    pub kind: String,
+    /// initial step size of the mover
+    #[serde(default = "default_max_range")]
+    pub max_range: f64,
+    /// weight of the mover in [`MCProtocol`]
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+    /// target success rate of the mover when step sizes are adapted; by default the target rate of [`AdaptiveConfig`]
+    #[serde(default)]
+    pub target_rate: Option<f64>,
+    #[serde(flatten)]
+    pub params: Parameters,
+}
+
+/// Configuration of an acceptance criterion.
+///
+/// Kinds provided by this crate are `metropolis` (the default), `barker` (Glauber dynamics for spin flips),
+/// `tsallis` (with parameter `q`) and `zero_temperature`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CriterionConfig {
+    #[serde(default = "default_criterion")]
+    pub kind: String,
+    #[serde(default = "default_temperature")]
+    pub temperature: f64,
+    #[serde(flatten)]
+    pub params: Parameters,
+}
+
+impl Default for CriterionConfig {
+    fn default() -> Self {
+        CriterionConfig { kind: default_criterion(), temperature: default_temperature(), params: Parameters::default() }
+    }
+}
+
+/// Settings of [`AdaptiveMCProtocol`]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdaptiveConfig {
+    #[serde(default = "default_target_rate")]
+    pub target_rate: f64,
+    /// adaptation strategy: `dead_band` (factor, tolerance) or `robbins_monro` (gain, decay)
+    #[serde(default = "default_strategy")]
+    pub strategy: String,
+    #[serde(default = "default_factor")]
+    pub factor: f64,
+    #[serde(default = "default_tolerance")]
+    pub tolerance: f64,
+    #[serde(default = "default_gain")]
+    pub gain: f64,
+    #[serde(default = "default_decay")]
+    pub decay: f64,
+    /// number of sweeps during which step sizes are adapted; by default they are adapted during the whole run
+    #[serde(default)]
+    pub burn_in: Option<usize>,
+}
+
+/// Configuration of an observer.
+///
+/// Kinds provided by this crate are `energy`, `acceptance`, `timing`, `statistics` and `metrics`. The `statistics`
+/// observer records the energy and accepts an optional `burn_in` parameter; `metrics` writes structured records
+/// in the `csv` (the default) or `jsonl` format, given by the `format` parameter. An empty `output` means
+/// the standard output.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ObserverConfig {
+    pub kind: String,
+    #[serde(default)]
+    pub output: String,
+    /// the observer is called every that many blocks
+    #[serde(default = "default_every")]
+    pub every: usize,
+    #[serde(flatten)]
+    pub params: Parameters,
+}
+
+/// Description of a whole simulation run, loaded from a TOML file.
+///
+/// A minimal run description looks like:
+/// ```toml
+/// seed = 1
+/// n_blocks = 1000
+/// sweeps_per_block = 100
+///
+/// [system]
+/// kind = "square_grid"
+/// n = 20
+///
+/// [[energy]]
+/// name = "repulsion"
+/// kind = "hard_disk"
+/// r = 4.0
+///
+/// [[movers]]
+/// kind = "disk"
+/// max_range = 3.0
+///
+/// [criterion]
+/// temperature = 1.0
+///
+/// [[observers]]
+/// kind = "energy"
+/// output = "energy.dat"
+/// ```
+/// Kinds of systems, energy terms and movers are resolved by a [`ComponentFactory`]. Parameters of a component
+/// that none of the factories reads are reported as an error by [`SimulationRunner::new()`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RunConfig {
+    /// master seed; a run without a seed is seeded from the entropy source
+    #[serde(default)]
+    pub seed: Option<u64>,
+    pub n_blocks: usize,
+    #[serde(default = "default_sweeps_per_block")]
+    pub sweeps_per_block: usize,
+    #[serde(default)]
+    pub sweep_policy: Option<SweepPolicy>,
+    pub system: ComponentConfig,
+    pub energy: Vec<TermConfig>,
+    pub movers: Vec<MoverConfig>,
+    #[serde(default)]
+    pub criterion: CriterionConfig,
+    /// when given, step sizes of movers are adjusted during the run
+    #[serde(default)]
+    pub adaptive: Option<AdaptiveConfig>,
+    /// temperature schedule: `linear` (t_start, t_end, n_blocks), `geometric` (t_start, ratio, t_min)
+    /// or `exponential` (t_start, t_end, tau)
+    #[serde(default)]
+    pub schedule: Option<ComponentConfig>,
+    #[serde(default)]
+    pub observers: Vec<ObserverConfig>,
+    /// checkpoint file; a run restarts from it when it exists and updates it after every block
+    #[serde(default)]
+    pub checkpoint: Option<String>,
+}
+
+impl RunConfig {
+    pub fn from_toml(text: &str) -> Result<RunConfig, SimulationError> {
+        toml::from_str(text).map_err(|e| SimulationError::Config(e.to_string()))
+    }
+
+    pub fn load(in_fname: &str) -> Result<RunConfig, SimulationError> {
+        RunConfig::from_toml(&fs::read_to_string(in_fname)?)
+    }
+
+    /// Fails when any component has a parameter that hasn't been read while the components were created
+    fn check_parameters_used(&self) -> Result<(), SimulationError> {
+        self.system.params.check_used(&format!("system {}", self.system.kind))?;
+        for term in self.energy.iter() { term.params.check_used(&format!("energy term {}", term.name))?; }
+        for mover in self.movers.iter() { mover.params.check_used(&format!("mover {}", mover.kind))?; }
+        self.criterion.params.check_used(&format!("criterion {}", self.criterion.kind))?;
+        if let Some(schedule) = &self.schedule {
+            schedule.params.check_used(&format!("schedule {}", schedule.kind))?;
+        }
+        for observer in self.observers.iter() {
+            observer.params.check_used(&format!("observer {}", observer.kind))?;
+        }
+        Ok(())
+    }
+}
+
+fn default_weight() -> f64 { 1.0 }
+
+fn default_max_range() -> f64 { 1.0 }
+
+fn default_temperature() -> f64 { 1.0 }
+
+fn default_criterion() -> String { "metropolis".to_string() }
+
+fn default_target_rate() -> f64 { 0.4 }
+
+fn default_factor() -> f64 { 0.95 }
+
+fn default_strategy() -> String { "dead_band".to_string() }
+
+fn default_tolerance() -> f64 { 0.05 }
+
+fn default_gain() -> f64 { 1.0 }
+
+fn default_decay() -> f64 { 0.6 }
+
+fn default_every() -> usize { 1 }
+
+fn default_sweeps_per_block() -> usize { 100 }
+
+/// Error reported for a component of unknown kind
+pub fn unknown_kind(component: &str, kind: &str) -> SimulationError {
+    SimulationError::Config(format!("unknown {} kind: {}", component, kind))
+}
+
+/// Creates model-specific components of a simulation from their configuration.
+///
+/// Every model that is run by a [`SimulationRunner`] provides its own factory that knows the kinds
+/// of systems, energy terms and movers of that model.
+pub trait ComponentFactory<S: System> {
+    fn create_system(&self, config: &ComponentConfig) -> Result<S, SimulationError>;
+    fn create_energy_term(&self, config: &TermConfig) -> Result<Box<dyn Energy<S>>, SimulationError>;
+    fn create_mover(&self, config: &MoverConfig) -> Result<Box<dyn Mover<S>>, SimulationError>;
+
+    /// Creates an observer of a kind not provided by this crate; by default every such kind is unknown.
+    ///
+    /// An observer that writes a file should append to it rather than truncate it, since the same observer
+    /// is created again when a run restarts from a checkpoint.
+    fn create_observer(&self, config: &ObserverConfig) -> Result<Box<dyn Observer<S>>, SimulationError> {
+        Err(unknown_kind("observer", &config.kind))
+    }
+}
+
+pub fn create_criterion(config: &CriterionConfig) -> Result<Box<dyn AcceptanceCriterion>, SimulationError> {
+    match config.kind.as_str() {
+        "metropolis" => Ok(Box::new(MetropolisCriterion::new(config.temperature))),
+        "barker" => Ok(Box::new(BarkerCriterion::new(config.temperature)?)),
+        "tsallis" => Ok(Box::new(TsallisCriterion::new(config.temperature, config.params.number("q")?)?)),
+        "zero_temperature" => Ok(Box::new(ZeroTemperatureCriterion::new())),
+        _ => Err(unknown_kind("acceptance criterion", &config.kind)),
+    }
+}
+
+pub fn create_schedule(config: &ComponentConfig) -> Result<Box<dyn TemperatureSchedule>, SimulationError> {
+    let p = &config.params;
+    match config.kind.as_str() {
+        "linear" => Ok(Box::new(LinearSchedule::new(p.number("t_start")?, p.number("t_end")?,
+                                                    p.number("n_blocks")? as usize))),
+        "geometric" => Ok(Box::new(GeometricSchedule::new(p.number("t_start")?, p.number("ratio")?,
+                                                          p.number_or("t_min", 0.0)?))),
+        "exponential" => Ok(Box::new(ExponentialSchedule::new(p.number("t_start")?, p.number("t_end")?,
+                                                              p.number("tau")?)?)),
+        _ => Err(unknown_kind("temperature schedule", &config.kind)),
+    }
+}
+
+pub fn create_adaptation_strategy(config: &AdaptiveConfig) -> Result<Box<dyn AdaptationStrategy>, SimulationError> {
+    match config.strategy.as_str() {
+        "dead_band" => Ok(Box::new(DeadBandAdaptation::new(config.factor, config.tolerance))),
+        "robbins_monro" => Ok(Box::new(RobbinsMonroAdaptation::new(config.gain, config.decay))),
+        _ => Err(unknown_kind("adaptation strategy", &config.strategy)),
+    }
+}
+
+/// Creates an observer of a kind provided by this crate, or asks the factory for any other kind.
+///
+/// With `append` set, observers of this crate append to their output files instead of truncating them,
+/// which is how a run restarted from a checkpoint continues its output.
+pub fn create_observer<S: System + 'static>(config: &ObserverConfig, factory: &dyn ComponentFactory<S>, append: bool)
+        -> Result<Box<dyn Observer<S>>, SimulationError> {
+    let output = config.output.as_str();
+    match config.kind.as_str() {
+        "energy" if append => Ok(Box::new(EnergyObserver::appending(output)?)),
+        "energy" => Ok(Box::new(EnergyObserver::new(output)?)),
+        "acceptance" if append => Ok(Box::new(AcceptanceObserver::appending(output)?)),
+        "acceptance" => Ok(Box::new(AcceptanceObserver::new(output)?)),
+        "timing" if append => Ok(Box::new(TimingObserver::appending(output)?)),
+        "timing" => Ok(Box::new(TimingObserver::new(output)?)),
+        "statistics" => {
+            let mut observer = StatisticsObserver::new(output);
+            observer.burn_in = config.params.number_or("burn_in", 0.0)? as usize;
+            observer.add_observable("energy", Box::new(|o| o.energy));
+            Ok(Box::new(observer))
+        }
+        "metrics" => {
+            let sink: Box<dyn MetricsSink> = match (config.params.text_or("format", "csv")?, append) {
+                ("csv", false) => Box::new(CsvSink::new(output)?),
+                ("csv", true) => Box::new(CsvSink::appending(output)?),
+                ("jsonl", false) => Box::new(JsonLinesSink::new(output)?),
+                ("jsonl", true) => Box::new(JsonLinesSink::appending(output)?),
+                (format, _) => return Err(unknown_kind("metrics format", format)),
+            };
+            Ok(Box::new(MetricsObserver::new(sink)))
+        }
+        _ => factory.create_observer(config),
+    }
+}
+
+/// Runs a simulation described by a [`RunConfig`].
+///
+/// The runner builds an [`MCProtocol`] (wrapped in [`AdaptiveMCProtocol`] when requested), a [`TotalEnergy`]
+/// made of all the configured terms and a [`SimulationDriver`] with its observers.
+pub struct SimulationRunner<S: CheckpointSystem> {
+    pub config: RunConfig,
+    pub system: S,
+    pub energy: TotalEnergy<S>,
+    pub driver: SimulationDriver<Box<dyn AcceptanceCriterion>, S>,
+}
+
+impl<S: CheckpointSystem + 'static> SimulationRunner<S> {
+    pub fn new(config: RunConfig, factory: &dyn ComponentFactory<S>) -> Result<SimulationRunner<S>, SimulationError> {
+        let streams = match config.seed {
+            Some(seed) => RandomStreams::new(seed),
+            None => RandomStreams::from_entropy()
+        };
+        let system = factory.create_system(&config.system)?;
+        let mut energy = TotalEnergy::new();
+        for term in config.energy.iter() {
+            energy.add_term(&term.name, term.weight, factory.create_energy_term(term)?);
+        }
+
+        let mut protocol = MCProtocol::with_streams(create_criterion(&config.criterion)?, streams);
+        if let Some(policy) = config.sweep_policy { protocol.sweep_policy = policy; }
+        for mover in config.movers.iter() {
+            protocol.add_weighted_mover(factory.create_mover(mover)?, mover.weight)?;
+        }
+        let sampler: Box<dyn MoversSetSampler<Box<dyn AcceptanceCriterion>, S>> = match &config.adaptive {
+            Some(adaptive) => {
+                let mut sampler = AdaptiveMCProtocol::new(Box::new(protocol));
+                sampler.target_rate = adaptive.target_rate;
+                sampler.adaptation_sweeps = adaptive.burn_in;
+                sampler.set_strategy(create_adaptation_strategy(adaptive)?);
+                for (i, mover) in config.movers.iter().enumerate() {
+                    if let Some(rate) = mover.target_rate { sampler.set_mover_target_rate(i, rate)?; }
+                }
+                Box::new(sampler)
+            }
+            None => Box::new(protocol)
+        };
+
+        let mut driver = SimulationDriver::new(sampler, config.sweeps_per_block);
+        if let Some(schedule) = &config.schedule { driver.set_schedule(create_schedule(schedule)?); }
+        // --- a run restarted from a checkpoint continues the output of the previous run
+        let restart = config.checkpoint.as_ref().is_some_and(|fname| Path::new(fname).exists());
+        for observer in config.observers.iter() {
+            driver.add_observer(create_observer(observer, factory, restart)?, observer.every);
+        }
+        config.check_parameters_used()?;
+        Ok(SimulationRunner { config, system, energy, driver })
+    }
+
+    pub fn from_file(in_fname: &str, factory: &dyn ComponentFactory<S>) -> Result<SimulationRunner<S>, SimulationError> {
+        SimulationRunner::new(RunConfig::load(in_fname)?, factory)
+    }
+
+    /// Runs all the blocks of the simulation and finalizes the observers.
+    ///
+    /// When a checkpoint file is configured, the run restarts from that file if it exists,
+    /// and the file is updated after every block.
+    pub fn run(&mut self) -> Result<(), SimulationError> {
+        match self.config.checkpoint.clone() {
+            Some(fname) => {
+                if Path::new(&fname).exists() { self.driver.restore_checkpoint(&fname, &mut self.system)?; }
+                let first_block = self.driver.sweep() / self.driver.sweeps_per_block.max(1);
+                for _ in first_block..self.config.n_blocks {
+                    self.driver.run(1, &mut self.system, &self.energy)?;
+                    self.driver.save_checkpoint(&fname, &self.system)?;
+                }
+            }
+            None => self.driver.run(self.config.n_blocks, &mut self.system, &self.energy)?
+        }
+        self.driver.finalize();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{unknown_kind, ComponentConfig, ComponentFactory, Energy, IsingEnergy, Mover, MoverConfig, RunConfig,
+                SimulationError, SimulationRunner, SpinFlipMover, SpinLattice, TermConfig};
+
+    /// Factory of Ising model components: system `lattice` (`side`), energy term `ising` (`j`, `h`), mover `flip`
+    struct IsingFactory;
+
+    impl ComponentFactory<SpinLattice> for IsingFactory {
+        fn create_system(&self, config: &ComponentConfig) -> Result<SpinLattice, SimulationError> {
+            let side = config.params.number("side")? as usize;
+            SpinLattice::new(&[side, side], 2)
+        }
+
+        fn create_energy_term(&self, config: &TermConfig) -> Result<Box<dyn Energy<SpinLattice>>, SimulationError> {
+            match config.kind.as_str() {
+                "ising" => {
+                    let p = &config.params;
+                    Ok(Box::new(IsingEnergy::new(p.number("j")?, p.number_or("h", 0.0)?)))
+                }
+                _ => Err(unknown_kind("energy term", &config.kind)),
+            }
+        }
+
+        fn create_mover(&self, config: &MoverConfig) -> Result<Box<dyn Mover<SpinLattice>>, SimulationError> {
+            match config.kind.as_str() {
+                "flip" => Ok(Box::new(SpinFlipMover::new())),
+                _ => Err(unknown_kind("mover", &config.kind)),
+            }
+        }
+    }
+
+    const CONFIG: &str = r#"
+seed = 3
+n_blocks = 5
+sweeps_per_block = 10
+
+[system]
+kind = "lattice"
+side = 4
+
+[[energy]]
+name = "bonds"
+kind = "ising"
+j = 1.0
+
+[[movers]]
+kind = "flip"
+weight = 2.0
+
+[criterion]
+kind = "tsallis"
+temperature = 2.5
+q = 1.0
+
+[adaptive]
+target_rate = 0.3
+"#;
+
+    #[test]
+    fn config_survives_round_trip() {
+        let config = RunConfig::from_toml(CONFIG).unwrap();
+        let text = toml::to_string(&config).unwrap();
+        let again = RunConfig::from_toml(&text).unwrap();
+        assert_eq!(again.seed, Some(3));
+        assert_eq!(again.n_blocks, 5);
+        assert_eq!(again.system.kind, "lattice");
+        assert_eq!(again.system.params, config.system.params);
+        assert_eq!(again.energy[0].params.number("j").unwrap(), 1.0);
+        assert_eq!(again.movers[0].weight, 2.0);
+        assert_eq!(again.criterion.temperature, 2.5);
+        assert_eq!(again.criterion.params.number("q").unwrap(), 1.0);
+        assert_eq!(again.adaptive.unwrap().target_rate, 0.3);
+
+        let mut runner = SimulationRunner::new(config, &IsingFactory).unwrap();
+        runner.run().unwrap();
+        assert_eq!(runner.driver.sweep(), 50);
+    }
+
+    #[test]
+    fn misspelled_parameters_are_rejected() {
+        let typo = CONFIG.replace("temperature = 2.5", "temprature = 2.5");
+        let result = SimulationRunner::new(RunConfig::from_toml(&typo).unwrap(), &IsingFactory);
+        assert!(matches!(result, Err(SimulationError::Config(message)) if message.contains("temprature")));
+
+        let typo = CONFIG.replace("weight = 2.0", "weight = 2.0\nmax_rnage = 2");
+        let result = SimulationRunner::new(RunConfig::from_toml(&typo).unwrap(), &IsingFactory);
+        assert!(matches!(result, Err(SimulationError::Config(message)) if message.contains("max_rnage")));
+
+        let typo = CONFIG.replace("target_rate = 0.3", "tagret_rate = 0.3");
+        assert!(RunConfig::from_toml(&typo).is_err());
+    }
+}
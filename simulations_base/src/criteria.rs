@@ -0,0 +1,263 @@
+use rand::Rng;
+
+use crate::{AcceptanceCriterion, RandomStream, SimulationError};
+
+/// Checks that a temperature given to a criterion is positive and finite
+fn check_temperature(temperature: f64) -> Result<f64, SimulationError> {
+    if temperature > 0.0 && temperature.is_finite() { return Ok(temperature); }
+    return Err(SimulationError::InvalidParameter(format!("temperature must be positive, got {}", temperature)));
+}
+
+/// Barker criterion: a move is accepted with probability `1 / (1 + exp(dE / T))`.
+///
+/// Like [`MetropolisCriterion`](crate::MetropolisCriterion), it satisfies detailed balance with the Boltzmann
+/// distribution, but it also rejects some downhill moves, so its acceptance rate is lower.
+/// When a move changes a two-state variable, e.g. flips an Ising spin, the Barker probability equals
+/// the heat-bath one, so such moves give Glauber dynamics; for Potts spins of more than two states
+/// the heat-bath update is made by [`HeatBathMover`](crate::HeatBathMover).
+///
+/// The constructor rejects a temperature that isn't positive; when
+/// [`set_temperature()`](AcceptanceCriterion::set_temperature) gets zero, e.g. at the end of an annealing schedule,
+/// moves are accepted with the zero-temperature limit of the Barker probability.
+pub struct BarkerCriterion {
+    temperature: f64,
+}
+
+impl BarkerCriterion {
+    pub fn new(temperature: f64) -> Result<BarkerCriterion, SimulationError> {
+        Ok(BarkerCriterion { temperature: check_temperature(temperature)? })
+    }
+
+    /// Probability of accepting a move that changes the energy by `delta_e`
+    pub fn probability(&self, delta_e: f64) -> f64 {
+        if self.temperature <= 0.0 {
+            return if delta_e < 0.0 { 1.0 } else if delta_e == 0.0 { 0.5 } else { 0.0 };
+        }
+        return 1.0 / (1.0 + (delta_e / self.temperature).exp());
+    }
+}
+
+impl AcceptanceCriterion for BarkerCriterion {
+    fn check(&mut self, energy_before: f64, energy_after: f64, rng: &mut RandomStream) -> bool {
+        return rng.gen_range(0.0..1.0) < self.probability(energy_after - energy_before);
+    }
+
+    fn temperature(&self) -> f64 { self.temperature }
+
+    fn set_temperature(&mut self, temperature: f64) { self.temperature = temperature; }
+}
+
+/// Generalised Metropolis criterion of Tsallis statistics.
+///
+/// An uphill move is accepted with probability `[1 - (1 - q) dE / T]^(1 / (1 - q))`, which is zero when
+/// the bracket isn't positive; downhill moves are always accepted. For `q = 1` this is the Metropolis criterion,
+/// while `q > 1` accepts uphill moves more often and is used e.g. by generalised simulated annealing.
+/// Except for `q = 1`, the stationary distribution isn't the Boltzmann one.
+///
+/// As for [`BarkerCriterion`], the constructor rejects a temperature that isn't positive, while at zero temperature
+/// set later no uphill move is accepted.
+pub struct TsallisCriterion {
+    pub q: f64,
+    temperature: f64,
+}
+
+impl TsallisCriterion {
+    pub fn new(temperature: f64, q: f64) -> Result<TsallisCriterion, SimulationError> {
+        Ok(TsallisCriterion { temperature: check_temperature(temperature)?, q })
+    }
+
+    /// Probability of accepting a move that changes the energy by `delta_e`
+    pub fn probability(&self, delta_e: f64) -> f64 {
+        if delta_e <= 0.0 { return 1.0; }
+        if self.temperature <= 0.0 { return 0.0; }
+        if (self.q - 1.0).abs() < 1e-12 { return (-delta_e / self.temperature).exp(); }
+        let base = 1.0 - (1.0 - self.q) * delta_e / self.temperature;
+        if base <= 0.0 { return 0.0; }
+        return base.powf(1.0 / (1.0 - self.q)).min(1.0);
+    }
+}
+
+impl AcceptanceCriterion for TsallisCriterion {
+    fn check(&mut self, energy_before: f64, energy_after: f64, rng: &mut RandomStream) -> bool {
+        let delta_e = energy_after - energy_before;
+        if delta_e <= 0.0 { return true; }
+        return rng.gen_range(0.0..1.0) < self.probability(delta_e);
+    }
+
+    fn temperature(&self) -> f64 { self.temperature }
+
+    fn set_temperature(&mut self, temperature: f64) { self.temperature = temperature; }
+}
+
+/// Greedy criterion, the zero-temperature limit of Metropolis: accepts every move that doesn't increase the energy.
+///
+/// Useful for a final minimisation; the temperature is always 0.0 and setting it has no effect.
+#[derive(Default)]
+pub struct ZeroTemperatureCriterion;
+
+impl ZeroTemperatureCriterion {
+    pub fn new() -> ZeroTemperatureCriterion { ZeroTemperatureCriterion }
+}
+
+impl AcceptanceCriterion for ZeroTemperatureCriterion {
+    fn check(&mut self, energy_before: f64, energy_after: f64, _rng: &mut RandomStream) -> bool {
+        energy_after <= energy_before
+    }
+
+    fn temperature(&self) -> f64 { 0.0 }
+
+    fn set_temperature(&mut self, _temperature: f64) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{AcceptanceCriterion, BarkerCriterion, Energy, HeatBathMover, IsingEnergy, MCProtocol,
+                MetropolisCriterion, Mover, MoversSet, PottsEnergy, RandomStreams, Sampler, SpinFlipMover, SpinLattice,
+                System, TsallisCriterion, ZeroTemperatureCriterion};
+
+    /// Index of the current state of a lattice of `q`-state spins
+    fn state_index(system: &SpinLattice) -> usize {
+        (0..system.size()).rev().fold(0, |index, i| index * system.q() as usize + system.spin(i) as usize)
+    }
+
+    /// Energies of all the states of a lattice of `q`-state spins, in the order of state indexes
+    fn state_energies(dims: &[usize], q: u8, energy: &dyn Energy<SpinLattice>) -> Vec<f64> {
+        let mut system = SpinLattice::new(dims, q).unwrap();
+        let n = system.size();
+        return (0..(q as usize).pow(n as u32)).map(|s| {
+            let mut rest = s;
+            for i in 0..n {
+                system.set_spin(i, (rest % q as usize) as u8);
+                rest /= q as usize;
+            }
+            energy.energy(&system)
+        }).collect();
+    }
+
+    /// Normalised Boltzmann distribution over states of given energies
+    fn boltzmann(energies: &[f64], temperature: f64) -> Vec<f64> {
+        let weights: Vec<f64> = energies.iter().map(|e| (-e / temperature).exp()).collect();
+        let z: f64 = weights.iter().sum();
+        return weights.iter().map(|w| w / z).collect();
+    }
+
+    /// Total variation distance between the distribution of states visited with single spin flips accepted
+    /// by a given criterion and the `exact` one
+    fn sampled_distance<T: AcceptanceCriterion + 'static>(criterion: T, dims: &[usize],
+            energy: &dyn Energy<SpinLattice>, exact: &[f64], seed: u64) -> f64 {
+        return distance_with_mover(criterion, Box::new(SpinFlipMover::new()), dims, 2, energy, exact, seed);
+    }
+
+    /// Total variation distance between the distribution of states visited with moves of a given mover, accepted
+    /// by a given criterion, and the `exact` one
+    fn distance_with_mover<T: AcceptanceCriterion + 'static>(criterion: T, mover: Box<dyn Mover<SpinLattice>>,
+            dims: &[usize], q: u8, energy: &dyn Energy<SpinLattice>, exact: &[f64], seed: u64) -> f64 {
+        let n_sweeps = 60000;
+        let mut streams = RandomStreams::new(seed);
+        let mut sampler = MCProtocol::with_streams(criterion, streams.split());
+        sampler.add_mover(mover);
+        let mut system = SpinLattice::new(dims, q).unwrap();
+        system.randomize(&mut streams.next_stream());
+        sampler.make_sweeps(100, &mut system, energy).unwrap();
+        let mut histogram = vec![0.0; exact.len()];
+        for _ in 0..n_sweeps {
+            sampler.make_sweeps(1, &mut system, energy).unwrap();
+            histogram[state_index(&system)] += 1.0 / n_sweeps as f64;
+        }
+        return 0.5 * histogram.iter().zip(exact.iter()).map(|(h, p)| (h - p).abs()).sum::<f64>();
+    }
+
+    #[test]
+    fn criteria_sample_boltzmann_distribution() {
+        let dims = [3, 3];
+        let energy = IsingEnergy::new(1.0, 0.2);
+        let energies = state_energies(&dims, 2, &energy);
+        for temperature in [2.0, 3.0] {
+            let exact = boltzmann(&energies, temperature);
+            let distances = [
+                sampled_distance(MetropolisCriterion::new(temperature), &dims, &energy, &exact, 1),
+                sampled_distance(BarkerCriterion::new(temperature).unwrap(), &dims, &energy, &exact, 2),
+                sampled_distance(TsallisCriterion::new(temperature, 1.0).unwrap(), &dims, &energy, &exact, 3),
+            ];
+            for d in distances { assert!(d < 0.05, "T = {}: distance {}", temperature, d); }
+        }
+    }
+
+    #[test]
+    fn tsallis_criterion_weights_two_level_systems() {
+        // --- for independent spins in a field, p(high) / p(low) equals the acceptance probability of a flip up
+        let (dims, field) = ([2], 0.5);
+        let free_spins = IsingEnergy::new(0.0, field);
+        let energies = state_energies(&dims, 2, &free_spins);
+        let e_min = energies.iter().cloned().fold(f64::INFINITY, f64::min);
+        for q in [0.5, 1.5, 2.0, 3.0] {
+            let criterion = TsallisCriterion::new(1.0, q).unwrap();
+            let uphill = criterion.probability(2.0 * field);
+            let weights: Vec<f64> = energies.iter()
+                .map(|e| uphill.powf(((e - e_min) / (2.0 * field)).round())).collect();
+            let z: f64 = weights.iter().sum();
+            let exact: Vec<f64> = weights.iter().map(|w| w / z).collect();
+            let d = sampled_distance(criterion, &dims, &free_spins, &exact, 4);
+            assert!(d < 0.02, "q = {}: distance {}", q, d);
+        }
+    }
+
+    #[test]
+    fn heat_bath_samples_potts_spins() {
+        // --- a ring of five 3-state Potts spins, where a Barker spin flip isn't a heat-bath update
+        let (dims, q) = ([5], 3);
+        let energy = PottsEnergy::new(1.0, 0.3);
+        let energies = state_energies(&dims, q, &energy);
+        for temperature in [0.8, 2.0] {
+            let exact = boltzmann(&energies, temperature);
+            // --- the sampler sets the temperature of the mover, created for a wrong one
+            let mover = Box::new(HeatBathMover::new(Arc::new(energy.clone()), 1.0));
+            let d = distance_with_mover(MetropolisCriterion::new(temperature), mover, &dims, q, &energy, &exact, 6);
+            assert!(d < 0.04, "T = {}: distance {}", temperature, d);
+        }
+    }
+
+    #[test]
+    fn greedy_runs_end_in_local_minima() {
+        let dims = [3, 3];
+        let energy = IsingEnergy::new(1.0, 0.2);
+        let energies = state_energies(&dims, 2, &energy);
+        let mut streams = RandomStreams::new(5);
+        let mut n_uphill = 0;
+        for _ in 0..50 {
+            let mut sampler = MCProtocol::with_streams(ZeroTemperatureCriterion::new(), streams.split());
+            sampler.add_mover(Box::new(SpinFlipMover::new()));
+            let mut system = SpinLattice::new(&dims, 2).unwrap();
+            system.randomize(&mut streams.next_stream());
+            let mut last = energy.energy(&system);
+            for _ in 0..100 {
+                sampler.make_sweeps(1, &mut system, &energy).unwrap();
+                let en = energy.energy(&system);
+                if en > last + 1e-9 { n_uphill += 1; }
+                last = en;
+            }
+            let index = state_index(&system);
+            assert!((0..system.size()).all(|i| energies[index ^ (1 << i)] >= last - 1e-9));
+        }
+        assert_eq!(n_uphill, 0);
+    }
+
+    #[test]
+    fn temperature_must_be_positive() {
+        for t in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(BarkerCriterion::new(t).is_err());
+            assert!(TsallisCriterion::new(t, 1.5).is_err());
+        }
+        // --- at zero temperature set later, the probabilities are the zero-temperature limits, not NaN
+        let mut barker = BarkerCriterion::new(1.0).unwrap();
+        barker.set_temperature(0.0);
+        assert_eq!(barker.probability(0.0), 0.5);
+        assert_eq!(barker.probability(1.0), 0.0);
+        assert_eq!(barker.probability(-1.0), 1.0);
+        let mut tsallis = TsallisCriterion::new(1.0, 0.5).unwrap();
+        tsallis.set_temperature(0.0);
+        assert_eq!(tsallis.probability(1.0), 0.0);
+    }
+}
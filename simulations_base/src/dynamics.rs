@@ -0,0 +1,226 @@
+use std::sync::Arc;
+
+use crate::{gaussian, AcceptanceCriterion, AcceptanceStatistics, ChangedPositions, Energy, MetropolisCriterion, Mover,
+            RandomStream, System};
+
+/// A [`System`] described by real-valued degrees of freedom, which can be integrated in time.
+///
+/// Degrees of freedom are indexed from 0 to `dof() - 1`, e.g. `x, y, z` of the first atom, then of the second one, etc.
+pub trait ContinuousSystem: System {
+    /// Number of degrees of freedom, e.g. `3 * N` for `N` atoms in three dimensions
+    fn dof(&self) -> usize;
+    fn coordinate(&self, i: usize) -> f64;
+    fn set_coordinate(&mut self, i: usize, value: f64);
+}
+
+/// An [`Energy`] function that also provides forces, i.e. the negative gradient of the energy
+pub trait Force<S: ContinuousSystem>: Energy<S> {
+    /// Computes the force acting along every degree of freedom and returns the energy of the system.
+    ///
+    /// The `forces` slice holds `system.dof()` values; they are overwritten.
+    fn forces(&self, system: &S, forces: &mut [f64]) -> f64;
+}
+
+/// Kinetic energy of unit masses moving with given velocities
+pub fn kinetic_energy(velocities: &[f64]) -> f64 { 0.5 * velocities.iter().map(|v| v * v).sum::<f64>() }
+
+/// Draws velocities of unit masses from the Maxwell–Boltzmann distribution at a given temperature
+pub fn maxwell_boltzmann(velocities: &mut [f64], temperature: f64, rng: &mut RandomStream) {
+    let sigma = temperature.sqrt();
+    for v in velocities.iter_mut() { *v = sigma * gaussian(rng); }
+}
+
+fn add_scaled<S: ContinuousSystem>(system: &mut S, velocities: &[f64], dt: f64) {
+    for (i, v) in velocities.iter().enumerate() { system.set_coordinate(i, system.coordinate(i) + dt * v); }
+}
+
+fn kick(velocities: &mut [f64], forces: &[f64], dt: f64) {
+    for (v, f) in velocities.iter_mut().zip(forces.iter()) { *v += dt * f; }
+}
+
+/// Velocity Verlet integrator of Newton's equations of motion for unit masses.
+///
+/// The integrator is time-reversible and preserves volume in phase space, which makes it suitable for
+/// [`HmcMover`].
+#[derive(Clone, Debug)]
+pub struct VelocityVerlet {
+    pub time_step: f64,
+}
+
+impl VelocityVerlet {
+    pub fn new(time_step: f64) -> VelocityVerlet { VelocityVerlet { time_step } }
+
+    /// Makes `n_steps` steps and returns the final potential energy.
+    ///
+    /// On input `forces` must hold forces for the current coordinates; on output they are updated,
+    /// so subsequent calls may continue the same trajectory.
+    pub fn run<S: ContinuousSystem>(&self, system: &mut S, velocities: &mut [f64], forces: &mut [f64],
+                                    force: &dyn Force<S>, n_steps: usize) -> f64 {
+        let dt = self.time_step;
+        let mut en = f64::NAN;
+        for _ in 0..n_steps {
+            kick(velocities, forces, 0.5 * dt);
+            add_scaled(system, velocities, dt);
+            en = force.forces(system, forces);
+            kick(velocities, forces, 0.5 * dt);
+        }
+        return en;
+    }
+}
+
+/// Langevin dynamics integrator for unit masses, using the BAOAB splitting of Leimkuhler and Matthews.
+///
+/// Samples the canonical distribution at a given temperature; `friction` is the collision frequency `gamma`.
+#[derive(Clone, Debug)]
+pub struct LangevinIntegrator {
+    pub time_step: f64,
+    pub friction: f64,
+    pub temperature: f64,
+}
+
+impl LangevinIntegrator {
+    pub fn new(time_step: f64, friction: f64, temperature: f64) -> LangevinIntegrator {
+        LangevinIntegrator { time_step, friction, temperature }
+    }
+
+    /// Makes `n_steps` steps and returns the final potential energy.
+    ///
+    /// As for [`VelocityVerlet::run()`], `forces` must hold forces for the current coordinates.
+    pub fn run<S: ContinuousSystem>(&self, system: &mut S, velocities: &mut [f64], forces: &mut [f64],
+                                    force: &dyn Force<S>, n_steps: usize, rng: &mut RandomStream) -> f64 {
+        let dt = self.time_step;
+        let c1 = (-self.friction * dt).exp();
+        let c2 = ((1.0 - c1 * c1) * self.temperature).sqrt();
+        let mut en = f64::NAN;
+        for _ in 0..n_steps {
+            kick(velocities, forces, 0.5 * dt);
+            add_scaled(system, velocities, 0.5 * dt);
+            for v in velocities.iter_mut() { *v = c1 * *v + c2 * gaussian(rng); }
+            add_scaled(system, velocities, 0.5 * dt);
+            en = force.forces(system, forces);
+            kick(velocities, forces, 0.5 * dt);
+        }
+        return en;
+    }
+}
+
+/// Hybrid Monte Carlo mover.
+///
+/// Each move draws random velocities, integrates a short [`VelocityVerlet`] trajectory and accepts or rejects
+/// its end point with [`MetropolisCriterion`] applied to the total (potential and kinetic) energy; a rejected
+/// trajectory is reverted by the sampler.
+/// The mover accepts its own moves (see [`Mover::self_accepting()`]) with its own `criterion`, which follows
+/// the temperature of the sampler the mover is added to (see [`Mover::set_temperature()`]). The time step is the `max_range()` of this mover,
+/// so it may be tuned by [`AdaptiveMCProtocol`](crate::AdaptiveMCProtocol).
+///
+/// A move changes every position of a system; in [`MCProtocol`](crate::MCProtocol) give this mover
+/// a weight of `1.0 / size()` to make a single trajectory per sweep.
+pub struct HmcMover<S: ContinuousSystem> {
+    pub n_steps: usize,
+    pub criterion: MetropolisCriterion,
+    integrator: VelocityVerlet,
+    force: Arc<dyn Force<S>>,
+    succ_rate: AcceptanceStatistics,
+    velocities: Vec<f64>,
+    forces: Vec<f64>,
+}
+
+impl<S: ContinuousSystem> HmcMover<S> {
+    pub fn new(force: Arc<dyn Force<S>>, time_step: f64, n_steps: usize, temperature: f64) -> HmcMover<S> {
+        HmcMover { n_steps, criterion: MetropolisCriterion::new(temperature), integrator: VelocityVerlet::new(time_step),
+            force, succ_rate: Default::default(), velocities: vec![], forces: vec![] }
+    }
+}
+
+impl<S: ContinuousSystem> Mover<S> for HmcMover<S> {
+
+    fn perturb(&mut self, system: &mut S, rng: &mut RandomStream) -> ChangedPositions {
+        let n = system.dof();
+        self.velocities.resize(n, 0.0);
+        self.forces.resize(n, 0.0);
+
+        maxwell_boltzmann(&mut self.velocities, self.criterion.temperature(), rng);
+        let en_before = self.force.forces(system, &mut self.forces) + kinetic_energy(&self.velocities);
+        let en_after = self.integrator.run(system, &mut self.velocities, &mut self.forces, self.force.as_ref(),
+                                           self.n_steps) + kinetic_energy(&self.velocities);
+        // --- a NaN energy, e.g. after a diverged trajectory, rejects the move
+        if en_after.is_finite() && self.criterion.check(en_before, en_after, rng) {
+            return ChangedPositions::Range(0..system.size());
+        }
+        return ChangedPositions::Range(0..0);
+    }
+
+    fn acceptance_statistics(&self) -> AcceptanceStatistics { self.succ_rate.clone() }
+
+    fn add_success(&mut self) { self.succ_rate.n_succ += 1; }
+
+    fn add_failure(&mut self) { self.succ_rate.n_failed += 1; }
+
+    fn set_acceptance_statistics(&mut self, stats: AcceptanceStatistics) { self.succ_rate = stats; }
+
+    fn max_range(&self) -> f64 { self.integrator.time_step }
+
+    fn set_max_range(&mut self, new_val: f64) { self.integrator.time_step = new_val; }
+
+    fn self_accepting(&self) -> bool { true }
+
+    fn set_temperature(&mut self, temperature: f64) { self.criterion.set_temperature(temperature); }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{ContinuousSystem, Energy, Force, HmcMover, MCProtocol, MetropolisCriterion, MoversSet, RandomStreams,
+                Sampler, System};
+
+    /// Independent harmonic oscillators with energy `x^2 / 2` each, so `<x^2> = T`
+    #[derive(Clone)]
+    struct Oscillators { x: Vec<f64> }
+
+    impl System for Oscillators {
+        fn size(&self) -> usize { self.x.len() }
+        fn copy_from(&mut self, i: usize, rhs: &Self) { self.x[i] = rhs.x[i]; }
+    }
+
+    impl ContinuousSystem for Oscillators {
+        fn dof(&self) -> usize { self.x.len() }
+        fn coordinate(&self, i: usize) -> f64 { self.x[i] }
+        fn set_coordinate(&mut self, i: usize, value: f64) { self.x[i] = value; }
+    }
+
+    struct Harmonic;
+
+    impl Energy<Oscillators> for Harmonic {
+        fn energy(&self, system: &Oscillators) -> f64 { system.x.iter().map(|x| 0.5 * x * x).sum() }
+        fn energy_by_pos(&self, system: &Oscillators, pos: usize) -> f64 { 0.5 * system.x[pos] * system.x[pos] }
+        fn delta_energy_by_pos(&self, old: &Oscillators, new: &Oscillators, pos: usize) -> (f64, f64) {
+            (self.energy_by_pos(old, pos), self.energy_by_pos(new, pos))
+        }
+    }
+
+    impl Force<Oscillators> for Harmonic {
+        fn forces(&self, system: &Oscillators, forces: &mut [f64]) -> f64 {
+            for (f, x) in forces.iter_mut().zip(system.x.iter()) { *f = -x; }
+            return self.energy(system);
+        }
+    }
+
+    #[test]
+    fn hmc_follows_the_sampler_temperature() {
+        let force = Arc::new(Harmonic);
+        let mut system = Oscillators { x: vec![0.0; 20] };
+        let mut sampler = MCProtocol::with_streams(MetropolisCriterion::new(1.0), RandomStreams::new(5));
+        sampler.add_mover(Box::new(HmcMover::new(force.clone(), 0.3, 10, 1.0)));
+        sampler.set_temperature(4.0);
+        sampler.make_sweeps(20, &mut system, force.as_ref()).unwrap();
+        let mut x2 = 0.0;
+        let n = 200;
+        for _ in 0..n {
+            sampler.make_sweeps(1, &mut system, force.as_ref()).unwrap();
+            x2 += system.x.iter().map(|x| x * x).sum::<f64>() / system.size() as f64;
+        }
+        let x2 = x2 / n as f64;
+        assert!((x2 - 4.0).abs() < 0.4, "<x^2> = {} at T = 4", x2);
+    }
+}
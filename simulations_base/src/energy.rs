@@ -1,6 +1,139 @@
+use crate::{ChangedPositions, SimulationError};
+use crate::error::check_term_index;
 
-pub trait Energy<S> {
+/// Energy functions are `Send + Sync`; a single energy function may be shared by chains simulated in parallel
+pub trait Energy<S>: Send + Sync {
     fn energy(&self, system: &S) -> f64;
     fn energy_by_pos(&self, system: &S, pos: usize) -> f64;
     fn delta_energy_by_pos(&self, old_system: &S, new_system: &S, pos: usize) -> (f64, f64);
-}
\ No newline at end of file
+
+    /// Energy before and after a move that changed the given positions; only the difference between
+    /// the two values is meaningful.
+    ///
+    /// The default implementation calls [`delta_energy_by_pos()`](Energy::delta_energy_by_pos) when a single
+    /// position has been changed and evaluates the total energy of both systems otherwise. Energy functions
+    /// may override it with a faster, local evaluation.
+    fn delta_energy(&self, old_system: &S, new_system: &S, changed: &ChangedPositions) -> (f64, f64) {
+        if changed.is_empty() { return (0.0, 0.0); }
+        if changed.len() == 1 {
+            let pos = changed.iter().next().unwrap();
+            return self.delta_energy_by_pos(old_system, new_system, pos);
+        }
+        return (self.energy(old_system), self.energy(new_system));
+    }
+
+    /// Energy of the given positions of a system; only differences of these values, computed for the same positions
+    /// before and after a move, are meaningful.
+    ///
+    /// Used to evaluate in-place moves, when the old and the new state are never stored at the same time.
+    /// The default implementation calls [`energy_by_pos()`](Energy::energy_by_pos) for a single position and
+    /// evaluates the total energy otherwise.
+    fn energy_of_positions(&self, system: &S, changed: &ChangedPositions) -> f64 {
+        if changed.is_empty() { return 0.0; }
+        if changed.len() == 1 { return self.energy_by_pos(system, changed.iter().next().unwrap()); }
+        return self.energy(system);
+    }
+}
+
+/// Energy of a system expressed as a weighted sum of named terms.
+///
+/// Each term is an [`Energy`] on its own, e.g. bond stretching or a contact potential;
+/// the total is `sum_i w_i E_i`. Since `TotalEnergy` implements [`Energy`], it can be used wherever
+/// a single energy function is expected.
+pub struct TotalEnergy<S> {
+    names: Vec<String>,
+    weights: Vec<f64>,
+    terms: Vec<Box<dyn Energy<S>>>,
+}
+
+impl<S> TotalEnergy<S> {
+    pub fn new() -> TotalEnergy<S> { TotalEnergy { names: vec![], weights: vec![], terms: vec![] } }
+
+    /// Appends a term to this energy function
+    pub fn add_term(&mut self, name: &str, weight: f64, term: Box<dyn Energy<S>>) {
+        self.names.push(name.to_string());
+        self.weights.push(weight);
+        self.terms.push(term);
+    }
+
+    pub fn count_terms(&self) -> usize { self.terms.len() }
+
+    /// Names of all the terms, in the order they were added
+    pub fn names(&self) -> &Vec<String> { &self.names }
+
+    /// Weight of a term or [`SimulationError::TermIndex`] when there's no term of that index
+    pub fn weight(&self, which_one: usize) -> Result<f64, SimulationError> {
+        check_term_index(which_one, self.terms.len())?;
+        Ok(self.weights[which_one])
+    }
+
+    pub fn set_weight(&mut self, which_one: usize, weight: f64) -> Result<(), SimulationError> {
+        check_term_index(which_one, self.terms.len())?;
+        self.weights[which_one] = weight;
+        Ok(())
+    }
+
+    /// Returns the index of a term of the given name
+    pub fn find_term(&self, name: &str) -> Option<usize> { self.names.iter().position(|n| n == name) }
+
+    pub fn get_term(&self, which_one: usize) -> Result<&dyn Energy<S>, SimulationError> {
+        check_term_index(which_one, self.terms.len())?;
+        Ok(self.terms[which_one].as_ref())
+    }
+
+    /// Weighted energy of every term, in the order the terms were added; these values sum up to the total energy
+    pub fn breakdown(&self, system: &S) -> Vec<f64> {
+        self.terms.iter().zip(self.weights.iter()).map(|(term, w)| w * term.energy(system)).collect()
+    }
+}
+
+impl<S> Default for TotalEnergy<S> {
+    fn default() -> Self { TotalEnergy::new() }
+}
+
+impl<S> Energy<S> for TotalEnergy<S> {
+    fn energy(&self, system: &S) -> f64 { self.breakdown(system).iter().sum() }
+
+    fn energy_by_pos(&self, system: &S, pos: usize) -> f64 {
+        self.terms.iter().zip(self.weights.iter()).map(|(term, w)| w * term.energy_by_pos(system, pos)).sum()
+    }
+
+    fn delta_energy_by_pos(&self, old_system: &S, new_system: &S, pos: usize) -> (f64, f64) {
+        let (mut en_old, mut en_new) = (0.0, 0.0);
+        for (term, w) in self.terms.iter().zip(self.weights.iter()) {
+            let (old, new) = term.delta_energy_by_pos(old_system, new_system, pos);
+            en_old += w * old;
+            en_new += w * new;
+        }
+        return (en_old, en_new);
+    }
+
+    fn delta_energy(&self, old_system: &S, new_system: &S, changed: &ChangedPositions) -> (f64, f64) {
+        let (mut en_old, mut en_new) = (0.0, 0.0);
+        for (term, w) in self.terms.iter().zip(self.weights.iter()) {
+            let (old, new) = term.delta_energy(old_system, new_system, changed);
+            en_old += w * old;
+            en_new += w * new;
+        }
+        return (en_old, en_new);
+    }
+
+    fn energy_of_positions(&self, system: &S, changed: &ChangedPositions) -> f64 {
+        self.terms.iter().zip(self.weights.iter()).map(|(term, w)| w * term.energy_of_positions(system, changed)).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{IsingEnergy, SimulationError, SpinLattice, TotalEnergy};
+
+    #[test]
+    fn term_index_out_of_range_is_reported() {
+        let mut energy: TotalEnergy<SpinLattice> = TotalEnergy::new();
+        energy.add_term("ising", 1.0, Box::new(IsingEnergy::new(1.0, 0.0)));
+        assert!(matches!(energy.weight(1), Err(SimulationError::TermIndex { index: 1, count: 1 })));
+        assert!(matches!(energy.set_weight(2, 0.5), Err(SimulationError::TermIndex { index: 2, count: 1 })));
+        assert_eq!(energy.get_term(1).err().unwrap().to_string(),
+                   "energy term index 1 out of range, the energy has 1 terms");
+    }
+}
@@ -0,0 +1,77 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::ChangedPositions;
+
+/// A move which energy change, evaluated for the changed positions only, differs from the difference
+/// of total energies of the system before and after that move
+#[derive(Clone, Debug)]
+pub struct EnergyInconsistency {
+    /// index of the mover that made the move
+    pub mover: usize,
+    /// positions changed by the move
+    pub changed: ChangedPositions,
+    /// energy change evaluated by [`delta_energy()`](crate::Energy::delta_energy)
+    pub local_delta: f64,
+    /// total energy of the system before the move
+    pub total_before: f64,
+    /// total energy of the system after the move
+    pub total_after: f64,
+}
+
+/// Errors reported by samplers and simulation drivers
+#[derive(Debug)]
+pub enum SimulationError {
+    /// energy change of a move is inconsistent with the total energy
+    InconsistentEnergy(EnergyInconsistency),
+    /// a mover was requested by an index that exceeds the number of movers
+    MoverIndex { index: usize, count: usize },
+    /// an energy term was requested by an index that exceeds the number of terms
+    TermIndex { index: usize, count: usize },
+    /// a sampler or a criterion can't be created with the given parameters
+    InvalidParameter(String),
+    /// a run description can't be parsed or refers to unknown components
+    Config(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimulationError::InconsistentEnergy(e) => write!(f,
+                "Inconsistent energy! Total {} -> {} with delta = {}, local delta: {} after move {:?} of mover {}",
+                e.total_before, e.total_after, e.total_after - e.total_before, e.local_delta, e.changed, e.mover),
+            SimulationError::MoverIndex { index, count } =>
+                write!(f, "mover index {} out of range, the sampler has {} movers", index, count),
+            SimulationError::TermIndex { index, count } =>
+                write!(f, "energy term index {} out of range, the energy has {} terms", index, count),
+            SimulationError::InvalidParameter(msg) => write!(f, "invalid parameter: {}", msg),
+            SimulationError::Config(msg) => write!(f, "configuration error: {}", msg),
+            SimulationError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl Error for SimulationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SimulationError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SimulationError {
+    fn from(e: io::Error) -> Self { SimulationError::Io(e) }
+}
+
+/// Checks whether a mover index is valid for a set of `count` movers
+pub(crate) fn check_mover_index(index: usize, count: usize) -> Result<(), SimulationError> {
+    if index < count { Ok(()) } else { Err(SimulationError::MoverIndex { index, count }) }
+}
+
+/// Checks whether an energy term index is valid for a sum of `count` terms
+pub(crate) fn check_term_index(index: usize, count: usize) -> Result<(), SimulationError> {
+    if index < count { Ok(()) } else { Err(SimulationError::TermIndex { index, count }) }
+}
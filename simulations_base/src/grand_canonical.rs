@@ -0,0 +1,100 @@
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::{AcceptanceStatistics, ChangedPositions, Energy, Mover, RandomStream, VolumeSystem};
+
+/// A system which particles may be inserted and removed, e.g. in the grand canonical ensemble.
+///
+/// The number of particles is given by [`size()`](crate::System::size).
+pub trait VariableSizeSystem: VolumeSystem {
+    /// Adds a particle at a position drawn uniformly from the simulation box; the new particle is the last one
+    fn insert_random(&mut self, rng: &mut RandomStream);
+    /// Removes a given particle; the last particle may be moved to its position
+    fn remove(&mut self, pos: usize);
+}
+
+/// Acceptance criterion of the grand canonical (μVT) ensemble.
+///
+/// The thermal wavelength is set to 1.0, so the activity is `z = exp(μ / T)`. Inserting a particle into a system
+/// of `N` particles is accepted with probability `min(1, z V / (N + 1) exp(-ΔE / T))`, removing one with
+/// probability `min(1, N / (z V) exp(-ΔE / T))`.
+#[derive(Clone, Debug)]
+pub struct GrandCanonicalCriterion {
+    pub chemical_potential: f64,
+    pub temperature: f64,
+}
+
+impl GrandCanonicalCriterion {
+    pub fn new(chemical_potential: f64, temperature: f64) -> GrandCanonicalCriterion {
+        GrandCanonicalCriterion { chemical_potential, temperature }
+    }
+
+    /// Checks an insertion which changes the energy by `delta_en`; `n` is the number of particles before it
+    pub fn check_insertion(&self, delta_en: f64, volume: f64, n: usize, rng: &mut RandomStream) -> bool {
+        let arg = (self.chemical_potential - delta_en) / self.temperature + (volume / (n + 1) as f64).ln();
+        return arg >= 0.0 || rng.gen_range(0.0..1.0) < arg.exp();
+    }
+
+    /// Checks a removal which changes the energy by `delta_en`; `n` is the number of particles before it
+    pub fn check_removal(&self, delta_en: f64, volume: f64, n: usize, rng: &mut RandomStream) -> bool {
+        if n == 0 { return false; }
+        let arg = -(self.chemical_potential + delta_en) / self.temperature + (n as f64 / volume).ln();
+        return arg >= 0.0 || rng.gen_range(0.0..1.0) < arg.exp();
+    }
+}
+
+/// Inserts or removes a single particle, each with probability 0.5.
+///
+/// The energy change is the energy of the inserted or removed particle, given by [`Energy::energy_by_pos()`].
+/// The move is accepted by its own [`GrandCanonicalCriterion`] (see [`Mover::self_accepting()`]), so the chemical
+/// potential is set by the `criterion` field of this mover; its temperature follows the temperature of the sampler
+/// the mover is added to (see [`Mover::set_temperature()`]). The mover has no range to adjust.
+pub struct InsertRemoveMover<S: VariableSizeSystem> {
+    pub criterion: GrandCanonicalCriterion,
+    energy: Arc<dyn Energy<S>>,
+    succ_rate: AcceptanceStatistics,
+}
+
+impl<S: VariableSizeSystem> InsertRemoveMover<S> {
+    pub fn new(energy: Arc<dyn Energy<S>>, chemical_potential: f64, temperature: f64) -> InsertRemoveMover<S> {
+        InsertRemoveMover { criterion: GrandCanonicalCriterion::new(chemical_potential, temperature), energy,
+            succ_rate: Default::default() }
+    }
+}
+
+impl<S: VariableSizeSystem> Mover<S> for InsertRemoveMover<S> {
+
+    fn perturb(&mut self, system: &mut S, rng: &mut RandomStream) -> ChangedPositions {
+        let n = system.size();
+        if rng.gen_bool(0.5) {
+            system.insert_random(rng);
+            let delta_en = self.energy.energy_by_pos(system, n);
+            if self.criterion.check_insertion(delta_en, system.volume(), n, rng) { return ChangedPositions::single(n); }
+        } else if n > 0 {
+            let pos = rng.gen_range(0..n);
+            let delta_en = -self.energy.energy_by_pos(system, pos);
+            if self.criterion.check_removal(delta_en, system.volume(), n, rng) {
+                system.remove(pos);
+                return ChangedPositions::Range(pos..n);
+            }
+        }
+        return ChangedPositions::Range(0..0);
+    }
+
+    fn acceptance_statistics(&self) -> AcceptanceStatistics { self.succ_rate.clone() }
+
+    fn add_success(&mut self) { self.succ_rate.n_succ += 1; }
+
+    fn add_failure(&mut self) { self.succ_rate.n_failed += 1; }
+
+    fn set_acceptance_statistics(&mut self, stats: AcceptanceStatistics) { self.succ_rate = stats; }
+
+    fn max_range(&self) -> f64 { 0.0 }
+
+    fn set_max_range(&mut self, _new_val: f64) {}
+
+    fn self_accepting(&self) -> bool { true }
+
+    fn set_temperature(&mut self, temperature: f64) { self.criterion.temperature = temperature; }
+}
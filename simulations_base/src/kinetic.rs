@@ -0,0 +1,333 @@
+use rand::Rng;
+
+use crate::{RandomStream, RandomStreams, SimulationError, System};
+
+/// A system that evolves by discrete events, each of them occurring at a known rate.
+///
+/// Events are identified by indexes from `0` to `count_events() - 1`; an event that can't occur in the current
+/// state has rate zero. The number of events must not change while the system is simulated, unless
+/// [`KineticMC::refresh()`] is called.
+pub trait KineticSystem: System {
+    fn count_events(&self) -> usize;
+    /// Rate of a given event, i.e. the probability per unit time that it occurs
+    fn rate(&self, event: usize) -> f64;
+    /// Executes an event and returns the events whose rates may have changed, including the executed one
+    fn execute(&mut self, event: usize) -> Vec<usize>;
+}
+
+/// Rates summed in a complete binary tree: both updating a rate and selecting an event take `O(log n)` time
+#[derive(Clone, Debug)]
+pub struct RateTree {
+    n: usize,
+    /// index of the first leaf; node `k` has children `2k` and `2k + 1`, the root is node 1
+    first_leaf: usize,
+    sums: Vec<f64>,
+}
+
+impl RateTree {
+    /// Creates a tree of `n` events, all of rate zero
+    pub fn new(n: usize) -> RateTree {
+        let first_leaf = n.next_power_of_two();
+        RateTree { n, first_leaf, sums: vec![0.0; 2 * first_leaf] }
+    }
+
+    pub fn from_rates(rates: &[f64]) -> RateTree {
+        let mut tree = RateTree::new(rates.len());
+        tree.sums[tree.first_leaf..tree.first_leaf + rates.len()].copy_from_slice(rates);
+        for k in (1..tree.first_leaf).rev() { tree.sums[k] = tree.sums[2 * k] + tree.sums[2 * k + 1]; }
+        return tree;
+    }
+
+    pub fn len(&self) -> usize { self.n }
+
+    pub fn is_empty(&self) -> bool { self.n == 0 }
+
+    pub fn rate(&self, event: usize) -> f64 { self.sums[self.first_leaf + event] }
+
+    /// Sets the rate of an event; sums are recomputed from the children, so rounding errors don't accumulate
+    pub fn set(&mut self, event: usize, rate: f64) {
+        let mut k = self.first_leaf + event;
+        self.sums[k] = rate;
+        while k > 1 {
+            k /= 2;
+            self.sums[k] = self.sums[2 * k] + self.sums[2 * k + 1];
+        }
+    }
+
+    pub fn total(&self) -> f64 { self.sums[1] }
+
+    /// Finds the event at which the cumulative rate exceeds `x`, given between `0.0` and [`total()`](RateTree::total).
+    ///
+    /// An event of rate zero is never selected when the total rate is positive.
+    pub fn select(&self, mut x: f64) -> usize {
+        let mut k = 1;
+        while k < self.first_leaf {
+            let left = 2 * k;
+            if x < self.sums[left] || self.sums[left + 1] <= 0.0 {
+                k = left;
+            } else {
+                x -= self.sums[left];
+                k = left + 1;
+            }
+        }
+        return k - self.first_leaf;
+    }
+}
+
+/// State of a kinetic Monte Carlo run, passed to every [`KineticObserver`]
+pub struct KineticObservation<'a, S> {
+    /// physical time of this observation
+    pub time: f64,
+    /// number of events executed so far
+    pub n_events: usize,
+    /// sum of the rates of all the events in the current state
+    pub total_rate: f64,
+    pub system: &'a S,
+}
+
+/// Collects data from a kinetic Monte Carlo run at regular intervals of physical time
+pub trait KineticObserver<S>: Send {
+    fn observe(&mut self, observation: &KineticObservation<S>);
+    fn flush(&mut self) {}
+}
+
+/// Rejection-free kinetic Monte Carlo (the BKL or Gillespie algorithm).
+///
+/// Every step selects an event with probability proportional to its rate, using a [`RateTree`], executes it
+/// and advances the physical time by an exponentially distributed interval of mean `1 / R`, where `R` is the total
+/// rate. Only the rates of the events reported by [`execute()`](KineticSystem::execute) are recomputed.
+///
+/// Observers are called at every multiple of their time interval; since the system doesn't change between events,
+/// it's observed exactly at that time.
+pub struct KineticMC<S: KineticSystem> {
+    tree: RateTree,
+    time: f64,
+    n_events: usize,
+    rng: RandomStream,
+    /// observers with their time interval and the time of their next observation
+    observers: Vec<(f64, f64, Box<dyn KineticObserver<S>>)>,
+}
+
+fn check_rate(event: usize, rate: f64) -> Result<f64, SimulationError> {
+    if rate >= 0.0 && rate.is_finite() { return Ok(rate); }
+    Err(SimulationError::InvalidParameter(format!("event {} has invalid rate {}", event, rate)))
+}
+
+impl<S: KineticSystem> KineticMC<S> {
+    /// Creates the engine for a system at time 0.0, evaluating the rates of all its events
+    pub fn new(system: &S, mut streams: RandomStreams) -> Result<KineticMC<S>, SimulationError> {
+        let mut out = KineticMC { tree: RateTree::new(0), time: 0.0, n_events: 0, rng: streams.next_stream(),
+            observers: vec![] };
+        out.refresh(system)?;
+        Ok(out)
+    }
+
+    /// Evaluates the rates of all the events again, e.g. after the system has been changed outside of this engine
+    pub fn refresh(&mut self, system: &S) -> Result<(), SimulationError> {
+        let rates = (0..system.count_events()).map(|e| check_rate(e, system.rate(e))).collect::<Result<Vec<f64>, _>>()?;
+        self.tree = RateTree::from_rates(&rates);
+        Ok(())
+    }
+
+    /// Physical time elapsed so far
+    pub fn time(&self) -> f64 { self.time }
+
+    /// Number of events executed so far
+    pub fn count_events(&self) -> usize { self.n_events }
+
+    /// Sum of the rates of all the events in the current state
+    pub fn total_rate(&self) -> f64 { self.tree.total() }
+
+    pub fn rates(&self) -> &RateTree { &self.tree }
+
+    /// Registers an observer called every `interval` units of time, starting `interval` after the current time
+    pub fn add_observer(&mut self, observer: Box<dyn KineticObserver<S>>, interval: f64) -> Result<(), SimulationError> {
+        if interval <= 0.0 || !interval.is_finite() {
+            return Err(SimulationError::InvalidParameter(format!("invalid observation interval: {}", interval)));
+        }
+        self.observers.push((interval, self.time + interval, observer));
+        Ok(())
+    }
+
+    /// Selects and executes a single event, without advancing the time; returns `None` when no event can occur
+    fn execute(&mut self, system: &mut S) -> Result<Option<usize>, SimulationError> {
+        let total = self.tree.total();
+        if total <= 0.0 { return Ok(None); }
+        let event = self.tree.select(self.rng.gen_range(0.0..total));
+        for e in system.execute(event) {
+            if e >= self.tree.len() {
+                return Err(SimulationError::InvalidParameter(format!("event {} out of range of {} events", e,
+                    self.tree.len())));
+            }
+            self.tree.set(e, check_rate(e, system.rate(e))?);
+        }
+        self.n_events += 1;
+        Ok(Some(event))
+    }
+
+    /// Time until the next event, drawn from the exponential distribution; infinite when no event can occur
+    fn waiting_time(&mut self) -> f64 {
+        let total = self.tree.total();
+        if total <= 0.0 { return f64::INFINITY; }
+        let u: f64 = self.rng.gen_range(0.0..1.0);
+        return -(1.0 - u).ln() / total;
+    }
+
+    /// Executes a single event and advances the time; returns the executed event or `None` when no event can occur
+    pub fn step(&mut self, system: &mut S) -> Result<Option<usize>, SimulationError> {
+        let t_next = self.time + self.waiting_time();
+        if t_next.is_infinite() { return Ok(None); }
+        self.observe_until(t_next, system);
+        self.time = t_next;
+        self.execute(system)
+    }
+
+    /// Runs the simulation for a given period of time, calling observers on the way.
+    ///
+    /// The last event that would occur after the end of the period isn't executed; as the waiting times are
+    /// exponential, the next run continues the same stochastic process.
+    pub fn run(&mut self, system: &mut S, duration: f64) -> Result<(), SimulationError> {
+        let t_end = self.time + duration;
+        loop {
+            let t_next = self.time + self.waiting_time();
+            self.observe_until(t_next.min(t_end), system);
+            if t_next > t_end { break; }
+            self.time = t_next;
+            self.execute(system)?;
+        }
+        self.time = t_end;
+        for (_, _, observer) in self.observers.iter_mut() { observer.flush(); }
+        Ok(())
+    }
+
+    /// Calls every observer whose next observation is due not later than `limit`, in the order of time
+    fn observe_until(&mut self, limit: f64, system: &S) {
+        loop {
+            let next = self.observers.iter().map(|(_, next, _)| *next).fold(f64::INFINITY, f64::min);
+            if next > limit { return; }
+            self.time = next;
+            let observation = KineticObservation { time: next, n_events: self.n_events, total_rate: self.tree.total(),
+                system };
+            for (interval, t, observer) in self.observers.iter_mut() {
+                if *t <= next {
+                    observer.observe(&observation);
+                    *t += *interval;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{KineticMC, KineticObservation, KineticObserver, KineticSystem, RandomStreams, RateTree, System};
+
+    /// Independent events of constant rates, each counting how many times it was executed
+    #[derive(Clone)]
+    struct Counters {
+        rates: Vec<f64>,
+        counts: Vec<usize>,
+    }
+
+    impl System for Counters {
+        fn size(&self) -> usize { self.rates.len() }
+
+        fn copy_from(&mut self, i: usize, rhs: &Self) { self.counts[i] = rhs.counts[i]; }
+    }
+
+    impl KineticSystem for Counters {
+        fn count_events(&self) -> usize { self.rates.len() }
+
+        fn rate(&self, event: usize) -> f64 { self.rates[event] }
+
+        fn execute(&mut self, event: usize) -> Vec<usize> {
+            self.counts[event] += 1;
+            vec![event]
+        }
+    }
+
+    /// Records the time and the number of events of every observation
+    struct Recorder(Arc<Mutex<Vec<(f64, usize)>>>);
+
+    impl KineticObserver<Counters> for Recorder {
+        fn observe(&mut self, observation: &KineticObservation<Counters>) {
+            self.0.lock().unwrap().push((observation.time, observation.n_events));
+        }
+    }
+
+    #[test]
+    fn select_follows_cumulative_rates() {
+        let tree = RateTree::from_rates(&[1.0, 0.0, 2.0, 3.0]);
+        assert_eq!(tree.total(), 6.0);
+        assert_eq!(tree.select(0.0), 0);
+        assert_eq!(tree.select(0.999), 0);
+        // --- the event of rate zero is skipped
+        assert_eq!(tree.select(1.0), 2);
+        assert_eq!(tree.select(2.999), 2);
+        assert_eq!(tree.select(3.0), 3);
+        assert_eq!(tree.select(5.999), 3);
+    }
+
+    #[test]
+    fn select_never_returns_padding_or_zero_rate_leaves() {
+        // --- three events padded to four leaves; x equal to the total may come from rounding
+        let tree = RateTree::from_rates(&[1.0, 2.0, 3.0]);
+        assert_eq!(tree.select(tree.total()), 2);
+        let mut tree = RateTree::from_rates(&[1.0, 2.0, 3.0, 4.0, 5.0]);
+        tree.set(4, 0.0);
+        tree.set(3, 0.0);
+        assert_eq!(tree.total(), 6.0);
+        assert_eq!(tree.select(tree.total()), 2);
+        tree.set(0, 0.0);
+        tree.set(2, 0.0);
+        for x in [0.0, 1.0, 1.999, 2.0] { assert_eq!(tree.select(x), 1); }
+    }
+
+    #[test]
+    fn events_are_selected_in_proportion_to_rates() {
+        let rates = [1.0, 0.0, 2.0, 3.0, 0.5];
+        let mut system = Counters { rates: rates.to_vec(), counts: vec![0; rates.len()] };
+        let mut kmc = KineticMC::new(&system, RandomStreams::new(3)).unwrap();
+        let n = 60000;
+        for _ in 0..n { kmc.step(&mut system).unwrap(); }
+        let total: f64 = rates.iter().sum();
+        for (rate, count) in rates.iter().zip(system.counts.iter()) {
+            let expected = rate / total;
+            let observed = *count as f64 / n as f64;
+            assert!((observed - expected).abs() < 0.01, "fraction {} instead of {}", observed, expected);
+        }
+        assert_eq!(system.counts[1], 0);
+    }
+
+    #[test]
+    fn observers_are_called_at_multiples_of_their_interval() {
+        let mut system = Counters { rates: vec![5.0, 5.0], counts: vec![0, 0] };
+        let mut kmc = KineticMC::new(&system, RandomStreams::new(7)).unwrap();
+        let records = Arc::new(Mutex::new(vec![]));
+        kmc.add_observer(Box::new(Recorder(records.clone())), 0.25).unwrap();
+        kmc.run(&mut system, 1.0).unwrap();
+        kmc.run(&mut system, 0.5).unwrap();
+        assert_eq!(kmc.time(), 1.5);
+        let records = records.lock().unwrap();
+        let times: Vec<f64> = records.iter().map(|(t, _)| *t).collect();
+        assert_eq!(times, vec![0.25, 0.5, 0.75, 1.0, 1.25, 1.5]);
+        // --- the last observation sees every event executed by both runs
+        assert!(records.windows(2).all(|w| w[0].1 <= w[1].1));
+        assert_eq!(records.last().unwrap().1, kmc.count_events());
+        assert_eq!(kmc.count_events(), system.counts.iter().sum::<usize>());
+    }
+
+    #[test]
+    fn system_without_events_is_still_observed() {
+        let mut system = Counters { rates: vec![0.0, 0.0], counts: vec![0, 0] };
+        let mut kmc = KineticMC::new(&system, RandomStreams::new(1)).unwrap();
+        let records = Arc::new(Mutex::new(vec![]));
+        kmc.add_observer(Box::new(Recorder(records.clone())), 0.5).unwrap();
+        kmc.run(&mut system, 2.0).unwrap();
+        assert_eq!(kmc.step(&mut system).unwrap(), None);
+        assert_eq!(records.lock().unwrap().iter().map(|(t, n)| (*t, *n)).collect::<Vec<_>>(),
+                   vec![(0.5, 0), (1.0, 0), (1.5, 0), (2.0, 0)]);
+    }
+}
@@ -1,7 +1,49 @@
+mod adaptive;
+mod annealing;
+mod checkpoint;
+mod config;
+mod criteria;
+mod dynamics;
 mod energy;
+mod error;
+mod grand_canonical;
+mod kinetic;
 mod system;
+mod metrics;
 mod montecarlo;
+mod nested;
+mod npt;
+mod observer;
+mod parallel;
+mod random;
+mod replica_exchange;
+mod spins;
+mod statistics;
+mod umbrella;
+mod undo;
+mod wang_landau;
 
-pub use energy::Energy;
+pub use adaptive::*;
+pub use annealing::*;
+pub use checkpoint::*;
+pub use config::*;
+pub use criteria::*;
+pub use dynamics::*;
+pub use energy::{Energy, TotalEnergy};
+pub use error::{EnergyInconsistency, SimulationError};
+pub use grand_canonical::*;
+pub use kinetic::*;
+pub use metrics::*;
 pub use montecarlo::*;
-pub use system::System;
+pub use nested::*;
+pub use npt::*;
+pub use observer::*;
+pub use parallel::ParallelChains;
+pub use random::{gaussian, RandomStream, RandomStreams};
+pub use replica_exchange::*;
+pub use spins::*;
+pub use statistics::*;
+pub use umbrella::*;
+pub use undo::*;
+pub use wang_landau::*;
+pub use system::{ChangedPositions, System};
@@ -0,0 +1,129 @@
+use std::fs;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{append_writer, out_writer, AcceptanceStatistics, Observation, Observer};
+
+/// Step size and recent success rate of a single mover
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MoverMetrics {
+    /// success rate since the previous record
+    pub acceptance_rate: f64,
+    pub max_range: f64,
+}
+
+/// Structured record of a single observation of a running simulation, written by [`MetricsObserver`]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MetricsRecord {
+    pub sweep: usize,
+    pub chain: usize,
+    pub energy: f64,
+    pub temperature: f64,
+    /// wall-clock time in seconds since the observer was created
+    pub elapsed: f64,
+    pub movers: Vec<MoverMetrics>,
+}
+
+/// Destination of [`MetricsRecord`]s, e.g. a file in a given format
+pub trait MetricsSink: Send {
+    fn write_record(&mut self, record: &MetricsRecord) -> io::Result<()>;
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+/// Writes records as comma-separated values, with a header line before the first record.
+///
+/// Every mover takes two columns: `acceptance_rate_i` and `max_range_i`, where `i` is the index of the mover.
+pub struct CsvSink {
+    out: BufWriter<Box<dyn Write + Send>>,
+    header_written: bool,
+}
+
+impl CsvSink {
+    pub fn new(out_fname: &str) -> io::Result<CsvSink> { Ok(CsvSink { out: out_writer(out_fname)?, header_written: false }) }
+
+    /// Appends records to an existing file; the header is written only when the file is empty
+    pub fn appending(out_fname: &str) -> io::Result<CsvSink> {
+        let header_written = !out_fname.is_empty() && fs::metadata(out_fname).is_ok_and(|m| m.len() > 0);
+        Ok(CsvSink { out: append_writer(out_fname)?, header_written })
+    }
+}
+
+impl MetricsSink for CsvSink {
+    fn write_record(&mut self, record: &MetricsRecord) -> io::Result<()> {
+        if !self.header_written {
+            write!(self.out, "sweep,chain,energy,temperature,elapsed")?;
+            for i in 0..record.movers.len() { write!(self.out, ",acceptance_rate_{},max_range_{}", i, i)?; }
+            writeln!(self.out)?;
+            self.header_written = true;
+        }
+        write!(self.out, "{},{},{},{},{:.6}", record.sweep, record.chain, record.energy, record.temperature,
+               record.elapsed)?;
+        for mover in record.movers.iter() { write!(self.out, ",{},{}", mover.acceptance_rate, mover.max_range)?; }
+        writeln!(self.out)
+    }
+
+    fn flush(&mut self) -> io::Result<()> { self.out.flush() }
+}
+
+/// Writes every record as a JSON object in a separate line (JSON Lines format)
+pub struct JsonLinesSink {
+    out: BufWriter<Box<dyn Write + Send>>,
+}
+
+impl JsonLinesSink {
+    pub fn new(out_fname: &str) -> io::Result<JsonLinesSink> { Ok(JsonLinesSink { out: out_writer(out_fname)? }) }
+
+    /// Appends records to an existing file
+    pub fn appending(out_fname: &str) -> io::Result<JsonLinesSink> { Ok(JsonLinesSink { out: append_writer(out_fname)? }) }
+}
+
+impl MetricsSink for JsonLinesSink {
+    fn write_record(&mut self, record: &MetricsRecord) -> io::Result<()> {
+        serde_json::to_writer(&mut self.out, record)?;
+        writeln!(self.out)
+    }
+
+    fn flush(&mut self) -> io::Result<()> { self.out.flush() }
+}
+
+/// Emits a [`MetricsRecord`] for every observation: the sweep, the energy, the temperature, the elapsed time
+/// and the success rate and step size of every mover.
+pub struct MetricsObserver {
+    sink: Box<dyn MetricsSink>,
+    start: Instant,
+    previous: Vec<AcceptanceStatistics>,
+}
+
+impl MetricsObserver {
+    pub fn new(sink: Box<dyn MetricsSink>) -> MetricsObserver {
+        MetricsObserver { sink, start: Instant::now(), previous: vec![] }
+    }
+
+    /// Writes records to a CSV file; an empty file name means the standard output
+    pub fn csv(out_fname: &str) -> io::Result<MetricsObserver> { Ok(MetricsObserver::new(Box::new(CsvSink::new(out_fname)?))) }
+
+    /// Writes records to a JSON Lines file; an empty file name means the standard output
+    pub fn json_lines(out_fname: &str) -> io::Result<MetricsObserver> {
+        Ok(MetricsObserver::new(Box::new(JsonLinesSink::new(out_fname)?)))
+    }
+}
+
+impl<S> Observer<S> for MetricsObserver {
+    fn observe(&mut self, observation: &Observation<S>) {
+        self.previous.resize(observation.movers.len(), AcceptanceStatistics::default());
+        let movers = observation.movers.iter().zip(self.previous.iter_mut()).map(|(mover, previous)| {
+            let metrics = MoverMetrics { acceptance_rate: mover.statistics.recent_success_rate(previous),
+                max_range: mover.max_range };
+            *previous = mover.statistics.clone();
+            metrics
+        }).collect();
+        let record = MetricsRecord { sweep: observation.sweep, chain: observation.chain, energy: observation.energy,
+            temperature: observation.temperature, elapsed: self.start.elapsed().as_secs_f64(), movers };
+        self.sink.write_record(&record).ok();
+    }
+
+    fn flush(&mut self) { self.sink.flush().ok(); }
+}
@@ -1,13 +1,14 @@
-
-use std::ops::Range;
-use std::default::Default;
+use std::io;
 use rand::Rng;
-use rand::rngs::ThreadRng;
+use serde::{Deserialize, Serialize};
 
-use crate::Energy;
-use crate::{System};
+use crate::{Energy, EnergyInconsistency, SimulationError};
+use crate::{ChangedPositions, System, RandomStream, RandomStreams, UndoLog, UndoSystem};
+use crate::{CriterionState, MCProtocolState, MoverState, SamplerCheckpoint, SamplerState};
+use crate::checkpoint::{invalid_state, wrong_sampler};
+use crate::error::check_mover_index;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct AcceptanceStatistics {
     pub n_succ:i32,
     pub n_failed:i32,
@@ -29,179 +30,420 @@ impl AcceptanceStatistics {
     }
 }
 
-impl Default for AcceptanceStatistics {
-    fn default() -> Self {
-        AcceptanceStatistics{ n_succ: 0, n_failed: 0 }
-    }
-}
+pub trait AcceptanceCriterion: Send {
+    fn check(&mut self, energy_before: f64, energy_after: f64, rng: &mut RandomStream) -> bool;
+    fn temperature(&self) -> f64;
+    fn set_temperature(&mut self, temperature: f64);
+
+    /// Returns `false` for a criterion that doesn't depend on temperature, e.g. [`WangLandauCriterion`](crate::WangLandauCriterion);
+    /// its [`temperature()`](AcceptanceCriterion::temperature) is meaningless and isn't stored in checkpoints
+    fn has_temperature(&self) -> bool { true }
+
+    /// State the criterion has learned while sampling, stored in checkpoints; `None` for a criterion without such state
+    fn criterion_state(&self) -> Option<CriterionState> { None }
 
-pub trait AcceptanceCriterion {
-    fn check(&mut self, energy_before: f64, energy_after: f64) -> bool;
+    /// Restores the state saved by [`criterion_state()`](AcceptanceCriterion::criterion_state)
+    fn restore_criterion_state(&mut self, _state: CriterionState) -> io::Result<()> {
+        Err(invalid_state("this acceptance criterion has no state to restore".to_string()))
+    }
 }
 
 pub struct MetropolisCriterion {
     pub temperature: f64,
-    rng: ThreadRng,
 }
 
 impl MetropolisCriterion {
-    pub fn new(temperature: f64) -> MetropolisCriterion { MetropolisCriterion{temperature, rng:rand::thread_rng() } }
+    pub fn new(temperature: f64) -> MetropolisCriterion { MetropolisCriterion{ temperature } }
 }
 
 impl AcceptanceCriterion for MetropolisCriterion {
-    fn check(&mut self, energy_before: f64, energy_after: f64) -> bool {
-        // let mut rng = rand::thread_rng();
+    fn check(&mut self, energy_before: f64, energy_after: f64, rng: &mut RandomStream) -> bool {
         let delta_e = energy_after - energy_before;
-        if delta_e <= 0.0 || self.rng.gen_range(0.0..1.0) <= (-delta_e / self.temperature).exp() {
+        if delta_e <= 0.0 || rng.gen_range(0.0..1.0) <= (-delta_e / self.temperature).exp() {
             return true
         }
         return false;
     }
+
+    fn temperature(&self) -> f64 { self.temperature }
+
+    fn set_temperature(&mut self, temperature: f64) { self.temperature = temperature; }
+}
+
+/// Allows an acceptance criterion to be chosen at run time, e.g. from a configuration file
+impl AcceptanceCriterion for Box<dyn AcceptanceCriterion> {
+    fn check(&mut self, energy_before: f64, energy_after: f64, rng: &mut RandomStream) -> bool {
+        self.as_mut().check(energy_before, energy_after, rng)
+    }
+
+    fn temperature(&self) -> f64 { self.as_ref().temperature() }
+
+    fn set_temperature(&mut self, temperature: f64) { self.as_mut().set_temperature(temperature); }
+
+    fn has_temperature(&self) -> bool { self.as_ref().has_temperature() }
+
+    fn criterion_state(&self) -> Option<CriterionState> { self.as_ref().criterion_state() }
+
+    fn restore_criterion_state(&mut self, state: CriterionState) -> io::Result<()> {
+        self.as_mut().restore_criterion_state(state)
+    }
 }
 
-pub trait Mover<S: System> {
-    fn perturb(&mut self, system: &mut S) -> Range<usize>;
+pub trait Mover<S: System>: Send {
+    /// Makes a move on a system and returns all the positions it has changed
+    fn perturb(&mut self, system: &mut S, rng: &mut RandomStream) -> ChangedPositions;
     fn acceptance_statistics(&self) -> AcceptanceStatistics;
     fn add_success(&mut self);
     fn add_failure(&mut self);
+    fn set_acceptance_statistics(&mut self, stats: AcceptanceStatistics);
     fn max_range(&self) -> f64;
     fn set_max_range(&mut self, new_val: f64);
+
+    /// Makes a move directly on a system, storing the old values of every changed position in `undo`.
+    ///
+    /// Called by [`UndoMCProtocol`](crate::UndoMCProtocol) instead of [`perturb()`](Mover::perturb);
+    /// a mover that doesn't support in-place moves returns `false`, which is the default.
+    fn perturb_in_place(&mut self, _system: &mut S, _undo: &mut UndoLog<S>, _rng: &mut RandomStream) -> bool
+        where S: UndoSystem { false }
+
+    /// Returns `true` for a mover that accepts or rejects its own moves, e.g. [`HmcMover`](crate::HmcMover).
+    ///
+    /// Such a move is not checked by the acceptance criterion of a sampler: [`perturb()`](Mover::perturb) returns
+    /// the positions of an accepted move, or no positions at all when the move has been rejected. In both cases
+    /// the sampler copies the whole system, so the move may also change its global properties, e.g. the volume.
+    fn self_accepting(&self) -> bool { false }
+
+    /// Tells the mover the temperature of the sampler it belongs to.
+    ///
+    /// Samplers call this method when a mover is added and whenever their temperature changes, so a
+    /// [self-accepting](Mover::self_accepting) mover follows the temperature of its sampler. The default
+    /// implementation does nothing.
+    fn set_temperature(&mut self, _temperature: f64) {}
 }
 
-pub trait Sampler<T: AcceptanceCriterion, S: System> {
-    fn make_sweeps(&mut self, n:usize, coords: &mut S, energy: &Box<dyn Energy<S>>);
+pub trait Sampler<T: AcceptanceCriterion, S: System>: Send {
+    fn make_sweeps(&mut self, n:usize, coords: &mut S, energy: &dyn Energy<S>) -> Result<(), SimulationError>;
+    fn temperature(&self) -> f64;
+    fn set_temperature(&mut self, temperature: f64);
+
+    /// Energy inconsistencies recorded so far by this sampler; see [`ValidationMode`]
+    fn energy_inconsistencies(&self) -> Vec<EnergyInconsistency> { vec![] }
 }
 
 pub trait MoversSet<T: AcceptanceCriterion, S: System> {
 
     fn add_mover(&mut self, perturb_fn: Box<dyn Mover<S>>);
 
-    fn get_mover(&mut self, which_one: usize) -> &mut Box<dyn Mover<S>>;
+    /// Returns a mover or [`SimulationError::MoverIndex`] when there's no mover of that index
+    fn get_mover(&mut self, which_one: usize) -> Result<&mut Box<dyn Mover<S>>, SimulationError>;
 
     fn count_movers(&self) -> usize;
 }
 
-pub trait MoversSetSampler<T: AcceptanceCriterion, S: System> : MoversSet<T, S> + Sampler<T, S> {}
+pub trait MoversSetSampler<T: AcceptanceCriterion, S: System> : MoversSet<T, S> + Sampler<T, S> + SamplerCheckpoint<S> {}
+
+/// Defines how [`MCProtocol`] applies its movers during a sweep.
+///
+/// Each mover is registered with a weight; in both cases the i-th mover makes on average `weight_i * size()`
+/// moves per sweep, where `size()` is the size of the system.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SweepPolicy {
+    /// movers are applied one after another, in the order they were added; the i-th mover makes
+    /// exactly `weight_i * size()` moves (rounded) before the next one starts
+    Cycle,
+    /// each move is made by a mover selected at random with probability proportional to its weight
+    Random,
+}
+
+/// Defines whether [`MCProtocol`] validates energy changes of moves.
+///
+/// Validation compares the energy change evaluated for the changed positions with the difference of total energies
+/// of the system before and after every move. It's expensive, but it catches bugs in energy functions and movers.
+/// By default validation records inconsistencies in debug builds and is disabled in release builds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ValidationMode {
+    Disabled,
+    /// inconsistencies are recorded and the simulation goes on
+    Record,
+    /// the first inconsistency stops the simulation with [`SimulationError::InconsistentEnergy`]
+    Fail,
+}
+
+impl Default for ValidationMode {
+    fn default() -> Self { if cfg!(debug_assertions) { ValidationMode::Record } else { ValidationMode::Disabled } }
+}
+
+/// Passes the temperature of an acceptance criterion, if it has one, to a mover
+pub(crate) fn sync_temperature<T: AcceptanceCriterion, S: System>(criterion: &T, mover: &mut dyn Mover<S>) {
+    if criterion.has_temperature() { mover.set_temperature(criterion.temperature()); }
+}
+
+/// Weight of a mover must be a finite, non-negative number
+fn check_weight(weight: f64) -> Result<(), SimulationError> {
+    if weight >= 0.0 && weight.is_finite() { return Ok(()); }
+    return Err(SimulationError::InvalidParameter(format!("mover weight {} is not a finite, non-negative number", weight)));
+}
+
+/// At most that many energy inconsistencies are kept by a protocol
+pub(crate) const MAX_RECORDED_INCONSISTENCIES: usize = 1000;
 
+/// Every mover and the acceptance criterion of this protocol use their own random stream,
+/// all derived from the [`RandomStreams`] given at construction.
+///
+/// By default movers are applied in a [`Cycle`](SweepPolicy::Cycle), each of them `size()` times per sweep.
 pub struct MCProtocol<T: AcceptanceCriterion, S: System> {
     pub acceptance_criterion: T,
-    movers: Vec<Box<dyn Mover<S>>>
+    pub sweep_policy: SweepPolicy,
+    /// number of moves of a mover with weight 1.0 per sweep; `None` means the size of the system. Set a fixed number
+    /// when the size changes during a simulation, e.g. of a [`VariableSizeSystem`](crate::VariableSizeSystem)
+    pub sweep_size: Option<usize>,
+    pub validation: ValidationMode,
+    /// largest allowed difference between the local and the total energy change of a validated move
+    pub energy_tolerance: f64,
+    inconsistencies: Vec<EnergyInconsistency>,
+    movers: Vec<Box<dyn Mover<S>>>,
+    weights: Vec<f64>,
+    streams: RandomStreams,
+    criterion_rng: RandomStream,
+    selection_rng: RandomStream,
+    mover_rngs: Vec<RandomStream>,
 }
 
 impl<T: AcceptanceCriterion, S: System> MCProtocol<T, S> {
-    pub fn new(acc_crit: T) -> MCProtocol<T, S> {
+    /// Creates a protocol seeded from the entropy source; use [`with_streams()`](MCProtocol::with_streams)
+    /// for a reproducible simulation
+    pub fn new(acc_crit: T) -> MCProtocol<T, S> { MCProtocol::with_streams(acc_crit, RandomStreams::from_entropy()) }
+
+    pub fn with_streams(acc_crit: T, mut streams: RandomStreams) -> MCProtocol<T, S> {
+        let criterion_rng = streams.next_stream();
+        let selection_rng = streams.next_stream();
         MCProtocol {
             acceptance_criterion: acc_crit,
-            movers: vec![]
+            sweep_policy: SweepPolicy::Cycle,
+            sweep_size: None,
+            validation: ValidationMode::default(),
+            energy_tolerance: 0.01,
+            inconsistencies: vec![],
+            movers: vec![],
+            weights: vec![],
+            streams,
+            criterion_rng,
+            selection_rng,
+            mover_rngs: vec![],
         }
     }
+
+    /// Adds a mover with a given weight, which must be finite and non-negative;
+    /// [`add_mover()`](MoversSet::add_mover) adds a mover with weight 1.0
+    pub fn add_weighted_mover(&mut self, perturb_fn: Box<dyn Mover<S>>, weight: f64) -> Result<(), SimulationError> {
+        check_weight(weight)?;
+        self.push_mover(perturb_fn, weight);
+        Ok(())
+    }
+
+    /// Weight of a mover or [`SimulationError::MoverIndex`] when there's no mover of that index
+    pub fn weight(&self, which_one: usize) -> Result<f64, SimulationError> {
+        check_mover_index(which_one, self.movers.len())?;
+        Ok(self.weights[which_one])
+    }
+
+    /// Changes the weight of a mover; the new weight must be finite and non-negative
+    pub fn set_weight(&mut self, which_one: usize, weight: f64) -> Result<(), SimulationError> {
+        check_mover_index(which_one, self.movers.len())?;
+        check_weight(weight)?;
+        self.weights[which_one] = weight;
+        Ok(())
+    }
+
+    fn push_mover(&mut self, mut perturb_fn: Box<dyn Mover<S>>, weight: f64) {
+        sync_temperature(&self.acceptance_criterion, perturb_fn.as_mut());
+        self.movers.push(perturb_fn);
+        self.weights.push(weight);
+        self.mover_rngs.push(self.streams.next_stream());
+    }
+
+    /// Energy inconsistencies found so far; only the first 1000 of them are kept
+    pub fn inconsistencies(&self) -> &Vec<EnergyInconsistency> { &self.inconsistencies }
+
+    pub fn clear_inconsistencies(&mut self) { self.inconsistencies.clear(); }
+
+    /// Attempts a single move with the given mover
+    fn make_move(&mut self, i_mover: usize, coords: &mut S, future_coords: &mut S, energy: &dyn Energy<S>)
+            -> Result<(), SimulationError> {
+        let mover = &mut self.movers[i_mover];
+        // ---------- Make a move on future system
+        let changed = mover.perturb(future_coords, &mut self.mover_rngs[i_mover]);
+        if mover.self_accepting() {
+            if changed.is_empty() {
+                future_coords.clone_from(coords);
+                mover.add_failure();
+            } else {
+                coords.clone_from(future_coords);
+                mover.add_success();
+            }
+            return Ok(());
+        }
+        // ---------- Evaluate energy difference
+        let (en_before, en_after) = energy.delta_energy(coords, future_coords, &changed);
+        // ---------- test the energy consistency
+        let delta_en = en_after - en_before;
+        // ---------- test only when it's unlikely the move will be rejected
+        if self.validation != ValidationMode::Disabled && delta_en < 1000.0 {
+            let total_before = energy.energy(coords);
+            let total_after = energy.energy(future_coords);
+            if f64::abs(total_after - total_before - delta_en) > self.energy_tolerance {
+                let inconsistency = EnergyInconsistency { mover: i_mover, changed: changed.clone(),
+                    local_delta: delta_en, total_before, total_after };
+                if self.validation == ValidationMode::Fail {
+                    return Err(SimulationError::InconsistentEnergy(inconsistency));
+                }
+                if self.inconsistencies.len() < MAX_RECORDED_INCONSISTENCIES { self.inconsistencies.push(inconsistency); }
+            }
+        }
+        // ---------- apply acceptance criterion, copy or undo the move
+        if self.acceptance_criterion.check(en_before, en_after, &mut self.criterion_rng) {
+            // --- update mover counts, copy future_pose on current_pose to make the move
+            for ipos in changed.iter() {
+                coords.copy_from(ipos, future_coords);
+            }
+            mover.add_success();
+        } else {
+            // --- update mover failures, copy current_pose on future_pose to clear the move
+            for ipos in changed.iter() {
+                future_coords.copy_from(ipos, coords);
+            }
+            mover.add_failure();
+        }
+        Ok(())
+    }
+
+    /// Selects a mover at random, with probability proportional to its weight
+    fn select_mover(&mut self, total_weight: f64) -> usize {
+        let mut r = self.selection_rng.gen_range(0.0..total_weight);
+        for (i, w) in self.weights.iter().enumerate() {
+            if r < *w { return i; }
+            r -= w;
+        }
+        return self.weights.len() - 1;
+    }
 }
 
 impl<T: AcceptanceCriterion, S: System> Sampler<T, S>  for MCProtocol<T, S> {
 
-    fn make_sweeps(&mut self, n:usize, coords: &mut S, energy: &Box<dyn Energy<S>>) {
+    fn make_sweeps(&mut self, n:usize, coords: &mut S, energy: &dyn Energy<S>) -> Result<(), SimulationError> {
         let mut future_coords = coords.clone();
+        let total_weight: f64 = self.weights.iter().sum();
         for _ in 0..n {
-            for i_mover in 0..self.movers.len() {
-                let mover = &mut self.movers[i_mover];
-                for _ in 0..coords.size() {
-                    // ---------- Make a move on future system
-                    let range: Range<usize> = mover.perturb(&mut future_coords);
-                    // ---------- Evaluate energy difference
-                    let (en_before, en_after) = energy.delta_energy_by_pos(coords, &future_coords, range.start);
-                    // ---------- test the energy consistency
-                    #[cfg(debug_assertions)] {
-                        let delta_en = en_after - en_before;
-                        if delta_en < 1000.0 {      // ---------- test only when it's unlikely the move will be rejected
-                            let en_old = energy.energy(&coords);
-                            let en_new = energy.energy(&future_coords);
-                            let total_delta = en_new - en_old;
-                            if f64::abs(total_delta - delta_en) > 0.01 {
-                                let str = format!("Inconsistent energy! Total {en_old} -> {en_new} with delta = {total_delta}, local delta: {delta_en} after move {:?}", range);
-                                panic!("{}", str);
-                            }
+            let sweep_size = self.sweep_size.unwrap_or(coords.size()) as f64;
+            match self.sweep_policy {
+                SweepPolicy::Cycle => {
+                    for i_mover in 0..self.movers.len() {
+                        let n_moves = (self.weights[i_mover] * sweep_size).round() as usize;
+                        for _ in 0..n_moves {
+                            self.make_move(i_mover, coords, &mut future_coords, energy)?;
                         }
                     }
-                    // ---------- apply acceptance criterion, copy or undo the move
-                    if self.acceptance_criterion.check(en_before, en_after) {
-                        // --- update mover counts, copy future_pose on current_pose to make the move
-                        for ipos in range.start..range.end + 1 {
-                            coords.copy_from(ipos, &future_coords);
-                        }
-                        mover.add_success();
-                    } else {
-                        // --- update mover failures, copy current_pose on future_pose to clear the move
-                        for ipos in range.start..range.end + 1 {
-                            future_coords.copy_from(ipos, &coords);
-                        }
-                        mover.add_failure();
+                }
+                SweepPolicy::Random => {
+                    if total_weight <= 0.0 { continue; }
+                    let n_moves = (total_weight * sweep_size).round() as usize;
+                    for _ in 0..n_moves {
+                        let i_mover = self.select_mover(total_weight);
+                        self.make_move(i_mover, coords, &mut future_coords, energy)?;
                     }
                 }
             }
         }
+        Ok(())
     }
+
+    fn temperature(&self) -> f64 { self.acceptance_criterion.temperature() }
+
+    /// Sets the temperature of the acceptance criterion and of every mover
+    fn set_temperature(&mut self, temperature: f64) {
+        self.acceptance_criterion.set_temperature(temperature);
+        for mover in self.movers.iter_mut() { sync_temperature(&self.acceptance_criterion, mover.as_mut()); }
+    }
+
+    fn energy_inconsistencies(&self) -> Vec<EnergyInconsistency> { self.inconsistencies.clone() }
 }
 
 
 impl<T: AcceptanceCriterion, S: System> MoversSet<T, S> for MCProtocol<T, S> {
 
-    fn add_mover(&mut self, perturb_fn: Box<dyn Mover<S>>){
-        self.movers.push(perturb_fn);
-    }
+    fn add_mover(&mut self, perturb_fn: Box<dyn Mover<S>>){ self.push_mover(perturb_fn, 1.0); }
 
-    fn get_mover(&mut self, which_one: usize) -> &mut Box<dyn Mover<S>> { &mut self.movers[which_one] }
+    fn get_mover(&mut self, which_one: usize) -> Result<&mut Box<dyn Mover<S>>, SimulationError> {
+        check_mover_index(which_one, self.movers.len())?;
+        Ok(&mut self.movers[which_one])
+    }
 
     fn count_movers(&self) -> usize { self.movers.len() }
 }
 
 impl<T: AcceptanceCriterion, S: System> MoversSetSampler<T, S> for MCProtocol<T, S> {}
 
-pub struct AdaptiveMCProtocol<T: AcceptanceCriterion, S: System> {
-    pub target_rate: f64,
-    pub factor: f64,
-    sampler: Box<dyn MoversSetSampler<T, S>>,
-    allowed_ranges: Vec<Range<f64>>
-}
-
-impl<T: AcceptanceCriterion, S: System> AdaptiveMCProtocol<T, S> {
-    pub fn new(mut sampler: Box<dyn MoversSetSampler<T, S>>) -> AdaptiveMCProtocol<T, S> {
-        let mut allowed_ranges:Vec<Range<f64>> = vec![];
-        for i in 0..sampler.count_movers() {
-            let r = sampler.get_mover(i).max_range();
-            allowed_ranges.push(r * 0.2..r * 5.0);
-        }
-        let out = AdaptiveMCProtocol { target_rate: 0.4, factor: 0.95, sampler, allowed_ranges};
-        return out;
+/// Stores the temperature, movers and random streams of the movers, of the acceptance criterion and of mover selection
+impl<T: AcceptanceCriterion, S: System> SamplerCheckpoint<S> for MCProtocol<T, S> {
+    fn save_state(&self) -> SamplerState<S> {
+        let criterion = &self.acceptance_criterion;
+        return SamplerState::MCProtocol(MCProtocolState {
+            temperature: if criterion.has_temperature() { Some(criterion.temperature()) } else { None },
+            criterion: criterion.criterion_state(),
+            movers: self.movers.iter().map(|m| MoverState::new(m.as_ref())).collect(),
+            streams: self.streams.state().clone(),
+            criterion_rng: self.criterion_rng.clone(),
+            selection_rng: self.selection_rng.clone(),
+            mover_rngs: self.mover_rngs.clone(),
+        });
     }
-}
-
-impl<T: AcceptanceCriterion, S: System> Sampler<T, S>  for AdaptiveMCProtocol<T, S> {
 
-    fn make_sweeps(&mut self, n: usize, coords: &mut S, energy: &Box<dyn Energy<S>>) {
-        let mut stats_before: Vec<AcceptanceStatistics> = vec![];
-        for i in 0..self.sampler.count_movers() {
-            stats_before.push(self.sampler.get_mover(i).acceptance_statistics());
+    fn restore_state(&mut self, state: SamplerState<S>) -> io::Result<()> {
+        let state = match state {
+            SamplerState::MCProtocol(state) => state,
+            other => return Err(wrong_sampler("MCProtocol", &other)),
+        };
+        if state.movers.len() != self.movers.len() || state.mover_rngs.len() != self.movers.len() {
+            return Err(invalid_state(format!("checkpoint holds {} movers while the sampler has {}",
+                                             state.movers.len(), self.movers.len())));
         }
-        self.sampler.make_sweeps(n, coords, energy);
-        for i in 0..self.sampler.count_movers() {
-            let stats_after = self.sampler.get_mover(i).acceptance_statistics();
-            let rate = stats_after.recent_success_rate(&stats_before[i]);
-
-            let mover = self.sampler.get_mover(i);
-            let mut range = mover.max_range();
-            if rate < self.target_rate - 0.05 { range = range * self.factor; }
-            if rate > self.target_rate + 0.05 { range = range / self.factor; }
-            if self.allowed_ranges[i].end.lt(&range) { range = self.allowed_ranges[i].end }
-            if self.allowed_ranges[i].start.gt(&range) { range = self.allowed_ranges[i].start }
-            mover.set_max_range(range);
+        if let Some(temperature) = state.temperature { self.acceptance_criterion.set_temperature(temperature); }
+        if let Some(criterion) = state.criterion { self.acceptance_criterion.restore_criterion_state(criterion)?; }
+        for (mover, mover_state) in self.movers.iter_mut().zip(state.movers.iter()) {
+            mover_state.restore(mover.as_mut());
+            sync_temperature(&self.acceptance_criterion, mover.as_mut());
         }
+        self.streams = RandomStreams::from_state(state.streams);
+        self.criterion_rng = state.criterion_rng;
+        self.selection_rng = state.selection_rng;
+        self.mover_rngs = state.mover_rngs;
+        Ok(())
     }
 }
 
-impl<T: AcceptanceCriterion, S: System> MoversSet<T, S>  for AdaptiveMCProtocol<T, S> {
-    fn add_mover(&mut self, perturb_fn: Box<dyn Mover<S>>) { self.sampler.add_mover(perturb_fn); }
-
-    fn get_mover(&mut self, which_one: usize) -> &mut Box<dyn Mover<S>> { self.sampler.get_mover(which_one) }
+#[cfg(test)]
+mod tests {
+    use crate::{MCProtocol, MetropolisCriterion, RandomStreams, SimulationError, SpinFlipMover, SpinLattice};
+
+    #[test]
+    fn invalid_weights_are_rejected() {
+        let mut sampler: MCProtocol<MetropolisCriterion, SpinLattice> =
+            MCProtocol::with_streams(MetropolisCriterion::new(1.0), RandomStreams::new(1));
+        assert!(sampler.add_weighted_mover(Box::new(SpinFlipMover::new()), -1.0).is_err());
+        assert!(sampler.add_weighted_mover(Box::new(SpinFlipMover::new()), f64::NAN).is_err());
+        assert!(sampler.add_weighted_mover(Box::new(SpinFlipMover::new()), 0.5).is_ok());
+        assert!(sampler.set_weight(0, f64::INFINITY).is_err());
+        assert!(sampler.set_weight(0, 0.0).is_ok());
+        assert_eq!(sampler.weight(0).unwrap(), 0.0);
+    }
 
-    fn count_movers(&self) -> usize { self.sampler.count_movers() }
-}
\ No newline at end of file
+    #[test]
+    fn weight_of_missing_mover_is_an_error() {
+        let mut sampler: MCProtocol<MetropolisCriterion, SpinLattice> =
+            MCProtocol::with_streams(MetropolisCriterion::new(1.0), RandomStreams::new(1));
+        sampler.add_weighted_mover(Box::new(SpinFlipMover::new()), 1.0).unwrap();
+        assert!(matches!(sampler.weight(1), Err(SimulationError::MoverIndex { index: 1, count: 1 })));
+        assert!(sampler.set_weight(1, 1.0).is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use std::io;
+use std::io::Write;
+use std::ops::Range;
+
+use rand::Rng;
+
+use crate::{out_writer, AcceptanceStatistics, AdaptationStrategy, DeadBandAdaptation, Energy, Mover, RandomStream,
+            RandomStreams, SimulationError, System};
+use crate::umbrella::log_sum_exp;
+
+/// An energy level removed from the live population by [`NestedSampling`]
+#[derive(Clone, Debug)]
+pub struct NestedLevel {
+    pub energy: f64,
+    /// estimated logarithm of the prior volume of all the configurations with energy lower than `energy`
+    pub log_volume: f64,
+}
+
+/// Thermodynamic properties at a single temperature, computed from the energy levels found by nested sampling
+#[derive(Clone, Debug)]
+pub struct NestedThermodynamics {
+    pub temperature: f64,
+    /// logarithm of the partition function, relative to the prior volume of the initial live systems
+    pub log_z: f64,
+    pub energy: f64,
+    pub heat_capacity: f64,
+}
+
+/// Nested sampling: estimates the density of states, so the partition function is known at any temperature.
+///
+/// The sampler keeps a population of live systems, drawn initially from the prior, i.e. uniformly
+/// from the configuration space. Every iteration removes the live system of the highest energy, which
+/// becomes the next [`NestedLevel`]: the prior volume below that level is estimated to shrink by the factor
+/// `exp(-1/K)`, where `K` is the number of live systems. The removed system is replaced by a copy of another
+/// live system, decorrelated by `walk_length` Monte Carlo moves that are accepted only below the removed energy.
+///
+/// Movers must propose symmetric moves and can't be self-accepting. Their step sizes are adapted after every walk
+/// towards `target_rate`, by a [`DeadBandAdaptation`] unless another [`AdaptationStrategy`] is set, staying between
+/// 0.2 and 5.0 times the step size a mover had when it was added.
+/// Ties between equal energies, frequent for discrete models like hard disks, are broken by a random label
+/// attached to every live system.
+pub struct NestedSampling<S: System> {
+    pub walk_length: usize,
+    pub target_rate: f64,
+    live: Vec<S>,
+    energies: Vec<f64>,
+    labels: Vec<f64>,
+    movers: Vec<Box<dyn Mover<S>>>,
+    allowed_ranges: Vec<Range<f64>>,
+    strategy: Box<dyn AdaptationStrategy>,
+    levels: Vec<NestedLevel>,
+    rng: RandomStream,
+}
+
+impl<S: System> NestedSampling<S> {
+    /// Creates a sampler whose live population holds the given systems
+    pub fn new(systems: Vec<S>, energy: &dyn Energy<S>, mut streams: RandomStreams)
+            -> Result<NestedSampling<S>, SimulationError> {
+        if systems.len() < 2 {
+            return Err(SimulationError::InvalidParameter("nested sampling needs at least two live systems".to_string()));
+        }
+        let mut rng = streams.next_stream();
+        let energies = systems.iter().map(|s| energy.energy(s)).collect();
+        let labels = (0..systems.len()).map(|_| rng.gen_range(0.0..1.0)).collect();
+        Ok(NestedSampling { walk_length: 100, target_rate: 0.5, live: systems, energies, labels, movers: vec![],
+            allowed_ranges: vec![], strategy: Box::new(DeadBandAdaptation::default()), levels: vec![], rng })
+    }
+
+    /// Adds a mover used by random walks; a self-accepting mover is rejected
+    pub fn add_mover(&mut self, mover: Box<dyn Mover<S>>) -> Result<(), SimulationError> {
+        if mover.self_accepting() {
+            return Err(SimulationError::InvalidParameter("nested sampling can't use a self-accepting mover".to_string()));
+        }
+        let r = mover.max_range();
+        self.allowed_ranges.push(r * 0.2..r * 5.0);
+        self.movers.push(mover);
+        Ok(())
+    }
+
+    pub fn set_strategy(&mut self, strategy: Box<dyn AdaptationStrategy>) { self.strategy = strategy; }
+
+    pub fn count_live(&self) -> usize { self.live.len() }
+
+    pub fn live(&self, i: usize) -> &S { &self.live[i] }
+
+    pub fn live_energies(&self) -> &Vec<f64> { &self.energies }
+
+    /// Energy levels removed so far, from the highest to the lowest
+    pub fn levels(&self) -> &Vec<NestedLevel> { &self.levels }
+
+    /// Estimated logarithm of the prior volume still occupied by the live systems
+    pub fn log_live_volume(&self) -> f64 { -(self.levels.len() as f64) / self.live.len() as f64 }
+
+    /// Makes `n_iterations` iterations, each replacing the highest-energy live system
+    pub fn run(&mut self, n_iterations: usize, energy: &dyn Energy<S>) -> Result<(), SimulationError> {
+        if self.movers.is_empty() {
+            return Err(SimulationError::InvalidParameter("nested sampling needs at least one mover".to_string()));
+        }
+        for _ in 0..n_iterations {
+            let worst = (0..self.live.len()).max_by(|&i, &j| self.energies[i].total_cmp(&self.energies[j])
+                .then(self.labels[i].total_cmp(&self.labels[j]))).unwrap();
+            let (e_max, l_max) = (self.energies[worst], self.labels[worst]);
+            self.levels.push(NestedLevel { energy: e_max, log_volume: -((self.levels.len() + 1) as f64)
+                / self.live.len() as f64 });
+
+            let mut start = self.rng.gen_range(0..self.live.len() - 1);
+            if start >= worst { start += 1; }
+            let copy = self.live[start].clone();
+            self.live[worst] = copy;
+            self.energies[worst] = self.energies[start];
+            self.labels[worst] = self.labels[start];
+            self.walk(worst, e_max, l_max, energy);
+        }
+        Ok(())
+    }
+
+    /// Moves a live system randomly, allowing only the moves that keep it below the given energy and label
+    fn walk(&mut self, which: usize, e_max: f64, l_max: f64, energy: &dyn Energy<S>) {
+        let stats_before: Vec<AcceptanceStatistics> = self.movers.iter().map(|m| m.acceptance_statistics()).collect();
+        let current = &mut self.live[which];
+        let mut trial = current.clone();
+        for _ in 0..self.walk_length {
+            let which_mover = self.rng.gen_range(0..self.movers.len());
+            let mover = &mut self.movers[which_mover];
+            let changed = mover.perturb(&mut trial, &mut self.rng);
+            let (before, after) = energy.delta_energy(current, &trial, &changed);
+            let new_energy = self.energies[which] - before + after;
+            let new_label: f64 = self.rng.gen_range(0.0..1.0);
+            if new_energy < e_max || (new_energy == e_max && new_label < l_max) {
+                for pos in changed.iter() { current.copy_from(pos, &trial); }
+                self.energies[which] = new_energy;
+                self.labels[which] = new_label;
+                mover.add_success();
+            } else {
+                for pos in changed.iter() { trial.copy_from(pos, current); }
+                mover.add_failure();
+            }
+        }
+
+        let update = self.levels.len() - 1;
+        for (i, mover) in self.movers.iter_mut().enumerate() {
+            let stats_after = mover.acceptance_statistics();
+            let stats = &stats_before[i];
+            if stats_after.n_succ + stats_after.n_failed == stats.n_succ + stats.n_failed { continue; }
+            let rate = stats_after.recent_success_rate(stats);
+            let range = self.strategy.new_range(mover.max_range(), rate, self.target_rate, update);
+            let allowed = &self.allowed_ranges[i];
+            mover.set_max_range(range.clamp(allowed.start, allowed.end));
+        }
+    }
+
+    /// Logarithm of the prior volume and energy of every removed level and every live system
+    fn weighted_energies(&self) -> Vec<(f64, f64)> {
+        let k = self.live.len() as f64;
+        let log_shell = (1.0 - (-1.0 / k).exp()).ln();
+        let mut out: Vec<(f64, f64)> = self.levels.iter().enumerate()
+            .map(|(i, level)| (log_shell - i as f64 / k, level.energy)).collect();
+        let log_live = self.log_live_volume() - k.ln();
+        out.extend(self.energies.iter().map(|e| (log_live, *e)));
+        return out;
+    }
+
+    /// Partition function, average energy and heat capacity at a given temperature
+    pub fn thermodynamics(&self, temperature: f64) -> NestedThermodynamics {
+        let weighted = self.weighted_energies();
+        let log_z = log_sum_exp(weighted.iter().map(|(log_w, e)| log_w - e / temperature));
+        let p: Vec<f64> = weighted.iter().map(|(log_w, e)| (log_w - e / temperature - log_z).exp()).collect();
+        let energy: f64 = weighted.iter().zip(p.iter()).map(|((_, e), p)| p * e).sum();
+        let variance: f64 = weighted.iter().zip(p.iter()).map(|((_, e), p)| p * (e - energy) * (e - energy)).sum();
+        NestedThermodynamics { temperature, log_z, energy, heat_capacity: variance / (temperature * temperature) }
+    }
+
+    /// Writes the removed energy levels together with their prior volumes; an empty file name means the standard output
+    pub fn write_levels(&self, out_fname: &str) -> io::Result<()> {
+        let mut out = out_writer(out_fname)?;
+        writeln!(out, "{:>8} {:>16} {:>12}", "level", "energy", "log_volume")?;
+        for (i, level) in self.levels.iter().enumerate() {
+            writeln!(out, "{:>8} {:>16.6} {:>12.5}", i, level.energy, level.log_volume)?;
+        }
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{IsingEnergy, NestedSampling, RandomStreams, SpinFlipMover, SpinLattice, WolffMover};
+
+    #[test]
+    fn self_accepting_mover_is_rejected() {
+        let systems = vec![SpinLattice::new(&[4, 4], 2).unwrap(); 2];
+        let energy = IsingEnergy::new(1.0, 0.0);
+        let mut sampler = NestedSampling::new(systems, &energy, RandomStreams::new(1)).unwrap();
+        let wolff = WolffMover::new(Arc::new(energy), 2.0).unwrap();
+        assert!(sampler.add_mover(Box::new(wolff)).is_err());
+        assert!(sampler.add_mover(Box::new(SpinFlipMover::new())).is_ok());
+    }
+
+    #[test]
+    fn independent_spins_are_reproduced() {
+        // --- N spins in a field h: Z relative to the prior is cosh(h/T)^N and E = -N h tanh(h/T)
+        let (n, h) = (10, 1.0_f64);
+        let mut streams = RandomStreams::new(3);
+        let mut rng = streams.next_stream();
+        let energy = IsingEnergy::new(0.0, h);
+        let systems: Vec<SpinLattice> = (0..1000).map(|_| {
+            let mut s = SpinLattice::new(&[n], 2).unwrap();
+            s.randomize(&mut rng);
+            s
+        }).collect();
+        let mut sampler = NestedSampling::new(systems, &energy, streams).unwrap();
+        sampler.walk_length = 20;
+        sampler.add_mover(Box::new(SpinFlipMover::new())).unwrap();
+        sampler.run(15000, &energy).unwrap();
+        for temperature in [0.5_f64, 1.0, 2.0] {
+            let thermo = sampler.thermodynamics(temperature);
+            let log_z = n as f64 * (h / temperature).cosh().ln();
+            let en = -(n as f64) * h * (h / temperature).tanh();
+            assert!((thermo.log_z - log_z).abs() < 0.3, "T = {}: ln Z = {} instead of {}", temperature, thermo.log_z,
+                    log_z);
+            assert!((thermo.energy - en).abs() < 0.2, "T = {}: E = {} instead of {}", temperature, thermo.energy, en);
+        }
+    }
+}
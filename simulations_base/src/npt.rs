@@ -0,0 +1,87 @@
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::{AcceptanceStatistics, ChangedPositions, Energy, Mover, RandomStream, System};
+
+/// A [`System`] in a simulation box which volume may change, e.g. in the isothermal–isobaric ensemble
+pub trait VolumeSystem: System {
+    /// Volume of the simulation box; an area for a two-dimensional system
+    fn volume(&self) -> f64;
+    /// Changes the volume of the simulation box, scaling every coordinate by the same factor
+    fn set_volume(&mut self, new_volume: f64);
+}
+
+/// Acceptance criterion of the isothermal–isobaric (NPT) ensemble.
+///
+/// A change of energy from `E` to `E'` and of volume from `V` to `V'` is accepted with probability
+/// `min(1, exp(-(E' - E + P (V' - V)) / T + N ln(V' / V)))`, where `N` is the number of particles.
+#[derive(Clone, Debug)]
+pub struct NptCriterion {
+    pub pressure: f64,
+    pub temperature: f64,
+}
+
+impl NptCriterion {
+    pub fn new(pressure: f64, temperature: f64) -> NptCriterion { NptCriterion { pressure, temperature } }
+
+    pub fn check(&self, energy_before: f64, energy_after: f64, volume_before: f64, volume_after: f64, n: usize,
+                 rng: &mut RandomStream) -> bool {
+        if volume_after <= 0.0 { return false; }
+        let arg = -(energy_after - energy_before + self.pressure * (volume_after - volume_before)) / self.temperature
+            + n as f64 * (volume_after / volume_before).ln();
+        return arg >= 0.0 || rng.gen_range(0.0..1.0) < arg.exp();
+    }
+}
+
+/// Changes the volume of a system by a random amount, uniformly distributed in `[-max_range, max_range)`.
+///
+/// Since a volume change rescales all coordinates, the energy of the whole system is evaluated before and after
+/// the move; the move is accepted by its own [`NptCriterion`] (see [`Mover::self_accepting()`]), so the pressure
+/// of the ensemble is set by the `criterion` field of this mover. The temperature of the criterion follows
+/// the temperature of the sampler the mover is added to (see [`Mover::set_temperature()`]).
+pub struct VolumeMover<S: VolumeSystem> {
+    pub criterion: NptCriterion,
+    max_step: f64,
+    energy: Arc<dyn Energy<S>>,
+    succ_rate: AcceptanceStatistics,
+}
+
+impl<S: VolumeSystem> VolumeMover<S> {
+    pub fn new(energy: Arc<dyn Energy<S>>, max_range: f64, pressure: f64, temperature: f64) -> VolumeMover<S> {
+        VolumeMover { criterion: NptCriterion::new(pressure, temperature), max_step: max_range, energy,
+            succ_rate: Default::default() }
+    }
+}
+
+impl<S: VolumeSystem> Mover<S> for VolumeMover<S> {
+
+    fn perturb(&mut self, system: &mut S, rng: &mut RandomStream) -> ChangedPositions {
+        let volume_before = system.volume();
+        let volume_after = volume_before + rng.gen_range(-self.max_step..self.max_step);
+        if volume_after <= 0.0 { return ChangedPositions::Range(0..0); }
+        let en_before = self.energy.energy(system);
+        system.set_volume(volume_after);
+        let en_after = self.energy.energy(system);
+        if self.criterion.check(en_before, en_after, volume_before, volume_after, system.size(), rng) {
+            return ChangedPositions::Range(0..system.size());
+        }
+        return ChangedPositions::Range(0..0);
+    }
+
+    fn acceptance_statistics(&self) -> AcceptanceStatistics { self.succ_rate.clone() }
+
+    fn add_success(&mut self) { self.succ_rate.n_succ += 1; }
+
+    fn add_failure(&mut self) { self.succ_rate.n_failed += 1; }
+
+    fn set_acceptance_statistics(&mut self, stats: AcceptanceStatistics) { self.succ_rate = stats; }
+
+    fn max_range(&self) -> f64 { self.max_step }
+
+    fn set_max_range(&mut self, new_val: f64) { self.max_step = new_val; }
+
+    fn self_accepting(&self) -> bool { true }
+
+    fn set_temperature(&mut self, temperature: f64) { self.criterion.temperature = temperature; }
+}
@@ -0,0 +1,256 @@
+use std::fs::File;
+use std::io;
+use std::io::{stdout, BufWriter, Write};
+use std::time::{Duration, Instant};
+
+use crate::{AcceptanceCriterion, AcceptanceStatistics, Checkpoint, CheckpointSystem, Energy, MoverState,
+            MoversSetSampler, SimulationError, System, TemperatureSchedule};
+
+/// Snapshot of a running simulation, passed to every [`Observer`]
+pub struct Observation<'a, S> {
+    /// number of sweeps made so far
+    pub sweep: usize,
+    /// index of the observed chain when several chains are simulated in parallel, 0 otherwise
+    pub chain: usize,
+    pub system: &'a S,
+    /// total energy of the observed system
+    pub energy: f64,
+    /// temperature of the sampler
+    pub temperature: f64,
+    /// step sizes and acceptance statistics of all movers of the sampler
+    pub movers: &'a [MoverState],
+}
+
+/// Collects data from a running simulation.
+///
+/// Observers are registered in a [`SimulationDriver`] which calls [`observe()`](Observer::observe) at
+/// a requested interval, [`flush()`](Observer::flush) after every [`run()`](SimulationDriver::run)
+/// and [`finalize()`](Observer::finalize) at the very end of a simulation.
+pub trait Observer<S>: Send {
+    fn observe(&mut self, observation: &Observation<S>);
+    fn flush(&mut self) {}
+    fn finalize(&mut self) { self.flush(); }
+}
+
+/// Opens a file for writing; an empty file name means the standard output
+pub fn out_writer(out_fname: &str) -> io::Result<BufWriter<Box<dyn Write + Send>>> {
+    let out: Box<dyn Write + Send> = if out_fname.is_empty() { Box::new(stdout()) } else { Box::new(File::create(out_fname)?) };
+    Ok(BufWriter::new(out))
+}
+
+/// Opens a file for appending, creating it when necessary; an empty file name means the standard output
+pub fn append_writer(out_fname: &str) -> io::Result<BufWriter<Box<dyn Write + Send>>> {
+    if out_fname.is_empty() { return out_writer(out_fname); }
+    let out: Box<dyn Write + Send> = Box::new(File::options().append(true).create(true).open(out_fname)?);
+    Ok(BufWriter::new(out))
+}
+
+/// Runs a sampler in blocks of sweeps and calls observers between the blocks.
+///
+/// If a [`TemperatureSchedule`] is set, the driver changes the temperature of the sampler before every block;
+/// the schedule counts blocks from the sweep at which it was set.
+/// When `track_lowest_energy` is set, the driver works as a minimiser: it keeps a copy of the lowest-energy
+/// system found at the end of a block, which is available from
+/// [`lowest_block_energy()`](SimulationDriver::lowest_block_energy).
+/// Only block-end snapshots are compared, so a lower energy visited within a block is missed; use shorter blocks
+/// for a finer search.
+pub struct SimulationDriver<T: AcceptanceCriterion, S: System> {
+    pub sweeps_per_block: usize,
+    pub track_lowest_energy: bool,
+    sampler: Box<dyn MoversSetSampler<T, S>>,
+    observers: Vec<(usize, Box<dyn Observer<S>>)>,
+    schedule: Option<Box<dyn TemperatureSchedule>>,
+    schedule_start: usize,
+    lowest: Option<(f64, S)>,
+    sweep: usize,
+}
+
+impl<T: AcceptanceCriterion, S: System> SimulationDriver<T, S> {
+    pub fn new(sampler: Box<dyn MoversSetSampler<T, S>>, sweeps_per_block: usize) -> SimulationDriver<T, S> {
+        SimulationDriver { sweeps_per_block, track_lowest_energy: false, sampler, observers: vec![], schedule: None,
+            schedule_start: 0, lowest: None, sweep: 0 }
+    }
+
+    /// Sets a temperature schedule; the block counter of the schedule starts from the current sweep of this driver.
+    ///
+    /// A schedule set before a checkpoint is restored continues from the restored sweep, as if the run
+    /// had not been interrupted.
+    pub fn set_schedule(&mut self, schedule: Box<dyn TemperatureSchedule>) {
+        self.schedule = Some(schedule);
+        self.schedule_start = self.sweep;
+    }
+
+    /// The lowest energy found so far at the end of a block and the system that has that energy
+    pub fn lowest_block_energy(&self) -> Option<(f64, &S)> { self.lowest.as_ref().map(|(en, system)| (*en, system)) }
+
+    /// Registers an observer that is called after every `every_n_blocks` blocks of sweeps
+    pub fn add_observer(&mut self, observer: Box<dyn Observer<S>>, every_n_blocks: usize) {
+        self.observers.push((every_n_blocks.max(1), observer));
+    }
+
+    pub fn sampler(&mut self) -> &mut dyn MoversSetSampler<T, S> { self.sampler.as_mut() }
+
+    /// Number of sweeps made so far
+    pub fn sweep(&self) -> usize { self.sweep }
+
+    /// Runs `n_blocks` blocks of sweeps, observing the system after blocks as requested by each observer
+    pub fn run(&mut self, n_blocks: usize, system: &mut S, energy: &dyn Energy<S>) -> Result<(), SimulationError> {
+        for _ in 0..n_blocks {
+            if let Some(schedule) = &self.schedule {
+                let block = self.sweep.saturating_sub(self.schedule_start) / self.sweeps_per_block.max(1);
+                self.sampler.set_temperature(schedule.temperature(block));
+            }
+            self.sampler.make_sweeps(self.sweeps_per_block, system, energy)?;
+            self.sweep += self.sweeps_per_block;
+            let block = self.sweep / self.sweeps_per_block.max(1);
+            let observe_now = self.observers.iter().any(|(every, _)| block.is_multiple_of(*every));
+            if !observe_now && !self.track_lowest_energy { continue; }
+
+            let total_energy = energy.energy(system);
+            if self.track_lowest_energy && self.lowest.as_ref().is_none_or(|(en, _)| total_energy < *en) {
+                self.lowest = Some((total_energy, system.clone()));
+            }
+            if observe_now { self.observe(block, system, total_energy)?; }
+        }
+        for (_, observer) in self.observers.iter_mut() { observer.flush(); }
+        Ok(())
+    }
+
+    /// Finalizes all the observers; should be called once, when the simulation is over
+    pub fn finalize(&mut self) {
+        for (_, observer) in self.observers.iter_mut() { observer.finalize(); }
+    }
+
+    fn observe(&mut self, block: usize, system: &S, energy: f64) -> Result<(), SimulationError> {
+        let mut movers: Vec<MoverState> = vec![];
+        for i in 0..self.sampler.count_movers() { movers.push(MoverState::new(self.sampler.get_mover(i)?.as_ref())); }
+        let observation = Observation { sweep: self.sweep, chain: 0, system, energy,
+            temperature: self.sampler.temperature(), movers: &movers };
+        for (every, observer) in self.observers.iter_mut() {
+            if block.is_multiple_of(*every) { observer.observe(&observation); }
+        }
+        Ok(())
+    }
+}
+
+impl<T: AcceptanceCriterion, S: CheckpointSystem> SimulationDriver<T, S> {
+    /// Saves the system, the state of the sampler and the sweep counter in a checkpoint file
+    pub fn save_checkpoint(&self, out_fname: &str, system: &S) -> io::Result<()> {
+        Checkpoint::new(self.sweep, system, self.sampler.as_ref()).save(out_fname)
+    }
+
+    /// Restores a simulation from a checkpoint file
+    pub fn restore_checkpoint(&mut self, in_fname: &str, system: &mut S) -> io::Result<()> {
+        self.sweep = Checkpoint::load(in_fname)?.restore(system, self.sampler.as_mut())?;
+        Ok(())
+    }
+}
+
+/// Writes the total energy of a system: one line per observation
+pub struct EnergyObserver {
+    out: BufWriter<Box<dyn Write + Send>>,
+}
+
+impl EnergyObserver {
+    pub fn new(out_fname: &str) -> io::Result<EnergyObserver> { Ok(EnergyObserver { out: out_writer(out_fname)? }) }
+
+    /// Appends to an existing file, e.g. when a simulation is restarted from a checkpoint
+    pub fn appending(out_fname: &str) -> io::Result<EnergyObserver> { Ok(EnergyObserver { out: append_writer(out_fname)? }) }
+}
+
+impl<S> Observer<S> for EnergyObserver {
+    fn observe(&mut self, observation: &Observation<S>) {
+        writeln!(self.out, "{:8} {:.4}", observation.sweep, observation.energy).ok();
+    }
+
+    fn flush(&mut self) { self.out.flush().ok(); }
+}
+
+/// Writes the success rate of every mover since the previous observation, followed by its current step size
+pub struct AcceptanceObserver {
+    out: BufWriter<Box<dyn Write + Send>>,
+    previous: Vec<AcceptanceStatistics>,
+}
+
+impl AcceptanceObserver {
+    pub fn new(out_fname: &str) -> io::Result<AcceptanceObserver> {
+        Ok(AcceptanceObserver { out: out_writer(out_fname)?, previous: vec![] })
+    }
+
+    /// Appends to an existing file, e.g. when a simulation is restarted from a checkpoint
+    pub fn appending(out_fname: &str) -> io::Result<AcceptanceObserver> {
+        Ok(AcceptanceObserver { out: append_writer(out_fname)?, previous: vec![] })
+    }
+}
+
+impl<S> Observer<S> for AcceptanceObserver {
+    fn observe(&mut self, observation: &Observation<S>) {
+        self.previous.resize(observation.movers.len(), AcceptanceStatistics::default());
+        write!(self.out, "{:8}", observation.sweep).ok();
+        for (mover, previous) in observation.movers.iter().zip(self.previous.iter_mut()) {
+            write!(self.out, " {:.4} {:.4}", mover.statistics.recent_success_rate(previous), mover.max_range).ok();
+            *previous = mover.statistics.clone();
+        }
+        writeln!(self.out).ok();
+    }
+
+    fn flush(&mut self) { self.out.flush().ok(); }
+}
+
+/// Writes the wall-clock time elapsed since the observer was created and the average time of a single sweep
+/// made since the previous observation
+pub struct TimingObserver {
+    out: BufWriter<Box<dyn Write + Send>>,
+    start: Instant,
+    previous: Option<(Instant, usize)>,
+}
+
+impl TimingObserver {
+    pub fn new(out_fname: &str) -> io::Result<TimingObserver> {
+        Ok(TimingObserver { out: out_writer(out_fname)?, start: Instant::now(), previous: None })
+    }
+
+    /// Appends to an existing file; the elapsed time is counted from the restart
+    pub fn appending(out_fname: &str) -> io::Result<TimingObserver> {
+        Ok(TimingObserver { out: append_writer(out_fname)?, start: Instant::now(), previous: None })
+    }
+}
+
+impl<S> Observer<S> for TimingObserver {
+    fn observe(&mut self, observation: &Observation<S>) {
+        let now = Instant::now();
+        write!(self.out, "{:8} {:.2?}", observation.sweep, now - self.start).ok();
+        if let Some((time, sweep)) = self.previous {
+            if observation.sweep > sweep {
+                let per_sweep = (now - time).as_secs_f64() / (observation.sweep - sweep) as f64;
+                write!(self.out, " {:.2?}/sweep", Duration::from_secs_f64(per_sweep)).ok();
+            }
+        }
+        writeln!(self.out).ok();
+        self.previous = Some((now, observation.sweep));
+    }
+
+    fn flush(&mut self) { self.out.flush().ok(); }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{IsingEnergy, MCProtocol, MetropolisCriterion, MoversSet, MoversSetSampler, RandomStreams,
+                SimulationDriver, SpinFlipMover, SpinLattice};
+
+    #[test]
+    fn schedule_counts_blocks_from_when_it_was_set() {
+        let energy = IsingEnergy::new(1.0, 0.0);
+        let mut system = SpinLattice::new(&[3, 3], 2).unwrap();
+        let mut protocol = MCProtocol::with_streams(MetropolisCriterion::new(1.0), RandomStreams::new(1));
+        protocol.add_mover(Box::new(SpinFlipMover::new()));
+        let sampler: Box<dyn MoversSetSampler<MetropolisCriterion, SpinLattice>> = Box::new(protocol);
+        let mut driver = SimulationDriver::new(sampler, 10);
+        driver.run(3, &mut system, &energy).unwrap();
+        driver.set_schedule(Box::new(|block: usize| 5.0 + block as f64));
+        driver.run(1, &mut system, &energy).unwrap();
+        assert_eq!(driver.sampler().temperature(), 5.0);
+        driver.run(2, &mut system, &energy).unwrap();
+        assert_eq!(driver.sampler().temperature(), 7.0);
+    }
+}
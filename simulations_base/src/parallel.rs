@@ -0,0 +1,141 @@
+use std::io;
+use std::io::Write;
+
+use rayon::prelude::*;
+
+use crate::{gelman_rubin, out_writer, AcceptanceCriterion, AcceptanceStatistics, Energy, MoverState, Observation,
+            Observer, RandomStreams, SeriesSummary, SimulationDriver, SimulationError, System, TimeSeries};
+
+/// Runs independent Monte Carlo chains of the same protocol in parallel.
+///
+/// Every chain has its own [`SimulationDriver`] and its own copy of the system; observers registered in the driver
+/// of a chain observe that chain only. Observers registered in `ParallelChains` are shared by all the chains: after
+/// every block they observe each chain in turn, in the order of chains given by [`Observation::chain`], so their
+/// output doesn't depend on thread scheduling. The energy of every chain is recorded after each block, except
+/// the first `burn_in` blocks.
+///
+/// Chains are distributed over rayon's thread pool; the number of threads may be set by the `RAYON_NUM_THREADS`
+/// environment variable.
+pub struct ParallelChains<T: AcceptanceCriterion, S: System> {
+    pub burn_in: usize,
+    drivers: Vec<SimulationDriver<T, S>>,
+    systems: Vec<S>,
+    energies: Vec<TimeSeries>,
+    observers: Vec<(usize, Box<dyn Observer<S>>)>,
+    block: usize,
+}
+
+impl<T: AcceptanceCriterion, S: System> ParallelChains<T, S> {
+    /// Creates `n_chains` chains, all starting from a copy of the given system.
+    ///
+    /// The `build_driver` closure is called for every chain with the index of that chain and random streams
+    /// split from `streams`; the chains are therefore independent, yet the whole run can be reproduced.
+    pub fn new<F>(n_chains: usize, system: &S, mut streams: RandomStreams, build_driver: F) -> ParallelChains<T, S>
+        where F: Fn(usize, RandomStreams) -> SimulationDriver<T, S> {
+        let drivers = (0..n_chains).map(|i| build_driver(i, streams.split())).collect();
+        let energies = (0..n_chains).map(|i| TimeSeries::new(&format!("energy_{}", i))).collect();
+        ParallelChains { burn_in: 0, drivers, systems: vec![system.clone(); n_chains], energies, observers: vec![],
+            block: 0 }
+    }
+
+    pub fn count_chains(&self) -> usize { self.drivers.len() }
+
+    /// Registers an observer shared by all the chains, called after every `every_n_blocks` blocks of sweeps
+    pub fn add_observer(&mut self, observer: Box<dyn Observer<S>>, every_n_blocks: usize) {
+        self.observers.push((every_n_blocks.max(1), observer));
+    }
+
+    /// Driver of a chain or [`SimulationError::InvalidParameter`] when there's no chain of that index
+    pub fn driver(&mut self, chain: usize) -> Result<&mut SimulationDriver<T, S>, SimulationError> {
+        let n_chains = self.drivers.len();
+        self.drivers.get_mut(chain).ok_or_else(|| chain_index_error(chain, n_chains))
+    }
+
+    /// System of a chain or [`SimulationError::InvalidParameter`] when there's no chain of that index
+    pub fn system(&self, chain: usize) -> Result<&S, SimulationError> {
+        self.systems.get(chain).ok_or_else(|| chain_index_error(chain, self.systems.len()))
+    }
+
+    /// Energies recorded so far, separately for every chain
+    pub fn energies(&self) -> &Vec<TimeSeries> { &self.energies }
+
+    /// Gelman–Rubin R-hat of the energy, computed over all the chains
+    pub fn energy_rhat(&self) -> f64 { gelman_rubin(&self.energies) }
+
+    /// Acceptance statistics of every mover, summed over all the chains
+    pub fn acceptance_statistics(&mut self) -> Result<Vec<AcceptanceStatistics>, SimulationError> {
+        let mut merged: Vec<AcceptanceStatistics> = vec![];
+        for driver in self.drivers.iter_mut() {
+            let sampler = driver.sampler();
+            merged.resize(sampler.count_movers().max(merged.len()), AcceptanceStatistics::default());
+            for (i, stats) in merged.iter_mut().enumerate().take(sampler.count_movers()) {
+                let s = sampler.get_mover(i)?.acceptance_statistics();
+                stats.n_succ += s.n_succ;
+                stats.n_failed += s.n_failed;
+            }
+        }
+        return Ok(merged);
+    }
+
+    /// Runs `n_blocks` blocks of sweeps on every chain; chains are synchronised after every block.
+    ///
+    /// When any chain fails, the first error (in the order of chains) is returned.
+    pub fn run(&mut self, n_blocks: usize, energy: &dyn Energy<S>) -> Result<(), SimulationError> {
+        for _ in 0..n_blocks {
+            let results: Vec<Result<(), SimulationError>> = self.drivers.par_iter_mut().zip(self.systems.par_iter_mut())
+                .map(|(driver, system)| driver.run(1, system, energy)).collect();
+            for result in results { result?; }
+            self.block += 1;
+            self.observe(energy)?;
+        }
+        for (_, observer) in self.observers.iter_mut() { observer.flush(); }
+        Ok(())
+    }
+
+    /// Finalizes the observers of every chain and the shared observers
+    pub fn finalize(&mut self) {
+        for driver in self.drivers.iter_mut() { driver.finalize(); }
+        for (_, observer) in self.observers.iter_mut() { observer.finalize(); }
+    }
+
+    /// Writes statistics of the energy of every chain, followed by the average over all the chains and R-hat
+    pub fn write_report(&self, out_fname: &str) -> io::Result<()> {
+        let mut out = out_writer(out_fname)?;
+        writeln!(out, "{}", SeriesSummary::header())?;
+        for series in self.energies.iter() { writeln!(out, "{}", series.summary())?; }
+        let n = self.energies.len() as f64;
+        let mean = self.energies.iter().map(|s| s.mean()).sum::<f64>() / n;
+        let error = self.energies.iter().map(|s| s.standard_error().powi(2)).sum::<f64>().sqrt() / n;
+        writeln!(out, "energy averaged over {} chains: {:.4} +/- {:.4}, R-hat: {:.4}", self.energies.len(), mean,
+                 error, self.energy_rhat())?;
+        out.flush()
+    }
+
+    fn observe(&mut self, energy: &dyn Energy<S>) -> Result<(), SimulationError> {
+        let record = self.block > self.burn_in;
+        let observe_now = self.observers.iter().any(|(every, _)| self.block.is_multiple_of(*every));
+        if !record && !observe_now { return Ok(()); }
+
+        for chain in 0..self.drivers.len() {
+            let total_energy = energy.energy(&self.systems[chain]);
+            if record { self.energies[chain].push(total_energy); }
+            if !observe_now { continue; }
+
+            let driver = &mut self.drivers[chain];
+            let sweep = driver.sweep();
+            let sampler = driver.sampler();
+            let mut movers: Vec<MoverState> = vec![];
+            for i in 0..sampler.count_movers() { movers.push(MoverState::new(sampler.get_mover(i)?.as_ref())); }
+            let observation = Observation { sweep, chain, system: &self.systems[chain], energy: total_energy,
+                temperature: sampler.temperature(), movers: &movers };
+            for (every, observer) in self.observers.iter_mut() {
+                if self.block.is_multiple_of(*every) { observer.observe(&observation); }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn chain_index_error(chain: usize, n_chains: usize) -> SimulationError {
+    SimulationError::InvalidParameter(format!("chain index {} out of range of {} chains", chain, n_chains))
+}
@@ -0,0 +1,51 @@
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+/// Random number generator used by samplers, movers and acceptance criteria
+pub type RandomStream = Xoshiro256PlusPlus;
+
+/// Provides independent random streams derived from a single master seed.
+///
+/// Subsequent streams are separated by a xoshiro jump of 2^128 steps, so they never overlap in practice.
+/// [`split()`](RandomStreams::split) creates a whole new family of streams (2^192 steps apart),
+/// which is useful when a sampler creates other samplers, e.g. one per replica.
+/// Two simulations that derive their streams in the same order from the same seed are bit-identical.
+#[derive(Clone, Debug)]
+pub struct RandomStreams {
+    next: Xoshiro256PlusPlus
+}
+
+impl RandomStreams {
+    /// Creates streams derived from a given master seed
+    pub fn new(seed: u64) -> RandomStreams { RandomStreams { next: Xoshiro256PlusPlus::seed_from_u64(seed) } }
+
+    /// Creates streams seeded from the operating system's entropy source; such a run can't be reproduced
+    pub fn from_entropy() -> RandomStreams { RandomStreams { next: Xoshiro256PlusPlus::from_entropy() } }
+
+    /// Returns a new random stream, independent of all the streams returned so far
+    pub fn next_stream(&mut self) -> RandomStream {
+        let stream = self.next.clone();
+        self.next.jump();
+        return stream;
+    }
+
+    /// Creates streams that continue from a state saved by [`state()`](RandomStreams::state)
+    pub(crate) fn from_state(next: RandomStream) -> RandomStreams { RandomStreams { next } }
+
+    /// The state of the generator the next stream will be copied from
+    pub(crate) fn state(&self) -> &RandomStream { &self.next }
+
+    /// Returns a new family of streams, independent of this one
+    pub fn split(&mut self) -> RandomStreams {
+        let streams = RandomStreams { next: self.next.clone() };
+        self.next.long_jump();
+        return streams;
+    }
+}
+
+/// Draws a number from the standard normal distribution, using the Box–Muller transform
+pub fn gaussian(rng: &mut RandomStream) -> f64 {
+    let u1: f64 = 1.0 - rng.gen_range(0.0..1.0);
+    let u2: f64 = rng.gen_range(0.0..1.0);
+    return (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+}
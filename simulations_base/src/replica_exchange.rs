@@ -0,0 +1,325 @@
+use std::io;
+use rand::Rng;
+
+use crate::{AcceptanceStatistics, Energy, EnergyInconsistency, SimulationError, MetropolisCriterion, Mover, MoversSet, MoversSetSampler, Sampler, System};
+use crate::{RandomStream, RandomStreams, ReplicaExchangeState, SamplerCheckpoint, SamplerState};
+use crate::checkpoint::{invalid_state, wrong_sampler};
+
+/// Replica exchange (parallel tempering) protocol.
+///
+/// A copy of a system is simulated at every temperature of a ladder, each by its own sampler
+/// with its own movers and [`MetropolisCriterion`]. Every `exchange_interval` sweeps the protocol
+/// attempts to swap configurations between neighbouring replicas; sweeps are counted across calls
+/// of [`make_sweeps()`](Sampler::make_sweeps), so an exchange isn't skipped when a single call makes fewer sweeps.
+///
+/// Every replica must have the same number of movers, best added by
+/// [`add_movers()`](ReplicaExchangeProtocol::add_movers); [`make_sweeps()`](Sampler::make_sweeps) returns an error
+/// otherwise.
+///
+/// The system passed to [`make_sweeps()`](Sampler::make_sweeps) is the replica simulated at the first
+/// temperature of the ladder; the remaining replicas are owned by the protocol.
+pub struct ReplicaExchangeProtocol<S: System> {
+    pub exchange_interval: usize,
+    temperatures: Vec<f64>,
+    samplers: Vec<Box<dyn MoversSetSampler<MetropolisCriterion, S>>>,
+    replicas: Vec<S>,
+    swap_stats: Vec<AcceptanceStatistics>,
+    n_exchanges: usize,
+    sweeps_since_exchange: usize,
+    rng: RandomStream,
+}
+
+impl<S: System> ReplicaExchangeProtocol<S> {
+    /// Creates a replica exchange protocol for a given temperature ladder, seeded from the entropy source.
+    ///
+    /// The `build_sampler` closure is called once for every temperature; it receives the acceptance
+    /// criterion for that temperature and random streams for the new sampler, and should return
+    /// a sampler equipped with movers. All replicas start from a copy of the given `system`.
+    /// The temperature ladder must not be empty and all its temperatures must be positive.
+    pub fn new<F>(temperatures: &[f64], system: &S, build_sampler: F) -> Result<ReplicaExchangeProtocol<S>, SimulationError>
+        where F: Fn(MetropolisCriterion, RandomStreams) -> Box<dyn MoversSetSampler<MetropolisCriterion, S>> {
+        ReplicaExchangeProtocol::with_streams(temperatures, system, RandomStreams::from_entropy(), build_sampler)
+    }
+
+    /// Creates a replica exchange protocol which derives all its random streams from the given ones
+    pub fn with_streams<F>(temperatures: &[f64], system: &S, mut streams: RandomStreams, build_sampler: F)
+            -> Result<ReplicaExchangeProtocol<S>, SimulationError>
+        where F: Fn(MetropolisCriterion, RandomStreams) -> Box<dyn MoversSetSampler<MetropolisCriterion, S>> {
+
+        if temperatures.is_empty() {
+            return Err(SimulationError::InvalidParameter("the temperature ladder is empty".to_string()));
+        }
+        if let Some(t) = temperatures.iter().find(|t| **t <= 0.0 || !t.is_finite()) {
+            return Err(SimulationError::InvalidParameter(format!("temperature {} is not positive", t)));
+        }
+        let rng = streams.next_stream();
+        let samplers = temperatures.iter()
+            .map(|t| build_sampler(MetropolisCriterion::new(*t), streams.split())).collect();
+        let n_pairs = temperatures.len().saturating_sub(1);
+        Ok(ReplicaExchangeProtocol { exchange_interval: 1, temperatures: temperatures.to_vec(), samplers,
+            replicas: vec![system.clone(); n_pairs], swap_stats: vec![AcceptanceStatistics::default(); n_pairs],
+            n_exchanges: 0, sweeps_since_exchange: 0, rng })
+    }
+
+    pub fn temperatures(&self) -> &Vec<f64> { &self.temperatures }
+
+    pub fn count_replicas(&self) -> usize { self.temperatures.len() }
+
+    /// Returns the replica simulated at the i-th temperature of the ladder.
+    ///
+    /// The replica at the first temperature is the system passed to [`make_sweeps()`](Sampler::make_sweeps),
+    /// therefore this method returns `None` for `i == 0`.
+    pub fn replica(&self, i: usize) -> Option<&S> {
+        if i == 0 { return None; }
+        return self.replicas.get(i - 1);
+    }
+
+    /// Acceptance statistics of swaps between replicas `pair` and `pair + 1`, or `None` if there is no such pair
+    pub fn swap_statistics(&self, pair: usize) -> Option<AcceptanceStatistics> { self.swap_stats.get(pair).cloned() }
+
+    /// Acceptance statistics of swaps for every pair of neighbouring replicas
+    pub fn all_swap_statistics(&self) -> &Vec<AcceptanceStatistics> { &self.swap_stats }
+
+    /// Adds a mover to the sampler of the i-th replica
+    pub fn add_mover_to(&mut self, replica: usize, mover: Box<dyn Mover<S>>) -> Result<(), SimulationError> {
+        let count = self.samplers.len();
+        match self.samplers.get_mut(replica) {
+            Some(sampler) => {
+                sampler.add_mover(mover);
+                Ok(())
+            }
+            None => Err(SimulationError::InvalidParameter(format!("replica index {} out of range of {} replicas",
+                replica, count))),
+        }
+    }
+
+    /// Adds a mover created by `factory` to every replica
+    pub fn add_movers<F>(&mut self, factory: F) where F: Fn() -> Box<dyn Mover<S>> {
+        for sampler in self.samplers.iter_mut() { sampler.add_mover(factory()); }
+    }
+
+    /// Checks that every replica has the same number of movers
+    fn check_movers(&self) -> Result<(), SimulationError> {
+        let counts: Vec<usize> = self.samplers.iter().map(|s| s.count_movers()).collect();
+        if counts.iter().all(|c| *c == counts[0]) { return Ok(()); }
+        return Err(SimulationError::InvalidParameter(format!("replicas have different numbers of movers: {:?}",
+            counts)));
+    }
+
+    /// Attempts swaps between neighbouring replicas.
+    ///
+    /// Even pairs (0-1, 2-3, ...) and odd pairs (1-2, 3-4, ...) are tried alternately in subsequent calls.
+    fn exchange_replicas(&mut self, coords: &mut S, energy: &dyn Energy<S>) {
+        let first = self.n_exchanges % 2;
+        self.n_exchanges += 1;
+        for pair in (first..self.swap_stats.len()).step_by(2) {
+            let (en_i, en_j) = if pair == 0 {
+                (energy.energy(coords), energy.energy(&self.replicas[0]))
+            } else {
+                (energy.energy(&self.replicas[pair - 1]), energy.energy(&self.replicas[pair]))
+            };
+            let delta = (1.0 / self.temperatures[pair] - 1.0 / self.temperatures[pair + 1]) * (en_i - en_j);
+            if delta >= 0.0 || self.rng.gen_range(0.0..1.0) <= delta.exp() {
+                if pair == 0 { std::mem::swap(coords, &mut self.replicas[0]); }
+                else { self.replicas.swap(pair - 1, pair); }
+                self.swap_stats[pair].n_succ += 1;
+            } else {
+                self.swap_stats[pair].n_failed += 1;
+            }
+        }
+    }
+}
+
+impl<S: System> Sampler<MetropolisCriterion, S> for ReplicaExchangeProtocol<S> {
+
+    fn make_sweeps(&mut self, n: usize, coords: &mut S, energy: &dyn Energy<S>) -> Result<(), SimulationError> {
+        self.check_movers()?;
+        let interval = self.exchange_interval.max(1);
+        let mut n_done = 0;
+        while n_done < n {
+            let n_block = interval.saturating_sub(self.sweeps_since_exchange).min(n - n_done);
+            self.samplers[0].make_sweeps(n_block, coords, energy)?;
+            for i in 1..self.samplers.len() {
+                self.samplers[i].make_sweeps(n_block, &mut self.replicas[i - 1], energy)?;
+            }
+            n_done += n_block;
+            self.sweeps_since_exchange += n_block;
+            if self.sweeps_since_exchange >= interval {
+                self.exchange_replicas(coords, energy);
+                self.sweeps_since_exchange = 0;
+            }
+        }
+        Ok(())
+    }
+
+    fn temperature(&self) -> f64 { self.temperatures[0] }
+
+    /// Rescales the whole temperature ladder so that its first temperature becomes equal to `temperature`
+    fn set_temperature(&mut self, temperature: f64) {
+        let scale = temperature / self.temperatures[0];
+        for (t, sampler) in self.temperatures.iter_mut().zip(self.samplers.iter_mut()) {
+            *t *= scale;
+            sampler.set_temperature(*t);
+        }
+    }
+
+    /// Inconsistencies of all the replicas; mover indexes refer to the flat set of movers of this protocol
+    fn energy_inconsistencies(&self) -> Vec<EnergyInconsistency> {
+        let mut out = vec![];
+        let mut first_mover = 0;
+        for sampler in self.samplers.iter() {
+            out.extend(sampler.energy_inconsistencies().into_iter()
+                .map(|e| EnergyInconsistency { mover: e.mover + first_mover, ..e }));
+            first_mover += sampler.count_movers();
+        }
+        return out;
+    }
+}
+
+/// Movers of all the replicas are visible as a single flat set: movers of the first replica come first,
+/// followed by the movers of the second one, etc.
+impl<S: System> MoversSet<MetropolisCriterion, S> for ReplicaExchangeProtocol<S> {
+
+    /// Adds a mover to the replica that has the fewest movers.
+    ///
+    /// Since movers can't be cloned, every mover must be added once per replica; use
+    /// [`add_movers()`](ReplicaExchangeProtocol::add_movers) or
+    /// [`add_mover_to()`](ReplicaExchangeProtocol::add_mover_to) to say explicitly which replica gets a mover
+    fn add_mover(&mut self, perturb_fn: Box<dyn Mover<S>>) {
+        if let Some(sampler) = self.samplers.iter_mut().min_by_key(|s| s.count_movers()) {
+            sampler.add_mover(perturb_fn);
+        }
+    }
+
+    fn get_mover(&mut self, which_one: usize) -> Result<&mut Box<dyn Mover<S>>, SimulationError> {
+        let mut idx = which_one;
+        for i in 0..self.samplers.len() {
+            let n = self.samplers[i].count_movers();
+            if idx < n { return self.samplers[i].get_mover(idx); }
+            idx -= n;
+        }
+        Err(SimulationError::MoverIndex { index: which_one, count: self.count_movers() })
+    }
+
+    fn count_movers(&self) -> usize { self.samplers.iter().map(|s| s.count_movers()).sum() }
+}
+
+impl<S: System> MoversSetSampler<MetropolisCriterion, S> for ReplicaExchangeProtocol<S> {}
+
+/// Stores the replicas, the temperature ladder, swap statistics and the swap random stream,
+/// followed by states of all the replica samplers
+impl<S: System> SamplerCheckpoint<S> for ReplicaExchangeProtocol<S> {
+    fn save_state(&self) -> SamplerState<S> {
+        return SamplerState::ReplicaExchangeProtocol(ReplicaExchangeState {
+            n_exchanges: self.n_exchanges,
+            sweeps_since_exchange: self.sweeps_since_exchange,
+            temperatures: self.temperatures.clone(),
+            rng: self.rng.clone(),
+            swap_stats: self.swap_stats.clone(),
+            replicas: self.replicas.clone(),
+            samplers: self.samplers.iter().map(|s| s.save_state()).collect(),
+        });
+    }
+
+    fn restore_state(&mut self, state: SamplerState<S>) -> io::Result<()> {
+        let state = match state {
+            SamplerState::ReplicaExchangeProtocol(state) => state,
+            other => return Err(wrong_sampler("ReplicaExchangeProtocol", &other)),
+        };
+        if state.samplers.len() != self.samplers.len() || state.replicas.len() != self.replicas.len()
+            || state.swap_stats.len() != self.swap_stats.len() || state.temperatures.len() != self.temperatures.len() {
+            return Err(invalid_state(format!("checkpoint doesn't hold a state of {} replicas", self.samplers.len())));
+        }
+        for (sampler, sampler_state) in self.samplers.iter_mut().zip(state.samplers) {
+            sampler.restore_state(sampler_state)?;
+        }
+        self.n_exchanges = state.n_exchanges;
+        self.sweeps_since_exchange = state.sweeps_since_exchange;
+        self.temperatures = state.temperatures;
+        self.rng = state.rng;
+        self.swap_stats = state.swap_stats;
+        self.replicas = state.replicas;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Energy, IsingEnergy, MCProtocol, MetropolisCriterion, MoversSet, MoversSetSampler, RandomStreams,
+                ReplicaExchangeProtocol, Sampler, SamplerCheckpoint, SpinFlipMover, SpinLattice};
+
+    fn ladder(temperatures: &[f64], seed: u64) -> ReplicaExchangeProtocol<SpinLattice> {
+        let mut sampler = ReplicaExchangeProtocol::with_streams(temperatures, &SpinLattice::new(&[3, 3], 2).unwrap(),
+            RandomStreams::new(seed), |criterion, streams| {
+                let s: Box<dyn MoversSetSampler<MetropolisCriterion, SpinLattice>> =
+                    Box::new(MCProtocol::with_streams(criterion, streams));
+                s
+            }).unwrap();
+        sampler.add_movers(|| Box::new(SpinFlipMover::new()));
+        return sampler;
+    }
+
+    #[test]
+    fn replicas_at_equal_temperatures_always_swap() {
+        let energy = IsingEnergy::new(1.0, 0.0);
+        let mut sampler = ladder(&[2.0, 2.0, 2.0], 1);
+        sampler.exchange_interval = 10;
+        let mut system = SpinLattice::new(&[3, 3], 2).unwrap();
+        // --- sweeps are counted across calls: 8 calls of 5 sweeps make 4 exchanges
+        for _ in 0..8 { sampler.make_sweeps(5, &mut system, &energy).unwrap(); }
+        let stats = sampler.all_swap_statistics();
+        assert_eq!(stats[0].n_succ + stats[1].n_succ, 4);
+        assert!(stats.iter().all(|s| s.n_failed == 0));
+        if let crate::SamplerState::ReplicaExchangeProtocol(state) = sampler.save_state() {
+            assert_eq!(state.sweeps_since_exchange, 0);
+        } else { panic!("wrong state kind"); }
+    }
+
+    #[test]
+    fn swap_rate_matches_exact_average() {
+        let temperatures = [1.5, 2.5, 3.5];
+        let energy = IsingEnergy::new(1.0, 0.0);
+        // --- energies of all the 512 states of the 3 x 3 lattice
+        let mut system = SpinLattice::new(&[3, 3], 2).unwrap();
+        let energies: Vec<f64> = (0..1 << 9).map(|s: usize| {
+            for i in 0..9 { system.set_spin(i, ((s >> i) & 1) as u8); }
+            energy.energy(&system)
+        }).collect();
+        let boltzmann = |t: f64| -> Vec<f64> {
+            let w: Vec<f64> = energies.iter().map(|e| (-e / t).exp()).collect();
+            let z: f64 = w.iter().sum();
+            w.iter().map(|x| x / z).collect()
+        };
+
+        let mut sampler = ladder(&temperatures, 5);
+        sampler.exchange_interval = 2;
+        sampler.make_sweeps(200, &mut system, &energy).unwrap();
+        let before = sampler.all_swap_statistics().clone();
+        sampler.make_sweeps(40000, &mut system, &energy).unwrap();
+        for pair in 0..2 {
+            let (p_i, p_j) = (boltzmann(temperatures[pair]), boltzmann(temperatures[pair + 1]));
+            let d_beta = 1.0 / temperatures[pair] - 1.0 / temperatures[pair + 1];
+            let mut exact = 0.0;
+            for (e_i, w_i) in energies.iter().zip(p_i.iter()) {
+                for (e_j, w_j) in energies.iter().zip(p_j.iter()) {
+                    exact += w_i * w_j * (d_beta * (e_i - e_j)).exp().min(1.0);
+                }
+            }
+            let rate = sampler.swap_statistics(pair).unwrap().recent_success_rate(&before[pair]);
+            assert!((rate - exact).abs() < 0.02, "pair {}: swap rate {} instead of {}", pair, rate, exact);
+        }
+    }
+
+    #[test]
+    fn uneven_movers_are_rejected() {
+        let energy = IsingEnergy::new(1.0, 0.0);
+        let mut sampler = ladder(&[2.0, 3.0], 1);
+        assert!(sampler.add_mover_to(2, Box::new(SpinFlipMover::new())).is_err());
+        sampler.add_mover_to(1, Box::new(SpinFlipMover::new())).unwrap();
+        let mut system = SpinLattice::new(&[3, 3], 2).unwrap();
+        assert!(sampler.make_sweeps(1, &mut system, &energy).is_err());
+        sampler.add_mover(Box::new(SpinFlipMover::new()));
+        assert_eq!(sampler.count_movers(), 4);
+        assert!(sampler.make_sweeps(1, &mut system, &energy).is_ok());
+    }
+}
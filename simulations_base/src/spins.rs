@@ -0,0 +1,561 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{AcceptanceStatistics, ChangedPositions, Energy, Mover, RandomStream, SimulationError, System, UndoLog,
+            UndoSystem};
+
+/// Spins of a `q`-state Potts model on a hypercubic lattice with periodic boundaries.
+///
+/// A spin is a state from `0` to `q - 1`; the Ising model is the case of `q = 2`, where state 0 stands for
+/// spin up (+1) and state 1 for spin down (-1). Sites are indexed in row-major order: the first dimension
+/// varies the slowest.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpinLattice {
+    dims: Vec<usize>,
+    q: u8,
+    spins: Vec<u8>,
+    /// `2 * dims.len()` neighbors of every site: the next and the previous site along every dimension
+    neighbors: Vec<usize>,
+}
+
+impl SpinLattice {
+    /// Creates a lattice of given dimensions with every spin in state 0
+    pub fn new(dims: &[usize], q: u8) -> Result<SpinLattice, SimulationError> {
+        if dims.is_empty() || dims.contains(&0) {
+            return Err(SimulationError::InvalidParameter(format!("invalid lattice dimensions: {:?}", dims)));
+        }
+        if q < 2 { return Err(SimulationError::InvalidParameter(format!("a spin needs at least 2 states, got {}", q))); }
+
+        let n: usize = dims.iter().product();
+        let mut neighbors = Vec::with_capacity(2 * dims.len() * n);
+        for i in 0..n {
+            let mut stride = n;
+            for d in dims.iter() {
+                stride /= d;
+                let k = (i / stride) % d;
+                let base = i - k * stride;
+                neighbors.push(base + ((k + 1) % d) * stride);
+                neighbors.push(base + ((k + d - 1) % d) * stride);
+            }
+        }
+        Ok(SpinLattice { dims: dims.to_vec(), q, spins: vec![0; n], neighbors })
+    }
+
+    pub fn dims(&self) -> &Vec<usize> { &self.dims }
+
+    /// Number of states of a spin
+    pub fn q(&self) -> u8 { self.q }
+
+    pub fn spin(&self, i: usize) -> u8 { self.spins[i] }
+
+    pub fn set_spin(&mut self, i: usize, state: u8) { self.spins[i] = state; }
+
+    /// Neighbors of a given site: the next and the previous one along every dimension
+    pub fn neighbors(&self, i: usize) -> &[usize] {
+        let n = 2 * self.dims.len();
+        &self.neighbors[i * n..(i + 1) * n]
+    }
+
+    /// Sets every spin to a random state
+    pub fn randomize(&mut self, rng: &mut RandomStream) {
+        for s in self.spins.iter_mut() { *s = rng.gen_range(0..self.q); }
+    }
+
+    /// Order parameter `(q n_0 / N - 1) / (q - 1)`, where `n_0` is the number of spins in state 0.
+    ///
+    /// For the Ising model this is the average spin.
+    pub fn magnetization(&self) -> f64 {
+        let n0 = self.spins.iter().filter(|s| **s == 0).count() as f64;
+        let q = self.q as f64;
+        return (q * n0 / self.spins.len() as f64 - 1.0) / (q - 1.0);
+    }
+}
+
+impl System for SpinLattice {
+    fn size(&self) -> usize { self.spins.len() }
+
+    fn copy_from(&mut self, i: usize, rhs: &Self) { self.spins[i] = rhs.spins[i]; }
+}
+
+impl UndoSystem for SpinLattice {
+    type Backup = u8;
+
+    fn backup(&self, pos: usize) -> u8 { self.spins[pos] }
+
+    fn restore(&mut self, pos: usize, backup: &u8) { self.spins[pos] = *backup; }
+}
+
+/// Energy of a spin model given by interactions of nearest neighbors and by an external field
+pub trait SpinCoupling: Energy<SpinLattice> {
+    /// Energy of a bond between two neighboring spins in given states
+    fn pair_energy(&self, a: u8, b: u8) -> f64;
+    /// Energy of a single spin in a given state due to the external field
+    fn field_energy(&self, state: u8) -> f64;
+    /// Energy cost of breaking a bond between two spins in the same state, which sets the bond probability
+    /// of cluster moves
+    fn bond_energy(&self) -> f64 { self.pair_energy(0, 1) - self.pair_energy(0, 0) }
+}
+
+fn total_spin_energy<C: SpinCoupling + ?Sized>(coupling: &C, system: &SpinLattice) -> f64 {
+    let mut en = 0.0;
+    for i in 0..system.size() {
+        en += coupling.field_energy(system.spin(i));
+        // --- every bond is counted once, from the site it starts from
+        for j in system.neighbors(i).iter().step_by(2) { en += coupling.pair_energy(system.spin(i), system.spin(*j)); }
+    }
+    return en;
+}
+
+fn spin_energy_by_pos<C: SpinCoupling + ?Sized>(coupling: &C, system: &SpinLattice, pos: usize) -> f64 {
+    let s = system.spin(pos);
+    let bonds: f64 = system.neighbors(pos).iter().map(|j| coupling.pair_energy(s, system.spin(*j))).sum();
+    return bonds + coupling.field_energy(s);
+}
+
+/// Ising model energy: `-J sum_<ij> s_i s_j - h sum_i s_i`, where spins are +1 (state 0) or -1 (state 1)
+#[derive(Clone, Debug)]
+pub struct IsingEnergy {
+    pub coupling: f64,
+    pub field: f64,
+}
+
+impl IsingEnergy {
+    pub fn new(coupling: f64, field: f64) -> IsingEnergy { IsingEnergy { coupling, field } }
+}
+
+fn ising_spin(state: u8) -> f64 { if state == 0 { 1.0 } else { -1.0 } }
+
+impl SpinCoupling for IsingEnergy {
+    fn pair_energy(&self, a: u8, b: u8) -> f64 { -self.coupling * ising_spin(a) * ising_spin(b) }
+
+    fn field_energy(&self, state: u8) -> f64 { -self.field * ising_spin(state) }
+}
+
+impl Energy<SpinLattice> for IsingEnergy {
+    fn energy(&self, system: &SpinLattice) -> f64 { total_spin_energy(self, system) }
+
+    fn energy_by_pos(&self, system: &SpinLattice, pos: usize) -> f64 { spin_energy_by_pos(self, system, pos) }
+
+    fn delta_energy_by_pos(&self, old_system: &SpinLattice, new_system: &SpinLattice, pos: usize) -> (f64, f64) {
+        (self.energy_by_pos(old_system, pos), self.energy_by_pos(new_system, pos))
+    }
+}
+
+/// Potts model energy: `-J sum_<ij> delta(s_i, s_j) - h sum_i delta(s_i, 0)`; the field favours state 0
+#[derive(Clone, Debug)]
+pub struct PottsEnergy {
+    pub coupling: f64,
+    pub field: f64,
+}
+
+impl PottsEnergy {
+    pub fn new(coupling: f64, field: f64) -> PottsEnergy { PottsEnergy { coupling, field } }
+}
+
+impl SpinCoupling for PottsEnergy {
+    fn pair_energy(&self, a: u8, b: u8) -> f64 { if a == b { -self.coupling } else { 0.0 } }
+
+    fn field_energy(&self, state: u8) -> f64 { if state == 0 { -self.field } else { 0.0 } }
+}
+
+impl Energy<SpinLattice> for PottsEnergy {
+    fn energy(&self, system: &SpinLattice) -> f64 { total_spin_energy(self, system) }
+
+    fn energy_by_pos(&self, system: &SpinLattice, pos: usize) -> f64 { spin_energy_by_pos(self, system, pos) }
+
+    fn delta_energy_by_pos(&self, old_system: &SpinLattice, new_system: &SpinLattice, pos: usize) -> (f64, f64) {
+        (self.energy_by_pos(old_system, pos), self.energy_by_pos(new_system, pos))
+    }
+}
+
+/// Critical temperature of the square lattice Ising model, for J = 1
+pub fn ising_critical_temperature() -> f64 { 2.0 / (1.0 + 2.0_f64.sqrt()).ln() }
+
+/// Complete elliptic integral of the first kind, computed by the arithmetic-geometric mean
+fn elliptic_k(k: f64) -> f64 {
+    let (mut a, mut b) = (1.0, (1.0 - k * k).sqrt());
+    while (a - b).abs() > 1e-15 * a { (a, b) = (0.5 * (a + b), (a * b).sqrt()); }
+    return PI / (2.0 * a);
+}
+
+/// Onsager's exact energy per spin of the infinite square lattice Ising model, for J = 1
+pub fn onsager_energy(temperature: f64) -> f64 {
+    let k2 = 2.0 / temperature;
+    let k = 2.0 * k2.sinh() / (k2.cosh() * k2.cosh());
+    if k >= 1.0 { return -(2.0_f64.sqrt()); }
+    return -1.0 / k2.tanh() * (1.0 + 2.0 / PI * (2.0 * k2.tanh().powi(2) - 1.0) * elliptic_k(k));
+}
+
+/// Changes a randomly selected spin to a different, randomly selected state
+#[derive(Default)]
+pub struct SpinFlipMover {
+    succ_rate: AcceptanceStatistics,
+}
+
+impl SpinFlipMover {
+    pub fn new() -> SpinFlipMover { SpinFlipMover::default() }
+}
+
+/// A state of a spin, different from `state`
+fn other_state(state: u8, q: u8, rng: &mut RandomStream) -> u8 {
+    ((state as u16 + rng.gen_range(1..q) as u16) % q as u16) as u8
+}
+
+impl Mover<SpinLattice> for SpinFlipMover {
+
+    fn perturb(&mut self, system: &mut SpinLattice, rng: &mut RandomStream) -> ChangedPositions {
+        let i = rng.gen_range(0..system.size());
+        system.set_spin(i, other_state(system.spin(i), system.q(), rng));
+        ChangedPositions::single(i)
+    }
+
+    fn perturb_in_place(&mut self, system: &mut SpinLattice, undo: &mut UndoLog<SpinLattice>, rng: &mut RandomStream)
+            -> bool {
+        let i = rng.gen_range(0..system.size());
+        undo.record(system, i);
+        system.set_spin(i, other_state(system.spin(i), system.q(), rng));
+        true
+    }
+
+    fn acceptance_statistics(&self) -> AcceptanceStatistics { self.succ_rate.clone() }
+
+    fn add_success(&mut self) { self.succ_rate.n_succ += 1; }
+
+    fn add_failure(&mut self) { self.succ_rate.n_failed += 1; }
+
+    fn set_acceptance_statistics(&mut self, stats: AcceptanceStatistics) { self.succ_rate = stats; }
+
+    fn max_range(&self) -> f64 { 0.0 }
+
+    fn set_max_range(&mut self, _new_val: f64) {}
+}
+
+/// Heat-bath (Glauber) mover: a randomly selected spin gets a new state drawn from its local Boltzmann distribution.
+///
+/// The probability of every one of the `q` states is proportional to `exp(-e / T)`, where `e` is the energy
+/// of the spin in that state, given by its bonds to the neighbors and by the field. Unlike a spin flip accepted
+/// by [`BarkerCriterion`](crate::BarkerCriterion), which equals this update only for two states, it samples
+/// all the states of a Potts spin at once. The mover accepts its own moves (see [`Mover::self_accepting()`]);
+/// a move that leaves the spin in its old state is counted as rejected. The `temperature` follows the temperature
+/// of the sampler (see [`Mover::set_temperature()`]).
+pub struct HeatBathMover {
+    pub temperature: f64,
+    coupling: Arc<dyn SpinCoupling>,
+    succ_rate: AcceptanceStatistics,
+    weights: Vec<f64>,
+}
+
+impl HeatBathMover {
+    pub fn new(coupling: Arc<dyn SpinCoupling>, temperature: f64) -> HeatBathMover {
+        HeatBathMover { temperature, coupling, succ_rate: Default::default(), weights: vec![] }
+    }
+}
+
+impl Mover<SpinLattice> for HeatBathMover {
+
+    fn perturb(&mut self, system: &mut SpinLattice, rng: &mut RandomStream) -> ChangedPositions {
+        let i = rng.gen_range(0..system.size());
+        let coupling = &self.coupling;
+        self.weights.clear();
+        for state in 0..system.q() {
+            let bonds: f64 = system.neighbors(i).iter().map(|j| coupling.pair_energy(state, system.spin(*j))).sum();
+            self.weights.push(bonds + coupling.field_energy(state));
+        }
+        // --- energies are shifted by their minimum, so the weights don't overflow
+        let e_min = self.weights.iter().cloned().fold(f64::INFINITY, f64::min);
+        for w in self.weights.iter_mut() { *w = (-(*w - e_min) / self.temperature).exp(); }
+        let mut x = rng.gen_range(0.0..self.weights.iter().sum::<f64>());
+        let mut new_state = system.q() - 1;
+        for (state, w) in self.weights.iter().enumerate() {
+            if x < *w {
+                new_state = state as u8;
+                break;
+            }
+            x -= w;
+        }
+        if new_state == system.spin(i) { return ChangedPositions::Range(0..0); }
+        system.set_spin(i, new_state);
+        ChangedPositions::single(i)
+    }
+
+    fn acceptance_statistics(&self) -> AcceptanceStatistics { self.succ_rate.clone() }
+
+    fn add_success(&mut self) { self.succ_rate.n_succ += 1; }
+
+    fn add_failure(&mut self) { self.succ_rate.n_failed += 1; }
+
+    fn set_acceptance_statistics(&mut self, stats: AcceptanceStatistics) { self.succ_rate = stats; }
+
+    fn max_range(&self) -> f64 { 0.0 }
+
+    fn set_max_range(&mut self, _new_val: f64) {}
+
+    fn self_accepting(&self) -> bool { true }
+
+    fn set_temperature(&mut self, temperature: f64) { self.temperature = temperature; }
+}
+
+/// Wolff single-cluster mover.
+///
+/// A cluster grows from a random site over bonds between spins in the same state, each bond added with probability
+/// `1 - exp(-bond_energy / T)`; then all its spins are changed to another random state. In an external field
+/// the move is accepted with the Metropolis probability of the field energy change. The mover accepts its own moves
+/// (see [`Mover::self_accepting()`]) at its `temperature`, which follows the temperature of the sampler the mover
+/// is added to (see [`Mover::set_temperature()`]).
+pub struct WolffMover {
+    pub temperature: f64,
+    coupling: Arc<dyn SpinCoupling>,
+    succ_rate: AcceptanceStatistics,
+    in_cluster: Vec<bool>,
+}
+
+impl WolffMover {
+    /// Creates a mover for a ferromagnetic coupling; fails unless its [`bond_energy()`](SpinCoupling::bond_energy)
+    /// is positive
+    pub fn new(coupling: Arc<dyn SpinCoupling>, temperature: f64) -> Result<WolffMover, SimulationError> {
+        check_ferromagnetic(coupling.as_ref())?;
+        Ok(WolffMover { temperature, coupling, succ_rate: Default::default(), in_cluster: vec![] })
+    }
+}
+
+/// Cluster moves are valid only when breaking a bond costs energy: `1 - exp(-bond_energy / T)` is a probability
+fn check_ferromagnetic(coupling: &dyn SpinCoupling) -> Result<(), SimulationError> {
+    let bond_energy = coupling.bond_energy();
+    if bond_energy > 0.0 && bond_energy.is_finite() { return Ok(()); }
+    return Err(SimulationError::InvalidParameter(
+        format!("cluster moves need a ferromagnetic coupling, got bond energy {}", bond_energy)));
+}
+
+impl Mover<SpinLattice> for WolffMover {
+
+    fn perturb(&mut self, system: &mut SpinLattice, rng: &mut RandomStream) -> ChangedPositions {
+        self.in_cluster.clear();
+        self.in_cluster.resize(system.size(), false);
+        let p_bond = 1.0 - (-self.coupling.bond_energy() / self.temperature).exp();
+
+        let seed = rng.gen_range(0..system.size());
+        let old_state = system.spin(seed);
+        let new_state = other_state(old_state, system.q(), rng);
+        let mut cluster = vec![seed];
+        let mut stack = vec![seed];
+        self.in_cluster[seed] = true;
+        while let Some(i) = stack.pop() {
+            for j in system.neighbors(i).iter() {
+                if !self.in_cluster[*j] && system.spin(*j) == old_state && rng.gen_range(0.0..1.0) < p_bond {
+                    self.in_cluster[*j] = true;
+                    cluster.push(*j);
+                    stack.push(*j);
+                }
+            }
+        }
+        let delta_en = cluster.len() as f64
+            * (self.coupling.field_energy(new_state) - self.coupling.field_energy(old_state));
+        if delta_en > 0.0 && rng.gen_range(0.0..1.0) > (-delta_en / self.temperature).exp() {
+            return ChangedPositions::Range(0..0);
+        }
+        for i in cluster.iter() { system.set_spin(*i, new_state); }
+        return ChangedPositions::List(cluster);
+    }
+
+    fn acceptance_statistics(&self) -> AcceptanceStatistics { self.succ_rate.clone() }
+
+    fn add_success(&mut self) { self.succ_rate.n_succ += 1; }
+
+    fn add_failure(&mut self) { self.succ_rate.n_failed += 1; }
+
+    fn set_acceptance_statistics(&mut self, stats: AcceptanceStatistics) { self.succ_rate = stats; }
+
+    fn max_range(&self) -> f64 { 0.0 }
+
+    fn set_max_range(&mut self, _new_val: f64) {}
+
+    fn self_accepting(&self) -> bool { true }
+
+    fn set_temperature(&mut self, temperature: f64) { self.temperature = temperature; }
+}
+
+/// Swendsen–Wang mover, which updates every spin of a lattice at once.
+///
+/// Bonds between neighboring spins in the same state are activated with probability `1 - exp(-bond_energy / T)`;
+/// every cluster of sites connected by active bonds then gets a new state, drawn from the Boltzmann distribution
+/// of its field energy. Every move is accepted, as a move of a self-accepting mover (see [`Mover::self_accepting()`]);
+/// the `temperature` follows the temperature of the sampler (see [`Mover::set_temperature()`]). Give this mover
+/// a weight of `1.0 / size()` in [`MCProtocol`](crate::MCProtocol) to make a single update per sweep.
+pub struct SwendsenWangMover {
+    pub temperature: f64,
+    coupling: Arc<dyn SpinCoupling>,
+    succ_rate: AcceptanceStatistics,
+    parent: Vec<usize>,
+}
+
+impl SwendsenWangMover {
+    /// Creates a mover for a ferromagnetic coupling; fails unless its [`bond_energy()`](SpinCoupling::bond_energy)
+    /// is positive
+    pub fn new(coupling: Arc<dyn SpinCoupling>, temperature: f64) -> Result<SwendsenWangMover, SimulationError> {
+        check_ferromagnetic(coupling.as_ref())?;
+        Ok(SwendsenWangMover { temperature, coupling, succ_rate: Default::default(), parent: vec![] })
+    }
+
+    fn root(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        return i;
+    }
+}
+
+impl Mover<SpinLattice> for SwendsenWangMover {
+
+    fn perturb(&mut self, system: &mut SpinLattice, rng: &mut RandomStream) -> ChangedPositions {
+        let n = system.size();
+        let p_bond = 1.0 - (-self.coupling.bond_energy() / self.temperature).exp();
+        // ---------- join sites into clusters
+        self.parent.clear();
+        self.parent.extend(0..n);
+        for i in 0..n {
+            for j in system.neighbors(i).iter().step_by(2) {
+                if system.spin(i) == system.spin(*j) && rng.gen_range(0.0..1.0) < p_bond {
+                    let (ri, rj) = (self.root(i), self.root(*j));
+                    if ri != rj { self.parent[ri] = rj; }
+                }
+            }
+        }
+        // ---------- count sites of every cluster
+        let mut cluster_size = vec![0usize; n];
+        for i in 0..n {
+            let r = self.root(i);
+            cluster_size[r] += 1;
+        }
+        // ---------- a new state of every cluster, drawn by heat bath in the external field
+        let q = system.q();
+        let field: Vec<f64> = (0..q).map(|s| self.coupling.field_energy(s)).collect();
+        let field_min = field.iter().cloned().fold(f64::INFINITY, f64::min);
+        let mut new_state = vec![0u8; n];
+        let mut weights = vec![0.0; q as usize];
+        for r in 0..n {
+            if cluster_size[r] == 0 { continue; }
+            for s in 0..q as usize {
+                weights[s] = (-(cluster_size[r] as f64) * (field[s] - field_min) / self.temperature).exp();
+            }
+            let mut x = rng.gen_range(0.0..weights.iter().sum::<f64>());
+            let mut state = q - 1;
+            for (s, w) in weights.iter().enumerate() {
+                if x < *w { state = s as u8; break; }
+                x -= w;
+            }
+            new_state[r] = state;
+        }
+        for i in 0..n {
+            let r = self.root(i);
+            system.set_spin(i, new_state[r]);
+        }
+        return ChangedPositions::Range(0..n);
+    }
+
+    fn acceptance_statistics(&self) -> AcceptanceStatistics { self.succ_rate.clone() }
+
+    fn add_success(&mut self) { self.succ_rate.n_succ += 1; }
+
+    fn add_failure(&mut self) { self.succ_rate.n_failed += 1; }
+
+    fn set_acceptance_statistics(&mut self, stats: AcceptanceStatistics) { self.succ_rate = stats; }
+
+    fn max_range(&self) -> f64 { 0.0 }
+
+    fn set_max_range(&mut self, _new_val: f64) {}
+
+    fn self_accepting(&self) -> bool { true }
+
+    fn set_temperature(&mut self, temperature: f64) { self.temperature = temperature; }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{ising_critical_temperature, onsager_energy, AdaptiveMCProtocol, Energy, IsingEnergy, MCProtocol,
+                MetropolisCriterion, MoversSet, MoversSetSampler, RandomStreams, SpinFlipMover, SpinLattice,
+                SwendsenWangMover, System, UndoMCProtocol, ValidationMode, WolffMover};
+
+    /// Average energy per spin sampled every sweep, after the same number of burn-in sweeps
+    fn sampled_energy(sampler: &mut dyn MoversSetSampler<MetropolisCriterion, SpinLattice>, l: usize,
+                      n_sweeps: usize) -> f64 {
+        let energy = IsingEnergy::new(1.0, 0.0);
+        let mut system = SpinLattice::new(&[l, l], 2).unwrap();
+        sampler.make_sweeps(n_sweeps / 10, &mut system, &energy).unwrap();
+        let mut total = 0.0;
+        for _ in 0..n_sweeps {
+            sampler.make_sweeps(1, &mut system, &energy).unwrap();
+            total += energy.energy(&system);
+        }
+        return total / (n_sweeps * system.size()) as f64;
+    }
+
+    /// Every sampler under test, at a given temperature, on an `l x l` lattice
+    fn samplers(temperature: f64, l: usize, seed: u64) -> Vec<Box<dyn MoversSetSampler<MetropolisCriterion, SpinLattice>>> {
+        let mut streams = RandomStreams::new(seed);
+        let coupling = Arc::new(IsingEnergy::new(1.0, 0.0));
+        let n = (l * l) as f64;
+        let mut metropolis = MCProtocol::with_streams(MetropolisCriterion::new(temperature), streams.split());
+        metropolis.validation = ValidationMode::Disabled;
+        metropolis.add_mover(Box::new(SpinFlipMover::new()));
+        let mut inner = MCProtocol::with_streams(MetropolisCriterion::new(temperature), streams.split());
+        inner.validation = ValidationMode::Disabled;
+        inner.add_mover(Box::new(SpinFlipMover::new()));
+        let mut undo = UndoMCProtocol::with_streams(MetropolisCriterion::new(temperature), streams.split());
+        undo.validation = ValidationMode::Disabled;
+        undo.add_mover(Box::new(SpinFlipMover::new()));
+        // --- cluster movers are built for a wrong temperature: the sampler must correct it
+        let mut wolff = MCProtocol::with_streams(MetropolisCriterion::new(temperature), streams.split());
+        wolff.add_weighted_mover(Box::new(WolffMover::new(coupling.clone(), 1.0).unwrap()), 10.0 / n).unwrap();
+        let mut sw = MCProtocol::with_streams(MetropolisCriterion::new(temperature), streams.split());
+        sw.add_weighted_mover(Box::new(SwendsenWangMover::new(coupling, 1.0).unwrap()), 1.0 / n).unwrap();
+        return vec![Box::new(metropolis), Box::new(AdaptiveMCProtocol::new(Box::new(inner))), Box::new(undo),
+                    Box::new(wolff), Box::new(sw)];
+    }
+
+    #[test]
+    fn samplers_reproduce_exact_enumeration() {
+        let temperature = 2.5;
+        let energy = IsingEnergy::new(1.0, 0.0);
+        let mut system = SpinLattice::new(&[3, 3], 2).unwrap();
+        let (mut z, mut e_sum) = (0.0, 0.0);
+        for state in 0..(1 << 9) {
+            for i in 0..9 { system.set_spin(i, ((state >> i) & 1) as u8); }
+            let e = energy.energy(&system);
+            let w = (-e / temperature).exp();
+            z += w;
+            e_sum += w * e;
+        }
+        let exact = e_sum / z / 9.0;
+        for mut sampler in samplers(temperature, 3, 14) {
+            let sampled = sampled_energy(sampler.as_mut(), 3, 20000);
+            assert!((sampled - exact).abs() < 0.02, "sampled {} vs exact {}", sampled, exact);
+        }
+    }
+
+    #[test]
+    fn samplers_agree_with_onsager() {
+        for temperature in [2.0, ising_critical_temperature(), 3.0] {
+            // --- at T_c the energy of a 16 x 16 lattice is still about 0.04 below the infinite lattice one
+            let tolerance = if temperature == 2.0 || temperature == 3.0 { 0.02 } else { 0.1 };
+            let exact = onsager_energy(temperature);
+            for mut sampler in samplers(temperature, 16, 14) {
+                let sampled = sampled_energy(sampler.as_mut(), 16, 3000);
+                assert!((sampled - exact).abs() < tolerance, "T = {}: sampled {} vs exact {}", temperature, sampled,
+                        exact);
+            }
+        }
+    }
+
+    #[test]
+    fn antiferromagnetic_coupling_is_rejected_by_cluster_movers() {
+        let coupling = Arc::new(IsingEnergy::new(-1.0, 0.0));
+        assert!(WolffMover::new(coupling.clone(), 2.0).is_err());
+        assert!(SwendsenWangMover::new(coupling, 2.0).is_err());
+    }
+}
@@ -0,0 +1,248 @@
+use std::fmt;
+use std::io;
+use std::io::Write;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{out_writer, Observation, Observer};
+
+/// Time series of a scalar observable, e.g. the energy recorded after every block of sweeps.
+///
+/// Subsequent values of a Monte Carlo time series are correlated; the error estimates provided here
+/// account for that either by the integrated autocorrelation time or by block averaging.
+/// Methods return NaN when the series is too short to compute a value.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TimeSeries {
+    pub name: String,
+    values: Vec<f64>,
+}
+
+impl TimeSeries {
+    pub fn new(name: &str) -> TimeSeries { TimeSeries { name: name.to_string(), values: vec![] } }
+
+    pub fn push(&mut self, value: f64) { self.values.push(value); }
+
+    pub fn values(&self) -> &Vec<f64> { &self.values }
+
+    pub fn len(&self) -> usize { self.values.len() }
+
+    pub fn is_empty(&self) -> bool { self.values.is_empty() }
+
+    pub fn mean(&self) -> f64 { self.values.iter().sum::<f64>() / self.values.len() as f64 }
+
+    /// Unbiased variance of the values
+    pub fn variance(&self) -> f64 {
+        let avg = self.mean();
+        return self.values.iter().map(|v| (v - avg) * (v - avg)).sum::<f64>() / (self.values.len() as f64 - 1.0);
+    }
+
+    /// Normalised autocorrelation function at a given lag; equal to 1.0 for `lag == 0`
+    pub fn autocorrelation(&self, lag: usize) -> f64 {
+        if lag >= self.values.len() { return f64::NAN; }
+        let avg = self.mean();
+        return self.normalised_autocovariance(lag, avg, self.autocovariance(0, avg));
+    }
+
+    /// Autocovariance at a given lag, for the mean `avg` of the series
+    fn autocovariance(&self, lag: usize, avg: f64) -> f64 {
+        let n = self.values.len();
+        let sum: f64 = (0..n - lag).map(|i| (self.values[i] - avg) * (self.values[i + lag] - avg)).sum();
+        return sum / (n - lag) as f64;
+    }
+
+    /// Autocovariance at a given lag divided by the variance `c0`, both computed for the mean `avg`
+    fn normalised_autocovariance(&self, lag: usize, avg: f64, c0: f64) -> f64 {
+        if c0 == 0.0 { return if lag == 0 { 1.0 } else { 0.0 }; }
+        return self.autocovariance(lag, avg) / c0;
+    }
+
+    /// Integrated autocorrelation time `tau = 1 + 2 sum_t rho(t)`, in units of observations.
+    ///
+    /// The sum is truncated by Sokal's automatic windowing: at the smallest lag `M` such that `M >= 5 tau(M)`.
+    /// Uncorrelated data give `tau` close to 1.0
+    pub fn integrated_autocorrelation_time(&self) -> f64 {
+        let n = self.values.len();
+        if n < 2 { return f64::NAN; }
+        let avg = self.mean();
+        let c0 = self.autocovariance(0, avg);
+        let mut tau = 1.0;
+        for lag in 1..n {
+            tau += 2.0 * self.normalised_autocovariance(lag, avg, c0);
+            if lag as f64 >= 5.0 * tau { break; }
+        }
+        return tau.max(1.0);
+    }
+
+    /// Number of effectively independent observations: `n / tau`
+    pub fn effective_sample_size(&self) -> f64 { self.values.len() as f64 / self.integrated_autocorrelation_time() }
+
+    /// Standard error of the mean corrected for autocorrelation: `sqrt(tau * var / n)`
+    pub fn standard_error(&self) -> f64 { (self.variance() / self.effective_sample_size()).sqrt() }
+
+    /// Standard error of the mean estimated by block averaging.
+    ///
+    /// The series is divided into `n_blocks` blocks of equal length (the remaining last values are not used);
+    /// the error is computed from the scatter of block averages, which are nearly independent when blocks
+    /// are much longer than the autocorrelation time.
+    pub fn block_standard_error(&self, n_blocks: usize) -> f64 {
+        if n_blocks < 2 || self.values.len() < n_blocks { return f64::NAN; }
+        let block_len = self.values.len() / n_blocks;
+        let mut blocks = TimeSeries::new(&self.name);
+        for block in self.values.chunks_exact(block_len).take(n_blocks) {
+            blocks.push(block.iter().sum::<f64>() / block_len as f64);
+        }
+        return (blocks.variance() / n_blocks as f64).sqrt();
+    }
+
+    pub fn summary(&self) -> SeriesSummary {
+        SeriesSummary { name: self.name.clone(), n: self.len(), mean: self.mean(), std_dev: self.variance().sqrt(),
+            tau: self.integrated_autocorrelation_time(), ess: self.effective_sample_size(),
+            std_error: self.standard_error(), block_std_error: self.block_standard_error(10) }
+    }
+}
+
+/// Gelman–Rubin potential scale reduction factor R-hat, computed for independent chains of the same observable.
+///
+/// Values close to 1.0 mean that all chains sample the same distribution; values above 1.1 are usually
+/// taken as a sign the simulation hasn't converged. Chains are truncated to the length of the shortest one.
+pub fn gelman_rubin(chains: &[TimeSeries]) -> f64 {
+    let n = chains.iter().map(|c| c.len()).min().unwrap_or(0);
+    if chains.len() < 2 || n < 2 { return f64::NAN; }
+    let truncated: Vec<TimeSeries> = chains.iter()
+        .map(|c| TimeSeries { name: c.name.clone(), values: c.values[..n].to_vec() }).collect();
+    let mut means = TimeSeries::new("means");
+    for c in truncated.iter() { means.push(c.mean()); }
+    let within = truncated.iter().map(|c| c.variance()).sum::<f64>() / truncated.len() as f64;
+    let between = n as f64 * means.variance();
+    let var_plus = (n as f64 - 1.0) / n as f64 * within + between / n as f64;
+    return (var_plus / within).sqrt();
+}
+
+/// Statistics of a single [`TimeSeries`]
+#[derive(Clone, Debug)]
+pub struct SeriesSummary {
+    pub name: String,
+    pub n: usize,
+    pub mean: f64,
+    pub std_dev: f64,
+    /// integrated autocorrelation time
+    pub tau: f64,
+    /// effective sample size
+    pub ess: f64,
+    /// standard error of the mean corrected for autocorrelation
+    pub std_error: f64,
+    /// standard error of the mean from 10 blocks
+    pub block_std_error: f64,
+}
+
+impl SeriesSummary {
+    /// Header line matching the output of `Display`
+    pub fn header() -> String {
+        format!("{:<12} {:>8} {:>12} {:>10} {:>8} {:>8} {:>10} {:>10}", "observable", "n", "mean", "std_dev",
+                "tau", "ess", "std_err", "block_err")
+    }
+}
+
+impl fmt::Display for SeriesSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<12} {:>8} {:>12.4} {:>10.4} {:>8.2} {:>8.1} {:>10.4} {:>10.4}", self.name, self.n, self.mean,
+               self.std_dev, self.tau, self.ess, self.std_error, self.block_std_error)
+    }
+}
+
+/// Extracts a scalar value from an observation
+pub type Observable<S> = Box<dyn Fn(&Observation<S>) -> f64 + Send>;
+
+/// Records time series of scalar observables and writes their summary when a simulation is finalized.
+///
+/// Each observable is a function that extracts a value from an [`Observation`], e.g. `|o| o.energy`.
+/// The first `burn_in` observations are not recorded.
+pub struct StatisticsObserver<S> {
+    pub burn_in: usize,
+    out_fname: String,
+    n_observed: usize,
+    observables: Vec<(TimeSeries, Observable<S>)>,
+}
+
+impl<S> StatisticsObserver<S> {
+    /// Creates an observer that writes its report to a given file, or to the standard output if the name is empty
+    pub fn new(out_fname: &str) -> StatisticsObserver<S> {
+        StatisticsObserver { burn_in: 0, out_fname: out_fname.to_string(), n_observed: 0, observables: vec![] }
+    }
+
+    pub fn add_observable(&mut self, name: &str, observable: Observable<S>) {
+        self.observables.push((TimeSeries::new(name), observable));
+    }
+
+    /// Time series recorded so far, in the order the observables were added
+    pub fn series(&self) -> Vec<&TimeSeries> { self.observables.iter().map(|(series, _)| series).collect() }
+
+    /// Writes the summary of every recorded observable
+    pub fn write_report(&self) -> io::Result<()> {
+        let mut out = out_writer(&self.out_fname)?;
+        writeln!(out, "{}", SeriesSummary::header())?;
+        for (series, _) in self.observables.iter() { writeln!(out, "{}", series.summary())?; }
+        out.flush()
+    }
+}
+
+impl<S> Observer<S> for StatisticsObserver<S> {
+    fn observe(&mut self, observation: &Observation<S>) {
+        self.n_observed += 1;
+        if self.n_observed <= self.burn_in { return; }
+        for (series, observable) in self.observables.iter_mut() { series.push(observable(observation)); }
+    }
+
+    fn finalize(&mut self) { self.write_report().ok(); }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{gaussian, gelman_rubin, RandomStreams, TimeSeries};
+
+    /// Series of independent Gaussian values of a given mean and standard deviation
+    fn gaussian_series(n: usize, mean: f64, sigma: f64, seed: u64) -> TimeSeries {
+        let mut rng = RandomStreams::new(seed).next_stream();
+        let mut series = TimeSeries::new("x");
+        for _ in 0..n { series.push(mean + sigma * gaussian(&mut rng)); }
+        return series;
+    }
+
+    #[test]
+    fn autoregressive_series_has_known_autocorrelation_time() {
+        // --- AR(1): x_t = phi x_(t-1) + noise, for which rho(t) = phi^t and tau = (1 + phi) / (1 - phi)
+        let (phi, n) = (0.8, 200000);
+        let mut rng = RandomStreams::new(1).next_stream();
+        let mut series = TimeSeries::new("ar1");
+        let mut x = 0.0;
+        for _ in 0..n {
+            x = phi * x + gaussian(&mut rng);
+            series.push(x);
+        }
+        let tau = series.integrated_autocorrelation_time();
+        assert!((tau - (1.0 + phi) / (1.0 - phi)).abs() < 1.0, "tau = {}", tau);
+        assert!((series.autocorrelation(1) - phi).abs() < 0.01);
+        assert!((series.effective_sample_size() - n as f64 / tau).abs() < 1e-6);
+    }
+
+    #[test]
+    fn block_error_of_independent_values() {
+        let (n, sigma) = (100000, 2.0);
+        let series = gaussian_series(n, 1.0, sigma, 2);
+        let expected = sigma / (n as f64).sqrt();
+        let block_error = series.block_standard_error(100);
+        assert!((block_error / expected - 1.0).abs() < 0.25, "block error {} instead of {}", block_error, expected);
+        assert!((series.standard_error() / expected - 1.0).abs() < 0.1);
+        assert!(series.block_standard_error(1).is_nan());
+    }
+
+    #[test]
+    fn gelman_rubin_detects_chains_of_different_distributions() {
+        let same: Vec<TimeSeries> = (0..4).map(|i| gaussian_series(1000, 0.0, 1.0, 10 + i)).collect();
+        let r_hat = gelman_rubin(&same);
+        assert!(r_hat < 1.05, "R-hat = {}", r_hat);
+        let shifted: Vec<TimeSeries> = (0..4).map(|i| gaussian_series(1000, 3.0 * i as f64, 1.0, 20 + i)).collect();
+        let r_hat = gelman_rubin(&shifted);
+        assert!(r_hat > 2.0, "R-hat = {}", r_hat);
+    }
+}
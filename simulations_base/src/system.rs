@@ -1,4 +1,50 @@
-pub trait System: Clone {
+use std::ops::Range;
+
+/// A system is `Send`, so it can be simulated in its own thread
+pub trait System: Clone + Send {
     fn size(&self) -> usize;
     fn copy_from(&mut self, i:usize, rhs: &Self);
-}
\ No newline at end of file
+}
+
+/// Positions of a system changed by a single move.
+///
+/// A [`Mover`](crate::Mover) reports the positions it has altered; the sampler evaluates the energy change
+/// for them and copies exactly these positions when the move is accepted or rejected.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChangedPositions {
+    /// contiguous positions, given as a half-open range `start..end`
+    Range(Range<usize>),
+    /// an arbitrary set of positions, each listed once
+    List(Vec<usize>),
+}
+
+impl ChangedPositions {
+    /// Just a single position has been changed
+    pub fn single(pos: usize) -> ChangedPositions { ChangedPositions::Range(pos..pos + 1) }
+
+    pub fn len(&self) -> usize {
+        match self {
+            ChangedPositions::Range(r) => r.len(),
+            ChangedPositions::List(l) => l.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// Iterates over the changed positions
+    pub fn iter(&self) -> impl Iterator<Item=usize> + '_ {
+        let (range, list) = match self {
+            ChangedPositions::Range(r) => (r.clone(), &[][..]),
+            ChangedPositions::List(l) => (0..0, &l[..]),
+        };
+        range.chain(list.iter().copied())
+    }
+}
+
+impl From<Range<usize>> for ChangedPositions {
+    fn from(range: Range<usize>) -> Self { ChangedPositions::Range(range) }
+}
+
+impl From<Vec<usize>> for ChangedPositions {
+    fn from(list: Vec<usize>) -> Self { ChangedPositions::List(list) }
+}
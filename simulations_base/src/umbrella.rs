@@ -0,0 +1,323 @@
+use std::fmt;
+use std::io;
+use std::io::Write;
+use std::sync::Arc;
+
+use rayon::prelude::*;
+
+use crate::{out_writer, AcceptanceCriterion, ChangedPositions, Energy, RandomStreams, SimulationDriver,
+            SimulationError, System, TimeSeries};
+
+/// Collective variable: a scalar function of the whole system, e.g. the radius of gyration of a polymer chain
+pub type CollectiveVariable<S> = Arc<dyn Fn(&S) -> f64 + Send + Sync>;
+
+/// Energy function with a harmonic bias on a collective variable: `E(x) + k / 2 (cv(x) - center)^2`.
+///
+/// The bias depends on the whole system, so it can't be split into local contributions:
+/// [`energy_by_pos()`](Energy::energy_by_pos) returns the unbiased energy of a position, while the bias is added
+/// to the total energy and to energy differences of a move, i.e. by [`delta_energy()`](Energy::delta_energy),
+/// [`delta_energy_by_pos()`](Energy::delta_energy_by_pos) and
+/// [`energy_of_positions()`](Energy::energy_of_positions), which [`UndoMCProtocol`](crate::UndoMCProtocol)
+/// evaluates before and after a move.
+pub struct HarmonicBias<S> {
+    pub center: f64,
+    pub force_constant: f64,
+    energy: Arc<dyn Energy<S>>,
+    cv: CollectiveVariable<S>,
+}
+
+impl<S> HarmonicBias<S> {
+    pub fn new(energy: Arc<dyn Energy<S>>, cv: CollectiveVariable<S>, center: f64, force_constant: f64)
+            -> HarmonicBias<S> {
+        HarmonicBias { center, force_constant, energy, cv }
+    }
+
+    /// Value of the collective variable for a given system
+    pub fn collective_variable(&self, system: &S) -> f64 { (self.cv)(system) }
+
+    /// Bias energy for a given value of the collective variable
+    pub fn bias(&self, value: f64) -> f64 { 0.5 * self.force_constant * (value - self.center) * (value - self.center) }
+
+    fn system_bias(&self, system: &S) -> f64 { self.bias(self.collective_variable(system)) }
+}
+
+impl<S> Energy<S> for HarmonicBias<S> {
+    fn energy(&self, system: &S) -> f64 { self.energy.energy(system) + self.system_bias(system) }
+
+    fn energy_by_pos(&self, system: &S, pos: usize) -> f64 { self.energy.energy_by_pos(system, pos) }
+
+    fn delta_energy_by_pos(&self, old_system: &S, new_system: &S, pos: usize) -> (f64, f64) {
+        let (before, after) = self.energy.delta_energy_by_pos(old_system, new_system, pos);
+        (before + self.system_bias(old_system), after + self.system_bias(new_system))
+    }
+
+    fn delta_energy(&self, old_system: &S, new_system: &S, changed: &ChangedPositions) -> (f64, f64) {
+        let (before, after) = self.energy.delta_energy(old_system, new_system, changed);
+        (before + self.system_bias(old_system), after + self.system_bias(new_system))
+    }
+
+    fn energy_of_positions(&self, system: &S, changed: &ChangedPositions) -> f64 {
+        self.energy.energy_of_positions(system, changed) + self.system_bias(system)
+    }
+}
+
+/// Runs umbrella sampling: independent simulations, each biased towards a different value of a collective variable.
+///
+/// Every window has its own [`SimulationDriver`], its own copy of the system and a [`HarmonicBias`] energy;
+/// windows are distributed over rayon's thread pool. The collective variable is recorded after every block
+/// of sweeps, except the first `burn_in` blocks, and the recorded samples may be combined by [`wham()`].
+pub struct UmbrellaSampling<T: AcceptanceCriterion, S: System> {
+    pub burn_in: usize,
+    biases: Vec<HarmonicBias<S>>,
+    drivers: Vec<SimulationDriver<T, S>>,
+    systems: Vec<S>,
+    samples: Vec<TimeSeries>,
+    block: usize,
+}
+
+impl<T: AcceptanceCriterion, S: System> UmbrellaSampling<T, S> {
+    /// Creates a window for every given center, all with the same force constant and starting from a copy
+    /// of the given system.
+    ///
+    /// As in [`ParallelChains`](crate::ParallelChains), `build_driver` is called for every window with its index
+    /// and random streams split from `streams`.
+    pub fn new<F>(system: &S, energy: Arc<dyn Energy<S>>, cv: CollectiveVariable<S>, centers: &[f64],
+                  force_constant: f64, mut streams: RandomStreams, build_driver: F) -> UmbrellaSampling<T, S>
+        where F: Fn(usize, RandomStreams) -> SimulationDriver<T, S> {
+        let biases = centers.iter()
+            .map(|c| HarmonicBias::new(energy.clone(), cv.clone(), *c, force_constant)).collect();
+        let drivers = (0..centers.len()).map(|i| build_driver(i, streams.split())).collect();
+        let samples = centers.iter().map(|c| TimeSeries::new(&format!("cv_{}", c))).collect();
+        UmbrellaSampling { burn_in: 0, biases, drivers, systems: vec![system.clone(); centers.len()], samples, block: 0 }
+    }
+
+    pub fn count_windows(&self) -> usize { self.biases.len() }
+
+    /// Bias of a window or [`SimulationError::InvalidParameter`] when there's no window of that index
+    pub fn bias(&self, window: usize) -> Result<&HarmonicBias<S>, SimulationError> {
+        self.biases.get(window).ok_or_else(|| window_index_error(window, self.biases.len()))
+    }
+
+    /// Driver of a window or [`SimulationError::InvalidParameter`] when there's no window of that index
+    pub fn driver(&mut self, window: usize) -> Result<&mut SimulationDriver<T, S>, SimulationError> {
+        let n_windows = self.drivers.len();
+        self.drivers.get_mut(window).ok_or_else(|| window_index_error(window, n_windows))
+    }
+
+    /// System of a window or [`SimulationError::InvalidParameter`] when there's no window of that index
+    pub fn system(&self, window: usize) -> Result<&S, SimulationError> {
+        self.systems.get(window).ok_or_else(|| window_index_error(window, self.systems.len()))
+    }
+
+    /// Values of the collective variable recorded so far, separately for every window
+    pub fn samples(&self) -> &Vec<TimeSeries> { &self.samples }
+
+    /// Runs `n_blocks` blocks of sweeps in every window; when any window fails, the first error is returned
+    pub fn run(&mut self, n_blocks: usize) -> Result<(), SimulationError> {
+        let first_recorded = self.burn_in.saturating_sub(self.block);
+        let results: Vec<Result<(), SimulationError>> = self.drivers.par_iter_mut()
+            .zip(self.systems.par_iter_mut()).zip(self.biases.par_iter()).zip(self.samples.par_iter_mut())
+            .map(|(((driver, system), bias), samples)| {
+                for i in 0..n_blocks {
+                    driver.run(1, system, bias)?;
+                    if i >= first_recorded { samples.push(bias.collective_variable(system)); }
+                }
+                Ok(())
+            }).collect();
+        self.block += n_blocks;
+        for result in results { result?; }
+        Ok(())
+    }
+
+    /// Combines the samples of all the windows into a free energy profile; see [`wham()`]
+    pub fn free_energy(&self, n_bins: usize, temperature: f64) -> Result<FreeEnergyProfile, SimulationError> {
+        let windows: Vec<WhamWindow> = self.biases.iter().zip(self.samples.iter())
+            .map(|(b, s)| WhamWindow { center: b.center, force_constant: b.force_constant,
+                samples: s.values().clone() }).collect();
+        return wham(&windows, n_bins, temperature);
+    }
+}
+
+/// Samples of a collective variable recorded in a single umbrella window with a harmonic bias
+#[derive(Clone, Debug)]
+pub struct WhamWindow {
+    pub center: f64,
+    pub force_constant: f64,
+    pub samples: Vec<f64>,
+}
+
+impl WhamWindow {
+    fn bias(&self, value: f64) -> f64 { 0.5 * self.force_constant * (value - self.center) * (value - self.center) }
+}
+
+fn window_index_error(window: usize, n_windows: usize) -> SimulationError {
+    SimulationError::InvalidParameter(format!("window index {} out of range of {} windows", window, n_windows))
+}
+
+/// Free energy as a function of a collective variable, given at the centers of histogram bins
+#[derive(Clone, Debug)]
+pub struct FreeEnergyProfile {
+    /// value of the collective variable at the center of every bin
+    pub bins: Vec<f64>,
+    /// free energy of every bin, equal to 0.0 at its minimum and infinite for a bin without any sample
+    pub free_energy: Vec<f64>,
+    /// unbiased probability of every bin
+    pub probability: Vec<f64>,
+    /// free energy of every window, i.e. the shift `f_i` found by WHAM
+    pub window_free_energy: Vec<f64>,
+    /// number of WHAM iterations
+    pub iterations: usize,
+}
+
+impl FreeEnergyProfile {
+    pub fn write(&self, out_fname: &str) -> io::Result<()> {
+        let mut out = out_writer(out_fname)?;
+        write!(out, "{}", self)?;
+        out.flush()
+    }
+}
+
+impl fmt::Display for FreeEnergyProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:>12} {:>12} {:>12}", "cv", "free_energy", "probability")?;
+        for i in 0..self.bins.len() {
+            writeln!(f, "{:>12.4} {:>12.4} {:>12.6}", self.bins[i], self.free_energy[i], self.probability[i])?;
+        }
+        Ok(())
+    }
+}
+
+/// Largest change of a window free energy at which WHAM iterations stop
+const WHAM_TOLERANCE: f64 = 1e-7;
+const WHAM_MAX_ITERATIONS: usize = 100000;
+
+/// Sum of exponents computed without overflow: `ln sum_i exp(x_i)`
+pub(crate) fn log_sum_exp(values: impl Iterator<Item = f64> + Clone) -> f64 {
+    let max = values.clone().fold(f64::NEG_INFINITY, f64::max);
+    if max == f64::NEG_INFINITY { return max; }
+    return max + values.map(|v| (v - max).exp()).sum::<f64>().ln();
+}
+
+/// Weighted Histogram Analysis Method: combines biased samples of umbrella windows into an unbiased
+/// free energy profile.
+///
+/// Samples are binned into `n_bins` bins of equal width spanning the range of all the samples. The self-consistent
+/// WHAM equations are iterated until window free energies change by less than 1e-7.
+pub fn wham(windows: &[WhamWindow], n_bins: usize, temperature: f64) -> Result<FreeEnergyProfile, SimulationError> {
+    let n_samples: usize = windows.iter().map(|w| w.samples.len()).sum();
+    if windows.is_empty() || n_samples == 0 || n_bins == 0 {
+        return Err(SimulationError::InvalidParameter("WHAM needs at least one sample and one bin".to_string()));
+    }
+    let min = windows.iter().flat_map(|w| w.samples.iter()).cloned().fold(f64::INFINITY, f64::min);
+    let max = windows.iter().flat_map(|w| w.samples.iter()).cloned().fold(f64::NEG_INFINITY, f64::max);
+    let width = if max > min { (max - min) / n_bins as f64 } else { 1.0 };
+    let bins: Vec<f64> = (0..n_bins).map(|b| min + (b as f64 + 0.5) * width).collect();
+
+    // ---------- histogram of all the windows together, biases in units of kT
+    let mut counts = vec![0.0f64; n_bins];
+    for x in windows.iter().flat_map(|w| w.samples.iter()) {
+        counts[(((x - min) / width) as usize).min(n_bins - 1)] += 1.0;
+    }
+    let beta_bias: Vec<Vec<f64>> = windows.iter()
+        .map(|w| bins.iter().map(|x| w.bias(*x) / temperature).collect()).collect();
+    let log_n: Vec<f64> = windows.iter().map(|w| (w.samples.len() as f64).ln()).collect();
+
+    // ---------- iterate WHAM equations; f holds window free energies in units of kT
+    let mut f = vec![0.0; windows.len()];
+    let mut log_p = vec![0.0; n_bins];
+    let mut iterations = 0;
+    loop {
+        iterations += 1;
+        for b in 0..n_bins {
+            let denominator = log_sum_exp((0..windows.len()).map(|i| log_n[i] + f[i] - beta_bias[i][b]));
+            log_p[b] = if counts[b] > 0.0 { counts[b].ln() - denominator } else { f64::NEG_INFINITY };
+        }
+        let mut change: f64 = 0.0;
+        for i in 0..windows.len() {
+            let new_f = -log_sum_exp((0..n_bins).map(|b| log_p[b] - beta_bias[i][b]));
+            change = change.max((new_f - f[i]).abs());
+            f[i] = new_f;
+        }
+        // --- f is defined up to a constant
+        let f0 = f[0];
+        for fi in f.iter_mut() { *fi -= f0; }
+        if change < WHAM_TOLERANCE || iterations >= WHAM_MAX_ITERATIONS { break; }
+    }
+
+    let log_norm = log_sum_exp(log_p.iter().cloned());
+    let probability: Vec<f64> = log_p.iter().map(|lp| (lp - log_norm).exp()).collect();
+    let lp_max = log_p.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let free_energy = log_p.iter().map(|lp| if lp.is_finite() { temperature * (lp_max - lp) } else { f64::INFINITY })
+        .collect();
+    let window_free_energy = f.iter().map(|fi| fi * temperature).collect();
+    Ok(FreeEnergyProfile { bins, free_energy, probability, window_free_energy, iterations })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{gaussian, wham, Energy, HarmonicBias, IsingEnergy, MCProtocol, MetropolisCriterion, MoversSet,
+                MoversSetSampler, RandomStreams, SimulationDriver, SpinFlipMover, SpinLattice, UmbrellaSampling,
+                WhamWindow};
+
+    #[test]
+    fn bias_is_not_a_local_energy() {
+        let mut system = SpinLattice::new(&[4, 4], 2).unwrap();
+        system.set_spin(0, 1);
+        let ising = Arc::new(IsingEnergy::new(1.0, 0.0));
+        let bias = HarmonicBias::new(ising.clone(), Arc::new(|s: &SpinLattice| s.magnetization()), 1.0, 10.0);
+        let expected_bias = bias.bias(system.magnetization());
+        assert!(expected_bias > 0.0);
+        assert_eq!(bias.energy_by_pos(&system, 0), ising.energy_by_pos(&system, 0));
+        assert!((bias.energy(&system) - ising.energy(&system) - expected_bias).abs() < 1e-12);
+        let (before, after) = bias.delta_energy_by_pos(&system, &system, 0);
+        assert!((before - ising.energy_by_pos(&system, 0) - expected_bias).abs() < 1e-12);
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn windows_out_of_range_are_reported() {
+        let system = SpinLattice::new(&[3, 3], 2).unwrap();
+        let mut umbrella = UmbrellaSampling::new(&system, Arc::new(IsingEnergy::new(1.0, 0.0)),
+            Arc::new(|s: &SpinLattice| s.magnetization()), &[-1.0, 1.0], 1.0, RandomStreams::new(1),
+            |_, streams| {
+                let mut protocol = MCProtocol::with_streams(MetropolisCriterion::new(2.0), streams);
+                protocol.add_mover(Box::new(SpinFlipMover::new()));
+                let sampler: Box<dyn MoversSetSampler<MetropolisCriterion, SpinLattice>> = Box::new(protocol);
+                SimulationDriver::new(sampler, 1)
+            });
+        assert_eq!(umbrella.bias(1).unwrap().center, 1.0);
+        assert!(umbrella.driver(1).is_ok() && umbrella.system(1).is_ok());
+        assert!(umbrella.bias(2).is_err() && umbrella.driver(2).is_err());
+        assert_eq!(umbrella.system(2).err().unwrap().to_string(),
+                   "invalid parameter: window index 2 out of range of 2 windows");
+    }
+
+    #[test]
+    fn wham_recovers_harmonic_well() {
+        // --- the unbiased free energy is k0 / 2 x^2; a biased window samples a Gaussian distribution exactly
+        let (k0, k, temperature): (f64, f64, f64) = (1.0, 4.0, 1.0);
+        let mut rng = RandomStreams::new(5).next_stream();
+        let windows: Vec<WhamWindow> = [-1.0, 1.0].iter().map(|&center| {
+            let mean = k * center / (k0 + k);
+            let sigma = (temperature / (k0 + k)).sqrt();
+            let samples = (0..200000).map(|_| mean + sigma * gaussian(&mut rng)).collect();
+            WhamWindow { center, force_constant: k, samples }
+        }).collect();
+        let profile = wham(&windows, 40, temperature).unwrap();
+
+        let p_sum: f64 = profile.probability.iter().sum();
+        assert!((p_sum - 1.0).abs() < 1e-9);
+        // --- both windows are equally far from the minimum, so their free energies are equal
+        assert!(profile.window_free_energy[1].abs() < 0.01);
+        let i0 = profile.bins.iter().enumerate()
+            .min_by(|a, b| a.1.abs().partial_cmp(&b.1.abs()).unwrap()).unwrap().0;
+        for (x, f) in profile.bins.iter().zip(profile.free_energy.iter()) {
+            if x.abs() > 1.0 { continue; }
+            let expected = 0.5 * k0 * (x * x - profile.bins[i0] * profile.bins[i0]);
+            let shift = profile.free_energy[i0];
+            assert!((f - shift - expected).abs() < 0.05, "F({}) = {} instead of {}", x, f - shift, expected);
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use std::io;
+
+use crate::{AcceptanceCriterion, ChangedPositions, Energy, EnergyInconsistency, Mover, MoversSet, MoversSetSampler,
+            MoverState, RandomStream, RandomStreams, Sampler, SamplerCheckpoint, SamplerState, SimulationError, System,
+            UndoMCProtocolState, ValidationMode};
+use crate::checkpoint::{invalid_state, wrong_sampler};
+use crate::error::check_mover_index;
+use crate::montecarlo::{sync_temperature, MAX_RECORDED_INCONSISTENCIES};
+
+/// A [`System`] which values at a given position can be stored and restored.
+///
+/// This allows a move to be undone without keeping a second copy of the whole system.
+pub trait UndoSystem: System {
+    /// Values stored for a single position, e.g. coordinates of a single atom
+    type Backup: Clone + Send;
+    fn backup(&self, pos: usize) -> Self::Backup;
+    fn restore(&mut self, pos: usize, backup: &Self::Backup);
+}
+
+/// Old values of all the positions changed by an in-place move
+pub struct UndoLog<S: UndoSystem> {
+    entries: Vec<(usize, S::Backup)>,
+}
+
+impl<S: UndoSystem> UndoLog<S> {
+    pub fn new() -> UndoLog<S> { UndoLog { entries: vec![] } }
+
+    /// Stores the current value at a given position; must be called before that position is changed.
+    ///
+    /// When a position is recorded more than once, only the first (i.e. the oldest) value is kept.
+    pub fn record(&mut self, system: &S, pos: usize) {
+        if self.entries.iter().any(|(p, _)| *p == pos) { return; }
+        self.entries.push((pos, system.backup(pos)));
+    }
+
+    pub fn len(&self) -> usize { self.entries.len() }
+
+    pub fn is_empty(&self) -> bool { self.entries.is_empty() }
+
+    pub fn clear(&mut self) { self.entries.clear(); }
+
+    /// Positions recorded in this log
+    pub fn changed(&self) -> ChangedPositions {
+        if self.entries.len() == 1 { return ChangedPositions::single(self.entries[0].0); }
+        return ChangedPositions::List(self.entries.iter().map(|(pos, _)| *pos).collect());
+    }
+
+    /// Restores all the recorded values, bringing the system back to its state before the move, and clears this log
+    pub fn undo(&mut self, system: &mut S) {
+        for (pos, backup) in self.entries.iter().rev() { system.restore(*pos, backup); }
+        self.entries.clear();
+    }
+
+    /// Exchanges the values stored in this log with the current values of the system.
+    ///
+    /// After a move the first call brings the system back to its old state, the second one makes the move again.
+    pub fn swap(&mut self, system: &mut S) {
+        for (pos, backup) in self.entries.iter_mut() {
+            let current = system.backup(*pos);
+            system.restore(*pos, backup);
+            *backup = current;
+        }
+    }
+}
+
+impl<S: UndoSystem> Default for UndoLog<S> {
+    fn default() -> Self { UndoLog::new() }
+}
+
+/// Monte Carlo protocol that makes moves directly on the simulated system.
+///
+/// Unlike [`MCProtocol`](crate::MCProtocol), which applies every move on a copy of the system, this protocol
+/// keeps a single system: a mover records the old values of the positions it changes in an [`UndoLog`]
+/// (see [`Mover::perturb_in_place()`]), the energy change is evaluated by [`Energy::energy_of_positions()`]
+/// and a rejected move is rolled back. Movers are applied one after another, each of them `size()` times per sweep;
+/// every mover must support in-place moves.
+pub struct UndoMCProtocol<T: AcceptanceCriterion, S: UndoSystem> {
+    pub acceptance_criterion: T,
+    pub validation: ValidationMode,
+    /// largest allowed difference between the local and the total energy change of a validated move
+    pub energy_tolerance: f64,
+    inconsistencies: Vec<EnergyInconsistency>,
+    movers: Vec<Box<dyn Mover<S>>>,
+    undo: UndoLog<S>,
+    streams: RandomStreams,
+    criterion_rng: RandomStream,
+    mover_rngs: Vec<RandomStream>,
+}
+
+impl<T: AcceptanceCriterion, S: UndoSystem> UndoMCProtocol<T, S> {
+    /// Creates a protocol seeded from the entropy source; use [`with_streams()`](UndoMCProtocol::with_streams)
+    /// for a reproducible simulation
+    pub fn new(acc_crit: T) -> UndoMCProtocol<T, S> { UndoMCProtocol::with_streams(acc_crit, RandomStreams::from_entropy()) }
+
+    pub fn with_streams(acc_crit: T, mut streams: RandomStreams) -> UndoMCProtocol<T, S> {
+        let criterion_rng = streams.next_stream();
+        UndoMCProtocol { acceptance_criterion: acc_crit, validation: ValidationMode::default(), energy_tolerance: 0.01,
+            inconsistencies: vec![], movers: vec![], undo: UndoLog::new(), streams, criterion_rng, mover_rngs: vec![] }
+    }
+
+    /// Energy inconsistencies found so far; only the first 1000 of them are kept
+    pub fn inconsistencies(&self) -> &Vec<EnergyInconsistency> { &self.inconsistencies }
+
+    pub fn clear_inconsistencies(&mut self) { self.inconsistencies.clear(); }
+
+    /// Attempts a single in-place move with the given mover
+    fn make_move(&mut self, i_mover: usize, coords: &mut S, energy: &dyn Energy<S>) -> Result<(), SimulationError> {
+        let mover = &mut self.movers[i_mover];
+        // ---------- Make a move on the system, recording the old values
+        self.undo.clear();
+        if !mover.perturb_in_place(coords, &mut self.undo, &mut self.mover_rngs[i_mover]) {
+            return Err(SimulationError::InvalidParameter(format!("mover {} doesn't support in-place moves", i_mover)));
+        }
+        // ---------- Evaluate energy difference: after the move, then after going back to the old state
+        let changed = self.undo.changed();
+        let en_after = energy.energy_of_positions(coords, &changed);
+        self.undo.swap(coords);
+        let en_before = energy.energy_of_positions(coords, &changed);
+        // ---------- test the energy consistency only when it's unlikely the move will be rejected
+        let delta_en = en_after - en_before;
+        if self.validation != ValidationMode::Disabled && delta_en < 1000.0 {
+            let total_before = energy.energy(coords);
+            self.undo.swap(coords);
+            let total_after = energy.energy(coords);
+            self.undo.swap(coords);
+            if f64::abs(total_after - total_before - delta_en) > self.energy_tolerance {
+                let inconsistency = EnergyInconsistency { mover: i_mover, changed, local_delta: delta_en,
+                    total_before, total_after };
+                if self.validation == ValidationMode::Fail {
+                    return Err(SimulationError::InconsistentEnergy(inconsistency));
+                }
+                if self.inconsistencies.len() < MAX_RECORDED_INCONSISTENCIES { self.inconsistencies.push(inconsistency); }
+            }
+        }
+        // ---------- apply acceptance criterion; the system is in its old state now
+        if self.acceptance_criterion.check(en_before, en_after, &mut self.criterion_rng) {
+            self.undo.swap(coords);
+            mover.add_success();
+        } else {
+            mover.add_failure();
+        }
+        Ok(())
+    }
+}
+
+impl<T: AcceptanceCriterion, S: UndoSystem> Sampler<T, S> for UndoMCProtocol<T, S> {
+
+    fn make_sweeps(&mut self, n: usize, coords: &mut S, energy: &dyn Energy<S>) -> Result<(), SimulationError> {
+        for _ in 0..n {
+            for i_mover in 0..self.movers.len() {
+                for _ in 0..coords.size() {
+                    self.make_move(i_mover, coords, energy)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn temperature(&self) -> f64 { self.acceptance_criterion.temperature() }
+
+    /// Sets the temperature of the acceptance criterion and of every mover
+    fn set_temperature(&mut self, temperature: f64) {
+        self.acceptance_criterion.set_temperature(temperature);
+        for mover in self.movers.iter_mut() { sync_temperature(&self.acceptance_criterion, mover.as_mut()); }
+    }
+
+    fn energy_inconsistencies(&self) -> Vec<EnergyInconsistency> { self.inconsistencies.clone() }
+}
+
+impl<T: AcceptanceCriterion, S: UndoSystem> MoversSet<T, S> for UndoMCProtocol<T, S> {
+
+    /// Adds a mover, which must support in-place moves
+    fn add_mover(&mut self, mut perturb_fn: Box<dyn Mover<S>>) {
+        sync_temperature(&self.acceptance_criterion, perturb_fn.as_mut());
+        self.movers.push(perturb_fn);
+        self.mover_rngs.push(self.streams.next_stream());
+    }
+
+    fn get_mover(&mut self, which_one: usize) -> Result<&mut Box<dyn Mover<S>>, SimulationError> {
+        check_mover_index(which_one, self.movers.len())?;
+        Ok(&mut self.movers[which_one])
+    }
+
+    fn count_movers(&self) -> usize { self.movers.len() }
+}
+
+impl<T: AcceptanceCriterion, S: UndoSystem> MoversSetSampler<T, S> for UndoMCProtocol<T, S> {}
+
+/// Stores the temperature, movers and random streams of the movers and of the acceptance criterion
+impl<T: AcceptanceCriterion, S: UndoSystem> SamplerCheckpoint<S> for UndoMCProtocol<T, S> {
+    fn save_state(&self) -> SamplerState<S> {
+        let criterion = &self.acceptance_criterion;
+        return SamplerState::UndoMCProtocol(UndoMCProtocolState {
+            temperature: if criterion.has_temperature() { Some(criterion.temperature()) } else { None },
+            criterion: criterion.criterion_state(),
+            movers: self.movers.iter().map(|m| MoverState::new(m.as_ref())).collect(),
+            streams: self.streams.state().clone(),
+            criterion_rng: self.criterion_rng.clone(),
+            mover_rngs: self.mover_rngs.clone(),
+        });
+    }
+
+    fn restore_state(&mut self, state: SamplerState<S>) -> io::Result<()> {
+        let state = match state {
+            SamplerState::UndoMCProtocol(state) => state,
+            other => return Err(wrong_sampler("UndoMCProtocol", &other)),
+        };
+        if state.movers.len() != self.movers.len() || state.mover_rngs.len() != self.movers.len() {
+            return Err(invalid_state(format!("checkpoint holds {} movers while the sampler has {}",
+                                             state.movers.len(), self.movers.len())));
+        }
+        if let Some(temperature) = state.temperature { self.acceptance_criterion.set_temperature(temperature); }
+        if let Some(criterion) = state.criterion { self.acceptance_criterion.restore_criterion_state(criterion)?; }
+        for (mover, mover_state) in self.movers.iter_mut().zip(state.movers.iter()) {
+            mover_state.restore(mover.as_mut());
+            sync_temperature(&self.acceptance_criterion, mover.as_mut());
+        }
+        self.streams = RandomStreams::from_state(state.streams);
+        self.criterion_rng = state.criterion_rng;
+        self.mover_rngs = state.mover_rngs;
+        Ok(())
+    }
+}
@@ -0,0 +1,222 @@
+use std::io;
+use std::io::Write;
+use rand::Rng;
+
+use crate::{out_writer, AcceptanceCriterion, CriterionState, RandomStream, SimulationError, WangLandauState};
+use crate::checkpoint::invalid_state;
+
+/// Wang–Landau flat-histogram acceptance criterion.
+///
+/// The criterion builds a running estimate of the logarithm of the density of states, ln g(E), over energy bins
+/// spanning `[e_min, e_max)`. A move from energy E1 to E2 is accepted with probability `min(1, g(E1) / g(E2))`,
+/// after which ln g of the current energy is increased by the modification factor ln f. Whenever the
+/// histogram of visits is flat, ln f is halved and the histogram is reset; the estimate is converged
+/// when ln f drops below `ln_f_final`.
+///
+/// Since [`check()`](AcceptanceCriterion::check) receives only the energy change made by a move, the criterion
+/// tracks the total energy of the system itself; it must be initialised by [`set_energy()`](WangLandauCriterion::set_energy)
+/// before sampling. Moves that would leave the energy range are rejected. A system that starts outside the range
+/// accepts every move that doesn't take it further away from the range, until it enters the range.
+pub struct WangLandauCriterion {
+    /// a histogram is flat when every visited bin has at least `flatness` times the average number of visits
+    pub flatness: f64,
+    pub ln_f_final: f64,
+    /// number of visits between subsequent flatness tests
+    pub check_interval: usize,
+    e_min: f64,
+    bin_width: f64,
+    ln_g: Vec<f64>,
+    histogram: Vec<u64>,
+    ln_f: f64,
+    energy: f64,
+    n_visits: usize,
+}
+
+impl WangLandauCriterion {
+    /// Creates a criterion for `n_bins` energy bins; fails unless `e_min < e_max` and `n_bins > 0`
+    pub fn new(e_min: f64, e_max: f64, n_bins: usize) -> Result<WangLandauCriterion, SimulationError> {
+        if !e_min.is_finite() || !e_max.is_finite() || e_min >= e_max || n_bins == 0 {
+            return Err(SimulationError::InvalidParameter(
+                format!("can't divide energy range [{}, {}) into {} bins", e_min, e_max, n_bins)));
+        }
+        Ok(WangLandauCriterion { flatness: 0.8, ln_f_final: 1e-8, check_interval: 10000, e_min,
+            bin_width: (e_max - e_min) / n_bins as f64, ln_g: vec![0.0; n_bins], histogram: vec![0; n_bins],
+            ln_f: 1.0, energy: e_min, n_visits: 0 })
+    }
+
+    /// Sets the total energy of the sampled system
+    pub fn set_energy(&mut self, energy: f64) { self.energy = energy; }
+
+    /// Total energy of the sampled system
+    pub fn energy(&self) -> f64 { self.energy }
+
+    /// Current estimate of ln g(E) for every energy bin, up to an additive constant
+    pub fn ln_g(&self) -> &Vec<f64> { &self.ln_g }
+
+    pub fn histogram(&self) -> &Vec<u64> { &self.histogram }
+
+    /// Energy at the centre of the i-th bin
+    pub fn bin_energy(&self, i: usize) -> f64 { self.e_min + (i as f64 + 0.5) * self.bin_width }
+
+    /// Current modification factor ln f
+    pub fn modification_factor(&self) -> f64 { self.ln_f }
+
+    pub fn is_converged(&self) -> bool { self.ln_f < self.ln_f_final }
+
+    /// Returns true when each bin visited at least once has at least `flatness` times the average number of visits
+    pub fn is_flat(&self) -> bool {
+        let visited: Vec<u64> = self.visited_bins().map(|i| self.histogram[i]).collect();
+        if visited.is_empty() { return false; }
+        let average = visited.iter().sum::<u64>() as f64 / visited.len() as f64;
+        return visited.iter().all(|h| *h as f64 >= self.flatness * average);
+    }
+
+    /// Logarithm of the partition function at a given temperature, up to the same constant as ln g(E)
+    pub fn ln_partition_function(&self, temperature: f64) -> f64 {
+        let terms: Vec<f64> = self.visited_bins().map(|i| self.ln_g[i] - self.bin_energy(i) / temperature).collect();
+        let max = terms.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        return max + terms.iter().map(|t| (t - max).exp()).sum::<f64>().ln();
+    }
+
+    /// Canonical average energy at a given temperature
+    pub fn mean_energy(&self, temperature: f64) -> f64 {
+        let (e, _) = self.energy_moments(temperature);
+        return e;
+    }
+
+    /// Heat capacity at a given temperature, computed from the energy fluctuations
+    pub fn heat_capacity(&self, temperature: f64) -> f64 {
+        let (e, e2) = self.energy_moments(temperature);
+        return (e2 - e * e) / (temperature * temperature);
+    }
+
+    /// Writes a table of bin energies, ln g(E) and histogram counts
+    pub fn write_ln_g(&self, out_fname: &str) -> io::Result<()> {
+        let mut out = out_writer(out_fname)?;
+        for i in 0..self.ln_g.len() {
+            writeln!(out, "{:.4} {:.6} {}", self.bin_energy(i), self.ln_g[i], self.histogram[i])?;
+        }
+        out.flush()
+    }
+
+    fn e_max(&self) -> f64 { self.e_min + self.bin_width * self.ln_g.len() as f64 }
+
+    /// Distance of an energy to the energy range, zero inside the range
+    fn distance(&self, energy: f64) -> f64 { (self.e_min - energy).max(energy - self.e_max()).max(0.0) }
+
+    fn bin(&self, energy: f64) -> Option<usize> {
+        if energy < self.e_min { return None; }
+        let i = ((energy - self.e_min) / self.bin_width) as usize;
+        if i < self.ln_g.len() { Some(i) } else { None }
+    }
+
+    fn visited_bins(&self) -> impl Iterator<Item=usize> + '_ { (0..self.ln_g.len()).filter(|i| self.ln_g[*i] > 0.0) }
+
+    fn energy_moments(&self, temperature: f64) -> (f64, f64) {
+        let ln_z = self.ln_partition_function(temperature);
+        let (mut e, mut e2) = (0.0, 0.0);
+        for i in self.visited_bins() {
+            let en = self.bin_energy(i);
+            let p = (self.ln_g[i] - en / temperature - ln_z).exp();
+            e += p * en;
+            e2 += p * en * en;
+        }
+        return (e, e2);
+    }
+
+    /// Updates ln g(E) and the histogram for the current energy; reduces ln f when the histogram is flat
+    fn visit(&mut self) {
+        if let Some(i) = self.bin(self.energy) {
+            self.ln_g[i] += self.ln_f;
+            self.histogram[i] += 1;
+            self.n_visits += 1;
+            if self.n_visits.is_multiple_of(self.check_interval.max(1)) && self.is_flat() {
+                self.ln_f /= 2.0;
+                self.histogram.fill(0);
+            }
+        }
+    }
+}
+
+impl AcceptanceCriterion for WangLandauCriterion {
+    fn check(&mut self, energy_before: f64, energy_after: f64, rng: &mut RandomStream) -> bool {
+        let energy_new = self.energy + energy_after - energy_before;
+        let accepted = match (self.bin(self.energy), self.bin(energy_new)) {
+            (Some(_), None) => false,
+            (None, Some(_)) => true,        // --- let the system enter the energy range
+            (None, None) => self.distance(energy_new) <= self.distance(self.energy),
+            (Some(i), Some(j)) => self.ln_g[i] >= self.ln_g[j] || rng.gen_range(0.0..1.0) < (self.ln_g[i] - self.ln_g[j]).exp()
+        };
+        if accepted { self.energy = energy_new; }
+        self.visit();
+        return accepted;
+    }
+
+    /// Wang–Landau sampling doesn't depend on temperature; this method returns infinity
+    fn temperature(&self) -> f64 { f64::INFINITY }
+
+    /// Does nothing: Wang–Landau sampling doesn't depend on temperature
+    fn set_temperature(&mut self, _temperature: f64) {}
+
+    fn has_temperature(&self) -> bool { false }
+
+    fn criterion_state(&self) -> Option<CriterionState> {
+        Some(CriterionState::WangLandau(WangLandauState { ln_g: self.ln_g.clone(), histogram: self.histogram.clone(),
+            ln_f: self.ln_f, energy: self.energy, n_visits: self.n_visits }))
+    }
+
+    fn restore_criterion_state(&mut self, state: CriterionState) -> io::Result<()> {
+        let CriterionState::WangLandau(state) = state;
+        if state.ln_g.len() != self.ln_g.len() || state.histogram.len() != self.histogram.len() {
+            return Err(invalid_state(format!("checkpoint holds {} energy bins while the criterion has {}",
+                                             state.ln_g.len(), self.ln_g.len())));
+        }
+        self.ln_g = state.ln_g;
+        self.histogram = state.histogram;
+        self.ln_f = state.ln_f;
+        self.energy = state.energy;
+        self.n_visits = state.n_visits;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{AcceptanceCriterion, IsingEnergy, MCProtocol, MoversSet, RandomStreams, Sampler, SamplerCheckpoint,
+                SpinFlipMover, SpinLattice};
+    use super::WangLandauCriterion;
+
+    #[test]
+    fn system_outside_the_range_moves_towards_it() {
+        let mut rng = RandomStreams::new(3).next_stream();
+        let mut criterion = WangLandauCriterion::new(0.0, 10.0, 10).unwrap();
+        criterion.set_energy(20.0);
+        assert!(!criterion.check(0.0, 1.0, &mut rng));
+        assert!(criterion.check(0.0, -5.0, &mut rng));
+        assert_eq!(criterion.energy(), 15.0);
+        assert!(criterion.check(0.0, -10.0, &mut rng));
+        assert_eq!(criterion.energy(), 5.0);
+        assert!(!criterion.check(0.0, 6.0, &mut rng));
+    }
+
+    #[test]
+    fn checkpoint_keeps_the_density_of_states() {
+        let energy = IsingEnergy::new(1.0, 0.0);
+        let mut system = SpinLattice::new(&[4, 4], 2).unwrap();
+        let build = || {
+            let mut criterion = WangLandauCriterion::new(-32.0, 33.0, 17).unwrap();
+            criterion.set_energy(-32.0);
+            let mut sampler = MCProtocol::with_streams(criterion, RandomStreams::new(11));
+            sampler.add_mover(Box::new(SpinFlipMover::new()));
+            sampler
+        };
+        let mut sampler = build();
+        sampler.make_sweeps(200, &mut system, &energy).unwrap();
+        let mut restored = build();
+        restored.restore_state(sampler.save_state()).unwrap();
+        assert_eq!(restored.acceptance_criterion.ln_g(), sampler.acceptance_criterion.ln_g());
+        assert_eq!(restored.acceptance_criterion.histogram(), sampler.acceptance_criterion.histogram());
+        assert_eq!(restored.acceptance_criterion.energy(), sampler.acceptance_criterion.energy());
+        assert!(restored.acceptance_criterion.ln_g().iter().any(|g| *g > 0.0));
+    }
+}